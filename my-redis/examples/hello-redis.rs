@@ -1,9 +1,9 @@
-use mini_redis::{client, Result};
+use my_redis::{clients::Client, Result};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Open a connection to the mini-redis address.
-    let mut client = client::connect("127.0.0.1:6379").await?;
+    let mut client = Client::connect("127.0.0.1:6379").await?;
 
     // Set the key "hello" with value "world"
     client.set("hello", "world".into()).await?;
@@ -14,4 +14,4 @@ async fn main() -> Result<()> {
     println!("got value from the server; result={:?}", result);
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,65 @@
+//! Measure the throughput of pipelined commands.
+//!
+//! Sends `SET` commands to a running server in batches of 1, 16 and 128,
+//! waiting for the replies of a batch before sending the next.
+//!
+//!     cargo run --release --example pipeline-bench [addr] [commands]
+//!
+//! `addr` defaults to `127.0.0.1:6379`, `commands` to 100000 per depth.
+
+use bytes::Bytes;
+use my_redis::{Connection, Frame, Result};
+use std::time::Instant;
+use tokio::net::TcpStream;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:6379".to_string());
+    let total: usize = match args.next() {
+        Some(total) => total.parse()?,
+        None => 100_000,
+    };
+
+    let socket = TcpStream::connect(&addr).await?;
+    socket.set_nodelay(true)?;
+    let mut connection = Connection::new(socket);
+    for depth in [1, 16, 128] {
+        let started = Instant::now();
+        let mut sent = 0;
+        while sent < total {
+            let batch = depth.min(total - sent);
+            for i in 0..batch {
+                connection.queue_frame(&set_frame(sent + i));
+            }
+            connection.flush().await?;
+            for _ in 0..batch {
+                match connection.read_frame().await? {
+                    Some(Frame::Error(e)) => return Err(e.into()),
+                    Some(_) => {}
+                    None => return Err("connection closed by the server".into()),
+                }
+            }
+            sent += batch;
+        }
+        let elapsed = started.elapsed();
+        println!(
+            "depth {:>3}: {} commands in {:.2?}, {:.0} commands/s",
+            depth,
+            total,
+            elapsed,
+            total as f64 / elapsed.as_secs_f64()
+        );
+    }
+
+    Ok(())
+}
+
+/// `SET bench:<n> <n>`
+fn set_frame(n: usize) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from("SET")),
+        Frame::Bulk(Bytes::from(format!("bench:{}", n))),
+        Frame::Bulk(Bytes::from(n.to_string())),
+    ])
+}
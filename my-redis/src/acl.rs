@@ -0,0 +1,756 @@
+//! Access control lists: the users clients authenticate as, and what each of
+//! them may run.
+//!
+//! A user is described by rules, with the syntax of `ACL SETUSER`:
+//!
+//! * `on`, `off`: enable or disable the user
+//! * `>password`, `<password`: add or remove a password; `#hash` and `!hash`
+//!   do the same given the SHA-256 of the password
+//! * `nopass` accepts any password, `resetpass` forgets all of them
+//! * `+command`, `-command`, `+command|subcommand`, `+@category`,
+//!   `-@category`, `allcommands` (`+@all`) and `nocommands` (`-@all`)
+//! * `~pattern`, `allkeys` (`~*`) and `resetkeys` for the keys
+//! * `&pattern`, `allchannels` (`&*`) and `resetchannels` for pub/sub
+//!   channels
+//! * `reset` brings the user back to its initial state: disabled, without
+//!   passwords nor permissions
+//!
+//! Command rules are kept in order: the last rule matching a command decides
+//! whether the user may run it.
+
+use crate::cmd::{CommandSpec, COMMANDS};
+use crate::config::Config;
+use crate::frame::Frame;
+use crate::glob;
+
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, RwLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Command categories, as listed by `ACL CAT`.
+pub const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "list",
+    "string",
+    "pubsub",
+    "admin",
+    "fast",
+    "slow",
+    "blocking",
+    "dangerous",
+    "connection",
+];
+
+/// Entries kept in the ACL log.
+const LOG_LEN: usize = 128;
+
+/// The commands in `category`, `None` if there is no such category.
+/// Subcommands are listed on their own, as `container|subcommand`, when
+/// only they belong to it.
+pub fn category_commands(category: &str) -> Option<Vec<&'static str>> {
+    let category = category.to_lowercase();
+    if !CATEGORIES.contains(&&category[..]) {
+        return None;
+    }
+    let mut commands = Vec::new();
+    for spec in COMMANDS {
+        if spec.categories.contains(&&category[..]) {
+            commands.push(spec.name);
+            continue;
+        }
+        commands.extend(
+            spec.subcommands
+                .iter()
+                .filter(|sub| sub.categories.contains(&&category[..]))
+                .map(|sub| sub.name),
+        );
+    }
+    Some(commands)
+}
+
+/// Whether `command`, called with `subcommand`, is in `category`. A
+/// subcommand is in the categories of its container and in its own.
+fn in_category(command: &str, subcommand: Option<&str>, category: &str) -> bool {
+    let Some(spec) = CommandSpec::lookup(command) else {
+        return false;
+    };
+    let sub = subcommand.and_then(|sub| spec.subcommand(sub.as_bytes()));
+    spec.categories.contains(&category) || sub.is_some_and(|sub| sub.categories.contains(&category))
+}
+
+/// A user, see the module documentation for the meaning of its fields.
+#[derive(Debug, Clone)]
+pub struct User {
+    name: String,
+    enabled: bool,
+    nopass: bool,
+    /// SHA-256 of the passwords, hex encoded
+    passwords: BTreeSet<String>,
+    /// `+` and `-` rules, oldest first. Empty denies every command.
+    commands: Vec<String>,
+    keys: Vec<String>,
+    channels: Vec<String>,
+}
+
+impl User {
+    fn new(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            commands: Vec::new(),
+            keys: Vec::new(),
+            channels: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Apply a single rule.
+    fn apply(&mut self, rule: &str) -> Result<(), String> {
+        match &rule.to_lowercase()[..] {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.keys = vec!["*".to_string()],
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => self.channels = vec!["*".to_string()],
+            "resetchannels" => self.channels.clear(),
+            "allcommands" => self.commands = vec!["+@all".to_string()],
+            "nocommands" => self.commands.clear(),
+            "reset" => *self = User::new(&self.name),
+            _ => {
+                let (prefix, rest) = rule.split_at(rule.chars().next().map_or(0, char::len_utf8));
+                match prefix {
+                    ">" => {
+                        self.passwords.insert(hash_password(rest));
+                        self.nopass = false;
+                    }
+                    "<" => {
+                        if !self.passwords.remove(&hash_password(rest)) {
+                            return Err("no such password".to_string());
+                        }
+                    }
+                    "#" => {
+                        if rest.len() != 64 || !rest.bytes().all(|b| b.is_ascii_hexdigit()) {
+                            return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters".to_string());
+                        }
+                        self.passwords.insert(rest.to_lowercase());
+                        self.nopass = false;
+                    }
+                    "!" => {
+                        if !self.passwords.remove(&rest.to_lowercase()) {
+                            return Err("no such password".to_string());
+                        }
+                    }
+                    "~" => self.keys.push(rest.to_string()),
+                    "&" => self.channels.push(rest.to_string()),
+                    "+" | "-" => {
+                        let target = rest.to_lowercase();
+                        let known = match target.strip_prefix('@') {
+                            Some(category) => category == "all" || CATEGORIES.contains(&category),
+                            // `command|subcommand` is only known for container
+                            // commands.
+                            None => CommandSpec::lookup_full(&target).is_some(),
+                        };
+                        if !known {
+                            return Err("Unknown command or category name in ACL".to_string());
+                        }
+                        // `@all` overrides every rule given before it.
+                        if target == "@all" {
+                            self.commands.clear();
+                            if prefix == "-" {
+                                return Ok(());
+                            }
+                        }
+                        self.commands.push(format!("{}{}", prefix, target));
+                    }
+                    _ => return Err("Syntax error".to_string()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_password(&self, password: &str) -> bool {
+        self.enabled && (self.nopass || self.passwords.contains(&hash_password(password)))
+    }
+
+    /// Whether the user may run `command`, called with `subcommand` for
+    /// container commands such as `CONFIG`.
+    pub fn can_run(&self, command: &str, subcommand: Option<&str>) -> bool {
+        let mut allowed = false;
+        for rule in &self.commands {
+            let (prefix, target) = rule.split_at(1);
+            let matched = match target.strip_prefix('@') {
+                Some(category) => category == "all" || in_category(command, subcommand, category),
+                None => match target.split_once('|') {
+                    Some((name, sub)) => name == command && subcommand == Some(sub),
+                    None => target == command,
+                },
+            };
+            if matched {
+                allowed = prefix == "+";
+            }
+        }
+        allowed
+    }
+
+    pub fn can_access_key(&self, key: &str) -> bool {
+        self.keys
+            .iter()
+            .any(|pattern| glob::matches(pattern.as_bytes(), key.as_bytes(), false))
+    }
+
+    pub fn can_access_channel(&self, channel: &str) -> bool {
+        self.channels
+            .iter()
+            .any(|pattern| glob::matches(pattern.as_bytes(), channel.as_bytes(), false))
+    }
+
+    /// The command rules, as shown by `ACL GETUSER`
+    pub fn command_rules(&self) -> String {
+        if self.commands.is_empty() {
+            return "-@all".to_string();
+        }
+        self.commands.join(" ")
+    }
+
+    /// The key patterns, as shown by `ACL GETUSER`
+    pub fn key_rules(&self) -> String {
+        self.keys.iter().map(|key| format!("~{}", key)).collect::<Vec<_>>().join(" ")
+    }
+
+    /// The channel patterns, as shown by `ACL GETUSER`
+    pub fn channel_rules(&self) -> String {
+        self.channels
+            .iter()
+            .map(|channel| format!("&{}", channel))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The user as returned by `ACL GETUSER`.
+    pub fn to_frame(&self) -> Frame {
+        let mut flags = vec![Frame::Bulk(Bytes::from(if self.enabled { "on" } else { "off" }))];
+        if self.nopass {
+            flags.push(Frame::Bulk(Bytes::from("nopass")));
+        }
+        let passwords = self
+            .passwords
+            .iter()
+            .map(|hash| Frame::Bulk(Bytes::from(hash.clone())))
+            .collect();
+
+        let field = |name: &'static str, value: Frame| (Frame::Bulk(Bytes::from(name)), value);
+        Frame::Map(vec![
+            field("flags", Frame::Set(flags)),
+            field("passwords", Frame::Array(passwords)),
+            field("commands", Frame::Bulk(Bytes::from(self.command_rules()))),
+            field("keys", Frame::Bulk(Bytes::from(self.key_rules()))),
+            field("channels", Frame::Bulk(Bytes::from(self.channel_rules()))),
+        ])
+    }
+
+    /// The user as a line of `ACL LIST` and of the ACL file.
+    pub fn describe(&self) -> String {
+        let mut line = format!("user {} {}", self.name, if self.enabled { "on" } else { "off" });
+        if self.nopass {
+            line.push_str(" nopass");
+        }
+        for hash in &self.passwords {
+            let _ = write!(line, " #{}", hash);
+        }
+        for rules in [self.key_rules(), self.channel_rules()] {
+            if !rules.is_empty() {
+                line.push(' ');
+                line.push_str(&rules);
+            }
+        }
+        if self.channels.is_empty() {
+            line.push_str(" resetchannels");
+        }
+        line.push(' ');
+        line.push_str(&self.command_rules());
+        line
+    }
+}
+
+fn hash_password(password: &str) -> String {
+    let mut hex = String::with_capacity(64);
+    for byte in Sha256::digest(password.as_bytes()) {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+/// Why a command was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Denied {
+    Command,
+    Key(String),
+    Channel(String),
+}
+
+impl Denied {
+    /// The error returned to the client that ran `command` as `username`.
+    pub fn message(&self, username: &str, command: &str) -> String {
+        match self {
+            Denied::Command => format!(
+                "NOPERM User {} has no permissions to run the '{}' command",
+                username, command
+            ),
+            Denied::Key(_) => "NOPERM No permissions to access a key".to_string(),
+            Denied::Channel(_) => "NOPERM No permissions to access a channel".to_string(),
+        }
+    }
+}
+
+/// A refused command or authentication, as reported by `ACL LOG`. Identical
+/// events are grouped into a single entry.
+#[derive(Debug, Clone)]
+pub struct AclLogEntry {
+    pub entry_id: u64,
+    pub count: u64,
+    /// One of `command`, `key`, `channel` or `auth`
+    pub reason: String,
+    /// The command, key or channel refused
+    pub object: String,
+    pub username: String,
+    /// The client, formatted as by `CLIENT LIST`
+    pub client_info: String,
+    created: Instant,
+    /// Unix times in milliseconds
+    pub timestamp_created: u64,
+    pub timestamp_last_updated: u64,
+}
+
+impl AclLogEntry {
+    /// The entry as returned by `ACL LOG`.
+    pub fn to_frame(&self) -> Frame {
+        let field = |name: &'static str, value: Frame| (Frame::Bulk(Bytes::from(name)), value);
+        let bulk = |value: &str| Frame::Bulk(Bytes::from(value.to_string()));
+        Frame::Map(vec![
+            field("count", Frame::Integer(self.count as i64)),
+            field("reason", bulk(&self.reason)),
+            field("context", bulk("toplevel")),
+            field("object", bulk(&self.object)),
+            field("username", bulk(&self.username)),
+            field("age-seconds", Frame::Double((self.created.elapsed().as_secs_f64() * 1000.0).round() / 1000.0)),
+            field("client-info", bulk(&self.client_info)),
+            field("entry-id", Frame::Integer(self.entry_id as i64)),
+            field("timestamp-created", Frame::Integer(self.timestamp_created as i64)),
+            field("timestamp-last-updated", Frame::Integer(self.timestamp_last_updated as i64)),
+        ])
+    }
+}
+
+#[derive(Debug)]
+pub struct Acl {
+    users: RwLock<BTreeMap<String, User>>,
+    /// Newest entry first
+    log: Mutex<VecDeque<AclLogEntry>>,
+}
+
+impl Acl {
+    /// Create the `default` user, allowed to run everything, with
+    /// `requirepass` as its password if set.
+    pub fn new(config: &Config) -> Acl {
+        let mut default = User::new("default");
+        for rule in ["on", "nopass", "allkeys", "allchannels", "allcommands"] {
+            default.apply(rule).unwrap();
+        }
+        let acl = Acl {
+            users: RwLock::new(BTreeMap::from([("default".to_string(), default)])),
+            log: Mutex::new(VecDeque::new()),
+        };
+        acl.set_requirepass(&config.requirepass);
+        acl
+    }
+
+    /// Make `password` the only password of the `default` user. An empty one
+    /// lets anybody authenticate as `default`.
+    pub fn set_requirepass(&self, password: &str) {
+        let mut users = self.users.write().unwrap();
+        let default = users.entry("default".to_string()).or_insert_with(|| User::new("default"));
+        if password.is_empty() {
+            default.apply("nopass").unwrap();
+        } else {
+            default.apply("resetpass").unwrap();
+            default.apply(&format!(">{}", password)).unwrap();
+        }
+    }
+
+    /// Replace the users with the ones of an ACL file, holding one
+    /// `user <name> [rule ...]` line per user as written by `ACL LIST`.
+    ///
+    /// The `default` user is kept unchanged if the file doesn't define it.
+    pub fn load_file(&self, path: &Path) -> crate::Result<()> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("can't open ACL file {}: {}", path.display(), e))?;
+
+        let mut loaded = BTreeMap::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let name = match (words.next(), words.next()) {
+                (Some("user"), Some(name)) => name,
+                _ => {
+                    return Err(format!("ACL file {}, line {}: should start with user <name>", path.display(), number + 1).into())
+                }
+            };
+            let mut user = User::new(name);
+            for rule in words {
+                user.apply(rule).map_err(|e| {
+                    format!("ACL file {}, line {}: Error in rule '{}': {}", path.display(), number + 1, rule, e)
+                })?;
+            }
+            loaded.insert(name.to_string(), user);
+        }
+
+        let mut users = self.users.write().unwrap();
+        if !loaded.contains_key("default") {
+            loaded.insert("default".to_string(), users["default"].clone());
+        }
+        *users = loaded;
+        Ok(())
+    }
+
+    /// Returns `true` if `password` is valid for the enabled user `username`.
+    pub fn authenticate(&self, username: &str, password: &str) -> bool {
+        self.users
+            .read()
+            .unwrap()
+            .get(username)
+            .is_some_and(|user| user.check_password(password))
+    }
+
+    /// Whether the `default` user accepts any password, in which case new
+    /// clients are authenticated as `default` from the start.
+    pub fn default_is_open(&self) -> bool {
+        self.users
+            .read()
+            .unwrap()
+            .get("default")
+            .is_some_and(|user| user.enabled && user.nopass)
+    }
+
+    /// Check that `username` may run `command` with the arguments `args`,
+    /// touching `keys` and `channels`.
+    pub fn check(&self, username: &str, command: &str, args: &[Bytes], keys: &[&str], channels: &[&str]) -> Result<(), Denied> {
+        let users = self.users.read().unwrap();
+        let user = users.get(username).ok_or(Denied::Command)?;
+
+        let subcommand = match args.get(1) {
+            // Only container commands such as `CONFIG` have subcommands.
+            Some(arg) if CommandSpec::lookup(command).is_some_and(|spec| !spec.subcommands.is_empty()) => {
+                Some(String::from_utf8_lossy(arg).to_lowercase())
+            }
+            _ => None,
+        };
+        if !user.can_run(command, subcommand.as_deref()) {
+            return Err(Denied::Command);
+        }
+        if let Some(key) = keys.iter().find(|key| !user.can_access_key(key)) {
+            return Err(Denied::Key(key.to_string()));
+        }
+        if let Some(channel) = channels.iter().find(|channel| !user.can_access_channel(channel)) {
+            return Err(Denied::Channel(channel.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Create or update the user `name`. Either every rule applies, or the
+    /// user is left as it was.
+    pub fn set_user(&self, name: &str, rules: &[String]) -> Result<(), String> {
+        let mut users = self.users.write().unwrap();
+        let mut user = users.get(name).cloned().unwrap_or_else(|| User::new(name));
+        for rule in rules {
+            user.apply(rule)
+                .map_err(|e| format!("ERR Error in ACL SETUSER modifier '{}': {}", rule, e))?;
+        }
+        users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    pub fn get_user(&self, name: &str) -> Option<User> {
+        self.users.read().unwrap().get(name).cloned()
+    }
+
+    /// Returns `true` if the user existed. The `default` user can't be
+    /// deleted.
+    pub fn del_user(&self, name: &str) -> bool {
+        name != "default" && self.users.write().unwrap().remove(name).is_some()
+    }
+
+    /// Every user, as a line of `ACL LIST`
+    pub fn list(&self) -> Vec<String> {
+        self.users.read().unwrap().values().map(User::describe).collect()
+    }
+
+    pub fn usernames(&self) -> Vec<String> {
+        self.users.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Record a refused command or authentication in the log.
+    pub fn log_denied(&self, reason: &str, object: &str, username: &str, client_info: String) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis() as u64)
+            .unwrap_or(0);
+
+        let mut log = self.log.lock().unwrap();
+        if let Some(entry) = log
+            .iter_mut()
+            .find(|entry| entry.reason == reason && entry.object == object && entry.username == username)
+        {
+            entry.count += 1;
+            entry.client_info = client_info;
+            entry.timestamp_last_updated = now;
+            return;
+        }
+
+        let entry_id = log.front().map_or(0, |entry| entry.entry_id + 1);
+        log.push_front(AclLogEntry {
+            entry_id,
+            count: 1,
+            reason: reason.to_string(),
+            object: object.to_string(),
+            username: username.to_string(),
+            client_info,
+            created: Instant::now(),
+            timestamp_created: now,
+            timestamp_last_updated: now,
+        });
+        log.truncate(LOG_LEN);
+    }
+
+    /// The `count` most recent log entries, newest first.
+    pub fn log(&self, count: usize) -> Vec<AclLogEntry> {
+        self.log.lock().unwrap().iter().take(count).cloned().collect()
+    }
+
+    pub fn reset_log(&self) {
+        self.log.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(rules: &[&str]) -> User {
+        let mut user = User::new("test");
+        for rule in rules {
+            user.apply(rule).unwrap();
+        }
+        user
+    }
+
+    fn args(args: &[&str]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::from(arg.to_string())).collect()
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let u = user(&["+get", "-get"]);
+        assert!(!u.can_run("get", None));
+
+        let u = user(&["-get", "+get"]);
+        assert!(u.can_run("get", None));
+
+        let u = user(&["+@read", "-get"]);
+        assert!(!u.can_run("get", None));
+        assert!(u.can_run("exists", None));
+
+        let u = user(&["-@read", "+get"]);
+        assert!(u.can_run("get", None));
+    }
+
+    #[test]
+    fn no_rules_deny_everything() {
+        let u = user(&[]);
+        assert!(!u.can_run("get", None));
+        assert!(!u.can_run("ping", None));
+    }
+
+    #[test]
+    fn all_but_admin() {
+        let u = user(&["+@all", "-@admin"]);
+        assert!(u.can_run("get", None));
+        assert!(u.can_run("set", None));
+        assert!(!u.can_run("shutdown", None));
+        assert!(!u.can_run("config", Some("set")));
+        assert!(u.can_run("client", Some("id")));
+        assert!(!u.can_run("client", Some("kill")));
+        assert!(!u.can_run("client", Some("list")));
+        assert!(!u.can_run("client", Some("pause")));
+        assert!(!u.can_run("client", Some("unblock")));
+    }
+
+    #[test]
+    fn minus_all_resets_rules() {
+        let u = user(&["+get", "-@all", "+set"]);
+        assert!(!u.can_run("get", None));
+        assert!(u.can_run("set", None));
+        assert_eq!(u.command_rules(), "+set");
+    }
+
+    #[test]
+    fn subcommand_rules() {
+        let u = user(&["+config|get"]);
+        assert!(u.can_run("config", Some("get")));
+        assert!(!u.can_run("config", Some("set")));
+        assert!(!u.can_run("config", None));
+
+        let u = user(&["+config", "-config|set"]);
+        assert!(u.can_run("config", Some("get")));
+        assert!(!u.can_run("config", Some("set")));
+
+        let mut u = User::new("test");
+        assert!(u.apply("+get|foo").is_err());
+        assert!(u.apply("+config|nope").is_err());
+        assert!(u.apply("+nope").is_err());
+        assert!(u.apply("+@nope").is_err());
+    }
+
+    #[test]
+    fn subcommand_from_arguments() {
+        let acl = Acl::new(&Config::default());
+        acl.set_user("alice", &["on".to_string(), "nopass".to_string(), "+@all".to_string(), "-@dangerous".to_string()])
+            .unwrap();
+
+        assert_eq!(acl.check("alice", "client", &args(&["client", "id"]), &[], &[]), Ok(()));
+        assert_eq!(acl.check("alice", "client", &args(&["CLIENT", "KILL", "ID", "1"]), &[], &[]), Err(Denied::Command));
+    }
+
+    #[test]
+    fn key_patterns() {
+        let u = user(&["~user:*", "~session:?"]);
+        assert!(u.can_access_key("user:1000"));
+        assert!(u.can_access_key("session:a"));
+        assert!(!u.can_access_key("session:ab"));
+        assert!(!u.can_access_key("other"));
+
+        let u = user(&["~user:*", "resetkeys"]);
+        assert!(!u.can_access_key("user:1000"));
+
+        let u = user(&["allkeys"]);
+        assert!(u.can_access_key("anything"));
+    }
+
+    #[test]
+    fn channel_patterns() {
+        let u = user(&["&news.*"]);
+        assert!(u.can_access_channel("news.sport"));
+        assert!(!u.can_access_channel("weather"));
+
+        let u = user(&["allchannels", "resetchannels"]);
+        assert!(!u.can_access_channel("news"));
+    }
+
+    #[test]
+    fn check_reports_denied_key_and_channel() {
+        let acl = Acl::new(&Config::default());
+        let rules: Vec<String> = ["on", "nopass", "+@all", "~user:*", "&news"].iter().map(|r| r.to_string()).collect();
+        acl.set_user("bob", &rules).unwrap();
+
+        assert_eq!(acl.check("bob", "get", &args(&["get", "user:1"]), &["user:1"], &[]), Ok(()));
+        assert_eq!(
+            acl.check("bob", "get", &args(&["get", "other"]), &["other"], &[]),
+            Err(Denied::Key("other".to_string()))
+        );
+        assert_eq!(
+            acl.check("bob", "publish", &args(&["publish", "sport", "x"]), &[], &["sport"]),
+            Err(Denied::Channel("sport".to_string()))
+        );
+        assert_eq!(acl.check("nobody", "get", &args(&["get", "k"]), &[], &[]), Err(Denied::Command));
+    }
+
+    #[test]
+    fn passwords() {
+        let u = user(&["on", ">secret"]);
+        assert!(u.check_password("secret"));
+        assert!(!u.check_password("other"));
+
+        let u = user(&["on", ">secret", "nopass"]);
+        assert!(u.check_password("anything"));
+        assert!(u.passwords.is_empty());
+
+        let u = user(&["on", "nopass", ">secret"]);
+        assert!(!u.nopass);
+        assert!(!u.check_password("anything"));
+
+        let u = user(&["on", ">secret", "resetpass"]);
+        assert!(!u.check_password("secret"));
+        assert!(!u.check_password(""));
+
+        let u = user(&["on", "nopass", "resetpass"]);
+        assert!(!u.check_password("anything"));
+
+        let u = user(&["off", "nopass"]);
+        assert!(!u.check_password("anything"));
+
+        let mut u = user(&["on", ">secret"]);
+        assert!(u.apply("<other").is_err());
+        u.apply("<secret").unwrap();
+        assert!(!u.check_password("secret"));
+    }
+
+    #[test]
+    fn password_hashes() {
+        let hash = hash_password("secret");
+        let u = user(&["on", &format!("#{}", hash)]);
+        assert!(u.check_password("secret"));
+
+        let mut u = User::new("test");
+        assert!(u.apply("#abc").is_err());
+    }
+
+    #[test]
+    fn reset() {
+        let u = user(&["on", "nopass", "+@all", "~*", "&*", "reset"]);
+        assert!(!u.check_password(""));
+        assert!(!u.can_run("get", None));
+        assert!(!u.can_access_key("k"));
+        assert!(!u.can_access_channel("c"));
+    }
+
+    #[test]
+    fn failed_setuser_leaves_user_unchanged() {
+        let acl = Acl::new(&Config::default());
+        acl.set_user("carol", &["on".to_string(), "+get".to_string()]).unwrap();
+        assert!(acl.set_user("carol", &["-get".to_string(), "+nope".to_string()]).is_err());
+        assert!(acl.get_user("carol").unwrap().can_run("get", None));
+    }
+
+    #[test]
+    fn category_listing() {
+        let admin = category_commands("ADMIN").unwrap();
+        assert!(admin.contains(&"shutdown"));
+        assert!(admin.contains(&"client|kill"));
+        assert!(!admin.contains(&"client"));
+        assert!(!admin.contains(&"get"));
+        assert!(category_commands("nope").is_none());
+    }
+}
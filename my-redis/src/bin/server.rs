@@ -4,7 +4,13 @@
 //! performs command line parsing and passes the arguments on to
 //! `m()ini_redis::server`.
 //!
-//! The `clap` crate is used for parsing arguments.
+//! The `clap` crate is used for parsing arguments. Settings are read from an
+//! optional redis.conf-style configuration file first; flags given on the
+//! command line override the file:
+//!
+//! ```text
+//! my-redis-server ./redis.conf --port 7001 --loglevel debug
+//! ```
 
 
 
@@ -14,12 +20,16 @@ use std::collections::LinkedList;
 use std::future::Future;
 use std::io::ErrorKind;
 use std::sync::Arc;use tokio::time::Timeout;
-use my_redis::{server, DEFAULT_PORT, Connection, Frame};
+use my_redis::{Connection, Frame, Stream};
 use clap::Parser;
 use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::UnixListener;
 use tokio::sync::mpsc;use my_redis::db::{AllDbs, DataTypes};
 use my_redis::request::Request;
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::{broadcast, oneshot};
+use my_redis::Shutdown;
 use my_redis::db::SpecialSender;
 use log::debug;
 use log::Level::Debug;
@@ -32,77 +42,1479 @@ use my_redis::db::KeyAndValue;
 
 use my_redis::Command::*;
 use my_redis::db::DataTypes::BytesInDb;
-use my_redis::db::NUM_DBS;
 use my_redis::db::SenderType::{fromBlpop, fromBrpop};
+use my_redis::cluster::{self, Cluster, ClusterState};
+use my_redis::cmd::{ClusterSubcommand, SetslotAction};
+use my_redis::acl::{self, Acl, Denied};
+use my_redis::cmd::AclSubcommand;
+use my_redis::cmd::{CommandSpec, CommandsSubcommand, ListFilter, COMMANDS};
+use my_redis::dump;
+use my_redis::snapshot;
+use my_redis::Config;
+use my_redis::config::{ClientClass, SharedConfig};
+use my_redis::cmd::ConfigSubcommand;
+use std::sync::{OnceLock, RwLock};
+use tracing_subscriber::{prelude::*, reload, EnvFilter, Registry};
+use tracing::Instrument;
+#[cfg(feature = "otel")]
+// To be able to set the XrayPropagator
+use opentelemetry::global;
+#[cfg(feature = "otel")]
+use opentelemetry::sdk::{trace as sdktrace, trace::XrayIdGenerator, Resource};
+#[cfg(feature = "otel")]
+use opentelemetry::KeyValue;
+#[cfg(feature = "otel")]
+use opentelemetry_aws::XrayPropagator;
+#[cfg(feature = "otel")]
+use opentelemetry_otlp::WithExportConfig;
+use std::path::PathBuf;
+use std::time::Instant;
+use my_redis::info::{self, InfoContext};
+use my_redis::stats::Stats;
+use my_redis::slowlog::{SlowLog, SlowLogEntry};
+use my_redis::cmd::{LatencySubcommand, SlowlogSubcommand};
+use my_redis::monitor::Monitors;
+use my_redis::registry::{ClientHandle, ClientRegistry, PauseMode, Unblock};
+use my_redis::cmd::ClientSubcommand;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(name = "my-redis-server", version, author, about = "A Redis server")]
+struct Cli {
+    /// Path of a redis.conf-style configuration file
+    config_file: Option<PathBuf>,
+
+    /// Address to listen on
+    #[arg(long)]
+    bind: Option<String>,
+
+    /// Port to listen on
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Number of databases
+    #[arg(long)]
+    databases: Option<usize>,
+
+    /// One of debug, verbose, notice or warning
+    #[arg(long)]
+    loglevel: Option<String>,
+
+    /// Directory the snapshot file is read from and written to
+    #[arg(long)]
+    dir: Option<PathBuf>,
+
+    /// Name of the snapshot file
+    #[arg(long)]
+    dbfilename: Option<String>,
+
+    /// Seconds between automatic snapshots, 0 to disable
+    #[arg(long)]
+    save: Option<u64>,
+
+    /// Maximum number of connected clients
+    #[arg(long)]
+    maxclients: Option<usize>,
+
+    /// Seconds after which idle clients are disconnected, 0 to disable
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    /// Seconds between TCP keepalive probes, 0 to disable
+    #[arg(long)]
+    tcp_keepalive: Option<u64>,
+
+    /// Password clients must authenticate with as the default user
+    #[arg(long)]
+    requirepass: Option<String>,
+
+    /// File the ACL users are loaded from
+    #[arg(long)]
+    aclfile: Option<PathBuf>,
+
+    /// Path of a Unix socket to listen on
+    #[arg(long)]
+    unixsocket: Option<PathBuf>,
+
+    /// Permissions of the Unix socket, in octal, e.g. 770
+    #[arg(long)]
+    unixsocketperm: Option<String>,
+
+    /// Port of the TLS listener, 0 to disable
+    #[arg(long)]
+    tls_port: Option<u16>,
+
+    /// Server certificate chain, PEM encoded
+    #[arg(long)]
+    tls_cert_file: Option<PathBuf>,
+
+    /// Private key of the server certificate, PEM encoded
+    #[arg(long)]
+    tls_key_file: Option<PathBuf>,
+
+    /// CA certificates client certificates are checked against
+    #[arg(long)]
+    tls_ca_cert_file: Option<PathBuf>,
+
+    /// Whether clients must present a certificate (yes, no or optional)
+    #[arg(long)]
+    tls_auth_clients: Option<String>,
+
+    /// Run as part of a cluster (yes or no)
+    #[arg(long)]
+    cluster_enabled: Option<String>,
+
+    /// Static cluster node list, e.g. 127.0.0.1:7000,127.0.0.1:7001
+    #[arg(long)]
+    cluster_nodes: Option<String>,
+
+    /// Serve Prometheus metrics over HTTP on this address, e.g. 0.0.0.0:9121
+    #[cfg(feature = "metrics")]
+    #[arg(long)]
+    metrics_addr: Option<std::net::SocketAddr>,
+}
+
+impl Cli {
+    /// Build the configuration: defaults, then the file, then the flags.
+    fn into_config(self) -> my_redis::Result<Config> {
+        let mut config = match &self.config_file {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+
+        let overrides = [
+            ("bind", self.bind),
+            ("port", self.port.map(|v| v.to_string())),
+            ("databases", self.databases.map(|v| v.to_string())),
+            ("loglevel", self.loglevel),
+            ("dir", self.dir.map(|v| v.display().to_string())),
+            ("dbfilename", self.dbfilename),
+            ("save", self.save.map(|v| v.to_string())),
+            ("maxclients", self.maxclients.map(|v| v.to_string())),
+            ("timeout", self.timeout.map(|v| v.to_string())),
+            ("tcp-keepalive", self.tcp_keepalive.map(|v| v.to_string())),
+            ("requirepass", self.requirepass),
+            ("aclfile", self.aclfile.map(|v| v.display().to_string())),
+            ("unixsocket", self.unixsocket.map(|v| v.display().to_string())),
+            ("unixsocketperm", self.unixsocketperm),
+            ("tls-port", self.tls_port.map(|v| v.to_string())),
+            ("tls-cert-file", self.tls_cert_file.map(|v| v.display().to_string())),
+            ("tls-key-file", self.tls_key_file.map(|v| v.display().to_string())),
+            ("tls-ca-cert-file", self.tls_ca_cert_file.map(|v| v.display().to_string())),
+            ("tls-auth-clients", self.tls_auth_clients),
+            ("cluster-enabled", self.cluster_enabled),
+            ("cluster-nodes", self.cluster_nodes),
+        ];
+        for (name, value) in overrides {
+            if let Some(value) = value {
+                config.set(name, &[value]).map_err(|e| format!("--{}: {}", name, e))?;
+            }
+        }
+
+        Ok(config)
+    }
+}
 
 #[tokio::main]
 pub async fn main() -> my_redis::Result<()> {
-    let port = DEFAULT_PORT;
-    // Bind a TCP listener
-    let listener = TcpListener::bind(&format!("127.0.0.1:{}", port)).await?;
-    let databases = Arc::new(AllDbs::new());
+    let cli = Cli::parse();
+    #[cfg(feature = "metrics")]
+    let metrics_addr = cli.metrics_addr;
+    let config: SharedConfig = Arc::new(RwLock::new(cli.into_config()?));
+    let startup = config.read().unwrap().clone();
+    set_up_logging(&startup)?;
+
+    let cluster = if startup.cluster_enabled {
+        let state = ClusterState::from_spec(&startup.cluster_nodes, &startup.bind, startup.port)?;
+        Some(Arc::new(std::sync::Mutex::new(state)))
+    } else {
+        None
+    };
+    let databases = Arc::new(AllDbs::new(startup.databases));
+    let loaded = snapshot::load(&databases, &startup.snapshot_path())?;
+    tracing::info!(keys = loaded, path = %startup.snapshot_path().display(), "loaded snapshot");
+
+    if startup.port == 0 && startup.tls_port == 0 && startup.unixsocket.is_none() {
+        return Err("nothing to listen on, set port, tls-port or unixsocket".into());
+    }
+    #[cfg(not(feature = "tls"))]
+    if startup.tls_port != 0 {
+        return Err("tls-port is set but TLS support is not compiled in, build with the tls feature".into());
+    }
+    #[cfg(feature = "tls")]
+    let tls = if startup.tls_port != 0 {
+        Some(my_redis::tls::Tls::new(&startup)?)
+    } else {
+        None
+    };
+
+    // Bind the TCP listeners
+    let listener = bind(&startup.bind, startup.port).await?;
+    let tls_listener = bind(&startup.bind, startup.tls_port).await?;
+    let unix_listener = bind_unix(&startup)?;
+    let stats = Arc::new(Stats::new());
+    stats.latency().configure(&startup);
+    tokio::spawn(save_periodically(databases.clone(), config.clone(), stats.clone()));
+    let slowlog = Arc::new(SlowLog::new(&startup));
+    // Shutdown happens in two steps: connections are told to stop first, the
+    // workers executing their requests once they are done.
+    let (notify_shutdown, _) = broadcast::channel(1);
+    let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel::<()>(1);
+    let (notify_workers, _) = broadcast::channel(1);
+    let (workers_complete_tx, mut workers_complete_rx) = mpsc::channel::<()>(1);
     let mut senders: Vec<Sender<Request>> = vec![];
-    for database_index in 0..NUM_DBS {
+    for database_index in 0..databases.len() {
         let (tx, rx) = mpsc::channel(32);
         let all_dbs_clone = Arc::clone(&databases);
+        let stats_clone = stats.clone();
+        let slowlog_clone = slowlog.clone();
+        let shutdown = Shutdown::new(notify_workers.subscribe());
+        let complete = workers_complete_tx.clone();
         tokio::spawn(async move {
-            initialize_server(rx, database_index as usize, all_dbs_clone).await;
+            initialize_server(rx, database_index, all_dbs_clone, stats_clone, slowlog_clone, shutdown, complete).await;
         });
         senders.push(tx);
     }
     let sender_arc: Arc<Vec<Sender<Request>>> = Arc::new(senders);
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = metrics_addr {
+        let metrics_listener = TcpListener::bind(addr).await?;
+        tracing::info!(%addr, "serving metrics");
+        tokio::spawn(serve_metrics(metrics_listener, stats.clone(), databases.clone(), sender_arc.clone()));
+    }
+    let acl = Acl::new(&startup);
+    if let Some(path) = &startup.aclfile {
+        acl.load_file(path)?;
+        tracing::info!(path = %path.display(), "loaded ACL users");
+    }
+    let (request_shutdown, mut shutdown_requested) = mpsc::channel(1);
+    let shared = Arc::new(Shared {
+        all_dbs: databases,
+        channels: sender_arc,
+        cluster,
+        config,
+        stats,
+        slowlog,
+        monitors: Monitors::new(),
+        clients: ClientRegistry::new(),
+        acl,
+        #[cfg(feature = "tls")]
+        tls,
+        request_shutdown,
+    });
+
+    let save = tokio::select! {
+        _ = async {
+            tokio::join!(
+                serve(listener.as_ref(), false, &shared, &notify_shutdown, &shutdown_complete_tx),
+                serve(tls_listener.as_ref(), true, &shared, &notify_shutdown, &shutdown_complete_tx),
+                serve_unix(unix_listener.as_ref(), &shared, &notify_shutdown, &shutdown_complete_tx),
+            )
+        } => None,
+        _ = shutdown_signal() => {
+            tracing::info!("received shutdown signal");
+            None
+        }
+        Some(save) = shutdown_requested.recv() => {
+            tracing::info!("shutdown requested by a client");
+            save
+        }
+    };
+
+    // Stop accepting connections, then give the in-flight commands until the
+    // deadline to finish.
+    drop(listener);
+    drop(tls_listener);
+    drop(unix_listener);
+    if let Some(path) = &shared.config.read().unwrap().unixsocket {
+        let _ = std::fs::remove_file(path);
+    }
+    let deadline = Instant::now() + Duration::from_secs(shared.config.read().unwrap().shutdown_timeout);
+    let _ = notify_shutdown.send(());
+    let released = shared.clients.shut_down();
+    tracing::info!(released, "waiting for in-flight commands");
+    drop(shutdown_complete_tx);
+    if tokio::time::timeout_at(deadline.into(), shutdown_complete_rx.recv()).await.is_err() {
+        tracing::warn!("connections still busy after shutdown-timeout, closing them");
+    }
+
+    // Execute whatever is left in the request channels.
+    let _ = notify_workers.send(());
+    drop(workers_complete_tx);
+    if tokio::time::timeout_at(deadline.into(), workers_complete_rx.recv()).await.is_err() {
+        tracing::warn!("database workers still busy after shutdown-timeout");
+    }
+
+    let save = save.unwrap_or_else(|| shared.config.read().unwrap().save > 0);
+    if save {
+        let keys = save_snapshot(&shared.all_dbs, &shared.config, &shared.stats)?;
+        tracing::info!(keys, "snapshot saved before exiting");
+    }
+    tracing::info!("server stopped");
+    Ok(())
+}
+
+/// Bind a TCP listener on `port` of `addr`, `None` if the port is `0`.
+async fn bind(addr: &str, port: u16) -> Result<Option<TcpListener>> {
+    if port == 0 {
+        return Ok(None);
+    }
+    let listener = TcpListener::bind(&format!("{}:{}", addr, port)).await?;
+    tracing::info!("listening on {}:{}", addr, port);
+    Ok(Some(listener))
+}
+
+/// Accept connections until the future is dropped, spawning a task per
+/// connection. Every task holds a clone of `shutdown_complete` until it
+/// finishes. Connections to the `tls` listener start with a TLS handshake.
+async fn serve(listener: Option<&TcpListener>, tls: bool, shared: &Arc<Shared>, notify_shutdown: &broadcast::Sender<()>, shutdown_complete: &mpsc::Sender<()>) {
+    let listener = match listener {
+        Some(listener) => listener,
+        None => return std::future::pending().await,
+    };
+    loop {
+        let (socket, addr) = accept(|| listener.accept()).await;
+        tracing::debug!(%addr, tls, "accepted connection");
+        let laddr = socket.local_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let shared_clone = shared.clone();
+        let open = async move { open_stream(socket, tls, &shared_clone).await };
+        spawn_connection(addr.to_string(), laddr, open, shared, notify_shutdown, shutdown_complete);
+    }
+}
+
+/// Create the Unix socket listener if `unixsocket` is set, replacing a
+/// socket file left behind by a previous run.
+#[cfg(unix)]
+fn bind_unix(config: &Config) -> Result<Option<UnixListener>> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let path = match &config.unixsocket {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    if std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path).map_err(|e| format!("can't bind unix socket {}: {}", path.display(), e))?;
+    if config.unixsocketperm != 0 {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(config.unixsocketperm))?;
+    }
+    tracing::info!("listening on unix socket {}", path.display());
+    Ok(Some(listener))
+}
+
+#[cfg(not(unix))]
+fn bind_unix(config: &Config) -> Result<Option<UnixListener>> {
+    match config.unixsocket {
+        Some(_) => Err("unixsocket is only supported on Unix".into()),
+        None => Ok(None),
+    }
+}
+
+/// Stands in for the Unix socket listener where there is none.
+#[cfg(not(unix))]
+enum UnixListener {}
+
+/// Like `serve`, for the Unix socket. Its clients are known by the path of
+/// the socket.
+async fn serve_unix(listener: Option<&UnixListener>, shared: &Arc<Shared>, notify_shutdown: &broadcast::Sender<()>, shutdown_complete: &mpsc::Sender<()>) {
+    let listener = match listener {
+        Some(listener) => listener,
+        None => return std::future::pending().await,
+    };
+    #[cfg(unix)]
+    {
+        let addr = format!("{}:0", shared.config.read().unwrap().unixsocket.clone().unwrap_or_default().display());
+        loop {
+            let (socket, _) = accept(|| listener.accept()).await;
+            tracing::debug!(%addr, "accepted connection");
+            let open = async move { Ok(Box::new(socket) as Box<dyn Stream>) };
+            spawn_connection(addr.clone(), addr.clone(), open, shared, notify_shutdown, shutdown_complete);
+        }
+    }
+    #[cfg(not(unix))]
+    match *listener {}
+}
+
+/// Serve a connection from `addr` to `laddr` on a task of its own, once
+/// `open` has prepared its stream. The task holds a clone of
+/// `shutdown_complete` until it finishes.
+fn spawn_connection<F>(addr: String, laddr: String, open: F, shared: &Arc<Shared>, notify_shutdown: &broadcast::Sender<()>, shutdown_complete: &mpsc::Sender<()>)
+where
+    F: Future<Output = Result<Box<dyn Stream>>> + Send + 'static,
+{
+    let shared = shared.clone();
+    // Subscribe here, so a connection accepted right before shutdown still
+    // gets notified.
+    let shutdown = Shutdown::new(notify_shutdown.subscribe());
+    let complete = shutdown_complete.clone();
+    let span = tracing::info_span!("connection", %addr);
+    tokio::spawn(async move{
+        let res = match open.await {
+            Ok(stream) => process_incoming_frame(stream, addr, laddr, shared, shutdown).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            tracing::error!("connection error: {}", e);
+        }
+        drop(complete);
+    }.instrument(span));
+}
+
+/// Completes on SIGINT, or SIGTERM on Unix.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => tracing::error!("can't listen for SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        tracing::error!("can't listen for SIGINT: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Accept the next connection.
+///
+/// Errors such as running out of file descriptors are usually transient, so
+/// instead of giving up, wait before trying again, doubling the delay after
+/// every consecutive failure.
+async fn accept<T, F, Fut>(mut accept: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::io::Result<T>>,
+{
+    let mut backoff = Duration::from_millis(10);
     loop {
-        let (socket, _) = listener.accept().await?;
-        println!("Accepted");
-        let all_dbs_clone = databases.clone();
-        let sender_arc_clone = sender_arc.clone();
-        tokio::spawn(async move{
-            if let Err(e) = process_incoming_frame(socket, all_dbs_clone, sender_arc_clone).await {
-                eprintln!("An error occurred: {}", e);
+        match accept().await {
+            Ok(accepted) => return accepted,
+            Err(e) => {
+                tracing::error!(retry_in = ?backoff, "accept failed: {}", e);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(5));
             }
+        }
+    }
+}
+
+/// Time allowed to a client to complete the TLS handshake.
+#[cfg(feature = "tls")]
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Prepare an accepted socket to be served: enable TCP keepalive, and run
+/// the TLS handshake if it was accepted by the TLS listener.
+///
+/// Nagle's algorithm is disabled: replies are already written a batch at a
+/// time, and holding back the last one of a batch only adds latency.
+async fn open_stream(socket: TcpStream, tls: bool, shared: &Shared) -> Result<Box<dyn Stream>> {
+    socket.set_nodelay(true)?;
+    set_keepalive(&socket, shared.config.read().unwrap().tcp_keepalive)?;
+    if !tls {
+        return Ok(Box::new(socket));
+    }
+    #[cfg(feature = "tls")]
+    if let Some(tls) = &shared.tls {
+        let stream = tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, tls.acceptor().accept(socket))
+            .await
+            .map_err(|_| "TLS handshake timed out")??;
+        return Ok(Box::new(stream));
+    }
+    Err("TLS is not enabled".into())
+}
+
+/// Enable TCP keepalive on a client socket, probing after `interval` seconds
+/// of inactivity. `0` leaves keepalive disabled.
+fn set_keepalive(socket: &TcpStream, interval: u64) -> std::io::Result<()> {
+    if interval == 0 {
+        return Ok(());
+    }
+    let keepalive = socket2::TcpKeepalive::new().with_time(Duration::from_secs(interval));
+    socket2::SockRef::from(socket).set_tcp_keepalive(&keepalive)
+}
+
+/// Answer Prometheus scrapes. Only `GET /metrics` is served; the request
+/// body and headers are ignored.
+#[cfg(feature = "metrics")]
+async fn serve_metrics(listener: TcpListener, stats: Arc<Stats>, all_dbs: Arc<AllDbs>, channels: Arc<Vec<Sender<Request>>>) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    loop {
+        let (mut socket, _) = accept(|| listener.accept()).await;
+        let stats = stats.clone();
+        let all_dbs = all_dbs.clone();
+        let channels = channels.clone();
+        tokio::spawn(async move {
+            // Read the request head, which is all we need to route.
+            let mut head = Vec::new();
+            let mut buf = [0; 1024];
+            while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < 8192 {
+                match socket.read(&mut buf).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => head.extend_from_slice(&buf[..n]),
+                }
+            }
+
+            let response = if head.starts_with(b"GET /metrics ") {
+                let queue_depths: Vec<usize> = channels
+                    .iter()
+                    .map(|sender| sender.max_capacity() - sender.capacity())
+                    .collect();
+                let body = stats.metrics().render(
+                    stats.connected_clients(),
+                    stats.blocked_clients(),
+                    &all_dbs,
+                    &queue_depths,
+                );
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            };
+            let _ = socket.write_all(response.as_bytes()).await;
         });
     }
 }
+
+/// Handle to swap the log filter when `loglevel` changes at runtime.
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Log to the console at the configured level. `RUST_LOG` takes precedence
+/// when set.
+///
+/// With the `otel` feature, spans are also exported over OTLP to the
+/// collector named by `OTEL_EXPORTER_OTLP_ENDPOINT` (`localhost:4317` by
+/// default), using X-Ray compatible trace ids and propagation.
+fn set_up_logging(config: &Config) -> my_redis::Result<()> {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(config.tracing_level()));
+    let (filter, handle) = reload::Layer::new(filter);
+    let subscriber = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer());
+
+    #[cfg(feature = "otel")]
+    let subscriber = {
+        // Set the global propagator to the X-Ray propagator, so trace
+        // context can be passed along as `X-Amzn-Trace-Id`.
+        global::set_text_map_propagator(XrayPropagator::default());
+
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_env())
+            .with_trace_config(
+                sdktrace::config()
+                    .with_sampler(sdktrace::Sampler::AlwaysOn)
+                    // Needed in order to convert the trace IDs into an
+                    // X-Ray compatible format
+                    .with_id_generator(XrayIdGenerator::default())
+                    .with_resource(Resource::new(vec![KeyValue::new("service.name", "my-redis-server")])),
+            )
+            .install_simple()?;
+        subscriber.with(tracing_opentelemetry::layer().with_tracer(tracer))
+    };
+
+    subscriber.try_init()?;
+    let _ = LOG_FILTER.set(handle);
+    Ok(())
+}
+
+/// Write a snapshot every `save` seconds. The interval is read from the
+/// running configuration before every wait, so `CONFIG SET save` applies
+/// from the next snapshot on.
+async fn save_periodically(all_dbs: Arc<AllDbs>, config: SharedConfig, stats: Arc<Stats>) {
+    loop {
+        let seconds = config.read().unwrap().save;
+        if seconds == 0 {
+            // Saving is disabled, check again later.
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            continue;
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(seconds)).await;
+
+        match save_snapshot(&all_dbs, &config, &stats) {
+            Ok(keys) => tracing::info!(keys, "snapshot saved"),
+            Err(e) => tracing::error!("snapshot failed: {}", e),
+        }
+    }
+}
+
+/// Write a snapshot to the configured path, recording the time it took as
+/// latency events. Returns the number of keys written.
+fn save_snapshot(all_dbs: &AllDbs, config: &SharedConfig, stats: &Stats) -> my_redis::Result<usize> {
+    let path = config.read().unwrap().snapshot_path();
+    let started = Instant::now();
+    let saved = snapshot::save(all_dbs, &path)?;
+    stats.latency().record("save", started.elapsed());
+    stats.latency().record("save-fsync", saved.fsync);
+    Ok(saved.keys)
+}
+
+/// Execute a `CONFIG` subcommand against the running configuration.
+fn config_command(subcommand: &ConfigSubcommand, shared: &Shared) -> Frame {
+    let Shared { config, stats, slowlog, acl, .. } = shared;
+    match subcommand {
+        ConfigSubcommand::Get(patterns) => {
+            let config = config.read().unwrap();
+            let mut seen = Vec::new();
+            let mut frame = Vec::new();
+            for pattern in patterns {
+                for (name, value) in config.get_matching(pattern) {
+                    if !seen.contains(&name) {
+                        seen.push(name);
+                        frame.push((Frame::Bulk(Bytes::from(name)), Frame::Bulk(Bytes::from(value))));
+                    }
+                }
+            }
+            Frame::Map(frame)
+        }
+        ConfigSubcommand::Set(pairs) => {
+            let mut config = config.write().unwrap();
+            #[cfg(feature = "tls")]
+            let previous = config.clone();
+            if let Err(e) = config.set_at_runtime(pairs) {
+                return Frame::Error(e.to_string());
+            }
+            // New certificates are loaded right away, and the change is
+            // undone if they can't be.
+            #[cfg(feature = "tls")]
+            if let Some(tls) = &shared.tls {
+                if pairs.iter().any(|(name, _)| name.to_ascii_lowercase().starts_with("tls-")) {
+                    if let Err(e) = tls.reload(&config) {
+                        *config = previous;
+                        return Frame::Error(format!("ERR Unable to update TLS configuration: {}", e));
+                    }
+                }
+            }
+            if pairs.iter().any(|(name, _)| name.eq_ignore_ascii_case("loglevel")) {
+                if let Some(handle) = LOG_FILTER.get() {
+                    let _ = handle.reload(EnvFilter::new(config.tracing_level()));
+                }
+            }
+            if pairs.iter().any(|(name, _)| name.eq_ignore_ascii_case("requirepass")) {
+                acl.set_requirepass(&config.requirepass);
+            }
+            slowlog.configure(&config);
+            stats.latency().configure(&config);
+            Frame::Simple("OK".to_string())
+        }
+        ConfigSubcommand::Resetstat => {
+            stats.reset();
+            Frame::Simple("OK".to_string())
+        }
+        ConfigSubcommand::Rewrite => match config.read().unwrap().rewrite() {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(e) => Frame::Error(e.to_string()),
+        },
+    }
+}
+
+/// Execute `HELLO`: authenticate and name the client if asked to, switch its
+/// protocol version, and describe the server.
+fn hello_command(cmd: &my_redis::cmd::Hello, client: &mut Client, acl: &Acl) -> Frame {
+    match cmd.auth() {
+        Some((username, password)) => {
+            if !acl.authenticate(username, password) {
+                acl.log_denied("auth", "HELLO", username, client.handle.describe());
+                return Frame::Error("WRONGPASS invalid username-password pair or user is disabled.".to_string());
+            }
+            client.authenticated = true;
+            client.handle.set_user(username);
+        }
+        None if !client.authenticated => {
+            return Frame::Error(
+                "NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".to_string(),
+            );
+        }
+        None => {}
+    }
+    if let Some(name) = cmd.setname() {
+        client.handle.set_name(name);
+    }
+    if let Some(version) = cmd.protover() {
+        client.connection.set_protocol(version);
+    }
+
+    let field = |name: &'static str, value: Frame| (Frame::Bulk(Bytes::from(name)), value);
+    Frame::Map(vec![
+        field("server", Frame::Bulk(Bytes::from("redis"))),
+        field("version", Frame::Bulk(Bytes::from(env!("CARGO_PKG_VERSION")))),
+        field("proto", Frame::Integer(client.connection.protocol() as i64)),
+        field("id", Frame::Integer(client.handle.id() as i64)),
+        field("mode", Frame::Bulk(Bytes::from(if client.cluster.is_some() { "cluster" } else { "standalone" }))),
+        field("role", Frame::Bulk(Bytes::from("master"))),
+        field("modules", Frame::Array(vec![])),
+    ])
+}
+
+/// Execute a `COMMAND` subcommand, answered from the command table.
+fn command_command(subcommand: &CommandsSubcommand) -> Frame {
+    match subcommand {
+        CommandsSubcommand::Count => Frame::Integer(COMMANDS.len() as i64),
+        CommandsSubcommand::Info(names) if names.is_empty() => {
+            Frame::Array(COMMANDS.iter().map(CommandSpec::info_frame).collect())
+        }
+        CommandsSubcommand::Info(names) => Frame::Array(
+            names
+                .iter()
+                .map(|name| CommandSpec::lookup_full(name).map_or(Frame::Null, CommandSpec::info_frame))
+                .collect(),
+        ),
+        CommandsSubcommand::Docs(names) => {
+            let specs: Vec<&CommandSpec> = if names.is_empty() {
+                COMMANDS.iter().collect()
+            } else {
+                names.iter().filter_map(|name| CommandSpec::lookup_full(name)).collect()
+            };
+            Frame::Map(specs.into_iter().map(|spec| (Frame::Bulk(Bytes::from(spec.name)), spec.docs_frame())).collect())
+        }
+        CommandsSubcommand::Getkeys(args) => {
+            let spec = match CommandSpec::lookup(&String::from_utf8_lossy(&args[0])) {
+                Some(spec) => spec,
+                None => return Frame::Error("ERR Invalid command specified".to_string()),
+            };
+            if !spec.accepts(args.len()) {
+                return Frame::Error("ERR Invalid number of arguments specified for command".to_string());
+            }
+            let positions = spec.key_positions(args);
+            if positions.is_empty() {
+                return Frame::Error("ERR The command has no key arguments".to_string());
+            }
+            Frame::Array(positions.into_iter().map(|position| Frame::Bulk(args[position].clone())).collect())
+        }
+        CommandsSubcommand::List(filter) => Frame::Array(
+            COMMANDS
+                .iter()
+                .flat_map(|spec| std::iter::once(spec).chain(spec.subcommands))
+                .filter(|spec| match filter {
+                    None => true,
+                    // There are no modules.
+                    Some(ListFilter::Module(_)) => false,
+                    Some(ListFilter::Aclcat(category)) => spec.categories.iter().any(|c| c.eq_ignore_ascii_case(category)),
+                    Some(ListFilter::Pattern(pattern)) => my_redis::glob::matches(pattern.as_bytes(), spec.name.as_bytes(), true),
+                })
+                .map(|spec| Frame::Bulk(Bytes::from(spec.name)))
+                .collect(),
+        ),
+    }
+}
+
+/// Execute an `ACL` subcommand on behalf of the client `me`.
+fn acl_command(subcommand: &AclSubcommand, me: &ClientHandle, acl: &Acl, clients: &ClientRegistry) -> Frame {
+    let bulk_array = |items: Vec<String>| Frame::Array(items.into_iter().map(|item| Frame::Bulk(Bytes::from(item))).collect());
+    match subcommand {
+        AclSubcommand::Setuser(name, rules) => match acl.set_user(name, rules) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(e) => Frame::Error(e),
+        },
+        AclSubcommand::Getuser(name) => match acl.get_user(name) {
+            Some(user) => user.to_frame(),
+            None => Frame::Null,
+        },
+        AclSubcommand::Deluser(names) => {
+            if names.iter().any(|name| name == "default") {
+                return Frame::Error("ERR The 'default' user cannot be removed".to_string());
+            }
+            let mut deleted = 0;
+            for name in names {
+                if acl.del_user(name) {
+                    deleted += 1;
+                    // Clients authenticated as a deleted user are disconnected.
+                    for handle in clients.list() {
+                        if handle.user() == *name {
+                            handle.kill();
+                        }
+                    }
+                }
+            }
+            Frame::Integer(deleted as i64)
+        }
+        AclSubcommand::List => bulk_array(acl.list()),
+        AclSubcommand::Users => bulk_array(acl.usernames()),
+        AclSubcommand::Whoami => Frame::Bulk(Bytes::from(me.user())),
+        AclSubcommand::Cat(None) => bulk_array(acl::CATEGORIES.iter().map(|category| category.to_string()).collect()),
+        AclSubcommand::Cat(Some(category)) => match acl::category_commands(category) {
+            Some(commands) => bulk_array(commands.into_iter().map(String::from).collect()),
+            None => Frame::Error(format!("ERR Unknown category '{}'", category)),
+        },
+        AclSubcommand::Log(count) => Frame::Array(acl.log(*count).iter().map(|entry| entry.to_frame()).collect()),
+        AclSubcommand::LogReset => {
+            acl.reset_log();
+            Frame::Simple("OK".to_string())
+        }
+    }
+}
+
+/// Execute a `CLIENT` subcommand on behalf of the client `me`.
+fn client_command(subcommand: &ClientSubcommand, me: &ClientHandle, clients: &ClientRegistry) -> Frame {
+    match subcommand {
+        ClientSubcommand::List(ids) => {
+            let mut out = String::new();
+            for handle in clients.list() {
+                if ids.is_empty() || ids.contains(&handle.id()) {
+                    out.push_str(&handle.describe());
+                    out.push('\n');
+                }
+            }
+            Frame::Verbatim("txt".to_string(), Bytes::from(out))
+        }
+        ClientSubcommand::Info => Frame::Verbatim("txt".to_string(), Bytes::from(me.describe() + "\n")),
+        ClientSubcommand::Setname(name) => {
+            me.set_name(name);
+            Frame::Simple("OK".to_string())
+        }
+        ClientSubcommand::Getname => {
+            let name = me.name();
+            if name.is_empty() {
+                Frame::Null
+            } else {
+                Frame::Bulk(Bytes::copy_from_slice(name.as_bytes()))
+            }
+        }
+        ClientSubcommand::Id => Frame::Integer(me.id() as i64),
+        ClientSubcommand::Kill(filter) => {
+            let targets: Vec<_> = clients
+                .list()
+                .into_iter()
+                .filter(|handle| filter.id.is_none_or(|id| handle.id() == id))
+                .filter(|handle| filter.addr.as_ref().is_none_or(|addr| handle.addr() == addr))
+                .filter(|handle| filter.laddr.as_ref().is_none_or(|laddr| handle.laddr() == laddr))
+                .filter(|handle| filter.user.as_ref().is_none_or(|user| handle.user() == *user))
+                .filter(|handle| !(filter.skipme && handle.id() == me.id()) || filter.legacy)
+                .collect();
+            for handle in &targets {
+                handle.kill();
+            }
+            match (filter.legacy, targets.len()) {
+                (true, 0) => Frame::Error("ERR No such client".to_string()),
+                (true, _) => Frame::Simple("OK".to_string()),
+                (false, killed) => Frame::Integer(killed as i64),
+            }
+        }
+        ClientSubcommand::Pause(timeout, write_only) => {
+            let mode = if *write_only { PauseMode::Write } else { PauseMode::All };
+            clients.pause(*timeout, mode);
+            Frame::Simple("OK".to_string())
+        }
+        ClientSubcommand::Unpause => {
+            clients.unpause();
+            Frame::Simple("OK".to_string())
+        }
+        ClientSubcommand::NoEvict(no_evict) => {
+            me.set_no_evict(*no_evict);
+            Frame::Simple("OK".to_string())
+        }
+        ClientSubcommand::Unblock(id, how) => {
+            let unblocked = clients.get(*id).is_some_and(|handle| handle.unblock(*how));
+            Frame::Integer(unblocked as i64)
+        }
+    }
+}
+
+/// Execute a `LATENCY` subcommand.
+fn latency_command(subcommand: &LatencySubcommand, stats: &Stats) -> Frame {
+    let monitor = stats.latency();
+    match subcommand {
+        LatencySubcommand::Latest => {
+            let mut frame = Vec::new();
+            for (name, history) in monitor.events() {
+                if let Some(latest) = history.latest() {
+                    frame.push(Frame::Array(vec![
+                        Frame::Bulk(Bytes::from(name)),
+                        Frame::Integer(latest.timestamp as i64),
+                        Frame::Integer(latest.latency as i64),
+                        Frame::Integer(history.max as i64),
+                    ]));
+                }
+            }
+            Frame::Array(frame)
+        }
+        LatencySubcommand::History(event) => {
+            let samples = monitor.history(event).map(|history| history.samples).unwrap_or_default();
+            Frame::Array(
+                samples
+                    .iter()
+                    .map(|sample| Frame::Array(vec![Frame::Integer(sample.timestamp as i64), Frame::Integer(sample.latency as i64)]))
+                    .collect(),
+            )
+        }
+        LatencySubcommand::Reset(events) => Frame::Integer(monitor.reset(events) as i64),
+        LatencySubcommand::Doctor => Frame::Bulk(Bytes::from(monitor.doctor())),
+        LatencySubcommand::Histogram(commands) => {
+            let mut frame = Vec::new();
+            for (name, command) in stats.commands() {
+                if command.calls == 0 || (!commands.is_empty() && !commands.contains(&name)) {
+                    continue;
+                }
+                let buckets = command
+                    .histogram
+                    .cumulative()
+                    .into_iter()
+                    .map(|(bound, count)| (Frame::Integer(bound as i64), Frame::Integer(count as i64)))
+                    .collect();
+                frame.push((
+                    Frame::Bulk(Bytes::from(name)),
+                    Frame::Map(vec![
+                        (Frame::Bulk(Bytes::from("calls")), Frame::Integer(command.calls as i64)),
+                        (Frame::Bulk(Bytes::from("histogram_usec")), Frame::Map(buckets)),
+                    ]),
+                ));
+            }
+            Frame::Map(frame)
+        }
+    }
+}
+
+/// Turn the connection into a monitor, replying OK and then writing every
+/// processed command to it until the client disconnects. Commands sent by
+/// the client are ignored.
+async fn stream_monitor(client: &mut Client, shared: &Shared) -> Result<()> {
+    let Client { connection, handle, shutdown, .. } = client;
+    connection.set_output_limits(shared.config.read().unwrap().output_limits(ClientClass::Pubsub));
+    // Attach before acknowledging, so the commands the client sees run
+    // after its OK are all fed to it.
+    let mut feed = shared.monitors.subscribe();
+    connection.write_frame(&Frame::Simple("OK".to_string())).await?;
+    loop {
+        tokio::select! {
+            _ = shutdown.recv() => return Ok(()),
+            line = feed.recv() => {
+                let line = match line {
+                    Ok(line) => String::from_utf8_lossy(&line).into_owned(),
+                    Err(missed) => format!("(monitor missed {} commands)", missed),
+                };
+                connection.queue_frame(&Frame::Simple(line));
+                connection.check_output_limits()?;
+            }
+            // Lines are written as fast as the client takes them. One that
+            // doesn't keep up runs into the output buffer limits.
+            open = async {
+                if connection.queued_len() > 0 {
+                    connection.write_queued().await?;
+                    Ok::<_, my_redis::Error>(true)
+                } else {
+                    Ok(connection.read_frame().await?.is_some())
+                }
+            } => {
+                if !open? {
+                    return Ok(());
+                }
+            }
+        }
+        handle.set_output(connection.queued_len(), connection.queued_replies());
+    }
+}
+
+/// Server state shared by every connection.
+struct Shared {
+    all_dbs: Arc<AllDbs>,
+    /// Request channels of the per-database workers
+    channels: Arc<Vec<Sender<Request>>>,
+    cluster: Option<Cluster>,
+    config: SharedConfig,
+    stats: Arc<Stats>,
+    slowlog: Arc<SlowLog>,
+    monitors: Monitors,
+    clients: ClientRegistry,
+    acl: Acl,
+    /// Certificates of the TLS listener, if enabled
+    #[cfg(feature = "tls")]
+    tls: Option<my_redis::tls::Tls>,
+    /// Asks `main` to shut the server down, saving or not as given by
+    /// `SHUTDOWN`
+    request_shutdown: Sender<Option<bool>>,
+}
+
 struct Client {
-    connection: Connection,
+    connection: Connection<Box<dyn Stream>>,
     all_dbs: Arc<AllDbs>,
     index: usize,
+    cluster: Option<Cluster>,
+    // Set by `ASKING`, cleared after the next command.
+    asking: bool,
+    handle: Arc<ClientHandle>,
+    shutdown: Shutdown,
+    /// Whether the client may run commands other than `AUTH`
+    authenticated: bool,
+}
+
+impl Client {
+    /// Write the queued replies. While the client reads them, what is left
+    /// shows in `CLIENT LIST`.
+    async fn flush(&mut self) -> Result<()> {
+        while self.connection.queued_len() > 0 {
+            self.handle.set_output(self.connection.queued_len(), self.connection.queued_replies());
+            self.connection.write_queued().await?;
+        }
+        self.handle.set_output(0, 0);
+        Ok(())
+    }
 }
-async fn process_incoming_frame(socket: TcpStream, all_dbs: Arc<AllDbs>, channels: Arc<Vec<Sender<Request>>>) -> Result<()>{
+
+/// Serve a client connected from `addr` to the local address `laddr`.
+async fn process_incoming_frame(stream: Box<dyn Stream>, addr: String, laddr: String, shared: Arc<Shared>, shutdown: Shutdown) -> Result<()>{
+    let mut connection = Connection::new(stream);
+    let maxclients = shared.config.read().unwrap().maxclients;
+    let registration = match shared.clients.try_register(addr, laddr, maxclients) {
+        Some(registration) => registration,
+        None => {
+            shared.stats.connection_rejected();
+            connection.write_frame(&Frame::Error("ERR max number of clients reached".to_string())).await?;
+            return Ok(());
+        }
+    };
+    let _connected = shared.stats.client_connected();
+    let handle = registration.handle().clone();
     let mut client = Client {
-        connection: Connection::new(socket),
-        all_dbs,
+        connection,
+        all_dbs: shared.all_dbs.clone(),
         index: 0,
+        cluster: shared.cluster.clone(),
+        asking: false,
+        handle: handle.clone(),
+        shutdown,
+        authenticated: shared.acl.default_is_open(),
     };
-    while let Some(frame) = client.connection.read_frame().await? {
-        dbg!(&frame);
+    // `CLIENT KILL` closes the connection whatever it is doing.
+    let res = tokio::select! {
+        res = serve_client(&mut client, &shared) => res,
+        _ = handle.killed() => Ok(()),
+    };
+    match res {
+        Err(e) if is_output_limit_exceeded(&e) => {
+            shared.stats.output_limit_disconnection();
+            tracing::warn!(client = %handle.describe(), "client closed for overcoming of output buffer limits: {}", e);
+            Ok(())
+        }
+        res => res,
+    }
+}
+
+/// Whether writing to a client failed because of its output buffer limits.
+fn is_output_limit_exceeded(e: &my_redis::Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .and_then(|e| e.get_ref())
+        .is_some_and(|e| e.is::<my_redis::OutputLimitExceeded>())
+}
+
+/// Read the next frame, treating a client silent for `idle_timeout` seconds
+/// as disconnected. `0` waits forever.
+async fn read_frame_within(connection: &mut Connection<Box<dyn Stream>>, idle_timeout: u64) -> Result<Option<Frame>> {
+    if idle_timeout == 0 {
+        return connection.read_frame().await;
+    }
+    match tokio::time::timeout(Duration::from_secs(idle_timeout), connection.read_frame()).await {
+        Ok(frame) => frame,
+        Err(_) => {
+            tracing::debug!("closing idle client");
+            Ok(None)
+        }
+    }
+}
+
+/// Give up on a connection after failing to read from it. If the client
+/// broke the protocol, it is told so first, after the replies already queued.
+async fn close_on_error(connection: &mut Connection<Box<dyn Stream>>, e: my_redis::Error) -> Result<()> {
+    if e.is::<my_redis::frame::Error>() {
+        connection.queue_frame(&Frame::Error(format!("ERR {}", e)));
+        // The error is reported either way, there is no point in another.
+        let _ = connection.flush().await;
+    }
+    Err(e)
+}
+
+/// Read and execute the commands of a client until it disconnects.
+///
+/// Commands a client pipelined are executed in a batch: every command
+/// already received is executed, in order, before the replies are flushed
+/// together and the socket is read again. Replies are flushed early before
+/// a command that may block, so the client isn't kept waiting for them.
+async fn serve_client(client: &mut Client, shared: &Shared) -> Result<()> {
+    let Shared { channels, config, stats, slowlog, monitors, clients, acl, request_shutdown, .. } = shared;
+    loop {
+        // Replies pile up until the batch is flushed, a client pipelining
+        // enough commands may go past its limits before that.
+        client.connection.check_output_limits()?;
+        let frame = match client.connection.read_buffered_frame() {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                // The batch is done.
+                client.flush().await?;
+                let idle_timeout = {
+                    let config = config.read().unwrap();
+                    client.connection.set_limits(config.client_limits());
+                    client.connection.set_output_limits(config.output_limits(ClientClass::Normal));
+                    config.timeout
+                };
+                let frame = tokio::select! {
+                    frame = read_frame_within(&mut client.connection, idle_timeout) => frame,
+                    // Commands are only interrupted between two batches.
+                    _ = client.shutdown.recv() => return Ok(()),
+                };
+                match frame {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => return Ok(()),
+                    Err(e) => return close_on_error(&mut client.connection, e).await,
+                }
+            }
+            Err(e) => return close_on_error(&mut client.connection, e).await,
+        };
+        tracing::trace!(?frame, "received frame");
+        let mut args = frame.args();
         let cmd = my_redis::Command::from_frame(frame);
         match cmd {
             Ok(cmd) => {
-                let (request, receiver) = Request::new(cmd);
-                match request.cmd {
-                    Select(cmd) => {
-                        let db_index = *cmd.db_index();
-                        client.index = db_index;
-                        client.connection.write_frame(&Frame::Simple("OK".to_string())).await?;
+                let name = cmd.get_name().to_string();
+                let keys = my_redis::cmd::command_keys(&args);
+                let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+                let span = tracing::info_span!(
+                    "command",
+                    name = %name,
+                    db = client.index,
+                    keys = keys.len(),
+                    outcome = tracing::field::Empty,
+                );
+                // HELLO checks for itself, as it may carry credentials.
+                if !matches!(cmd, Auth(_) | Hello(_)) {
+                    if !client.authenticated {
+                        stats.record_rejected(&name);
+                        span.record("outcome", "denied");
+                        client.connection.queue_frame(&Frame::Error("NOAUTH Authentication required.".to_string()));
+                        continue;
+                    }
+                    let user = client.handle.user();
+                    // No command takes channels until pub/sub is implemented.
+                    if let Err(denied) = acl.check(&user, &name, &args, &keys, &[]) {
+                        let (reason, object) = match &denied {
+                            Denied::Command => ("command", name.as_str()),
+                            Denied::Key(key) => ("key", key.as_str()),
+                            Denied::Channel(channel) => ("channel", channel.as_str()),
+                        };
+                        acl.log_denied(reason, object, &user, client.handle.describe());
+                        stats.record_rejected(&name);
+                        span.record("outcome", "denied");
+                        client.connection.queue_frame(&Frame::Error(denied.message(&user, &name)));
+                        continue;
+                    }
+                }
+                let asking = std::mem::take(&mut client.asking);
+                if let Some(cluster) = &client.cluster {
+                    let db = client.all_dbs.get_instance(client.index).unwrap();
+                    let routed = cluster.lock().unwrap().route(&keys, asking, |key| {
+                        matches!(db.lock().unwrap().get(key), Some(value) if !matches!(value, DataTypes::SenderList(_)))
+                    });
+                    if let Err(redirect) = routed {
+                        stats.record_rejected(&name);
+                        span.record("outcome", "redirected");
+                        client.connection.queue_frame(&redirect);
+                        continue;
+                    }
+                }
+                if !matches!(cmd, my_redis::Command::Client(_) | my_redis::Command::Shutdown(_)) && clients.is_paused(cmd.is_write()) {
+                    client.flush().await?;
+                    clients.wait_unpaused(cmd.is_write()).await;
+                }
+                if matches!(cmd, Blpop(_) | Brpop(_)) {
+                    client.flush().await?;
+                }
+                let (qbuf, qbuf_free) = client.connection.read_buffer();
+                client.handle.start_command(&name, client.index, qbuf, qbuf_free);
+                if let Monitor(_) = cmd {
+                    stats.record_command(&name, Duration::ZERO, false);
+                    client.handle.set_monitor();
+                    return stream_monitor(client, shared).await;
+                }
+                if let my_redis::Command::Shutdown(cmd) = &cmd {
+                    stats.record_command(&name, Duration::ZERO, cmd.abort());
+                    if cmd.abort() {
+                        client.connection.queue_frame(&Frame::Error("ERR No shutdown in progress.".to_string()));
+                        continue;
                     }
-                    _ => {
-                        channels.get(client.index).expect("REASON").send(request).await?;
-                        let frame = receiver.await?;
-                        client.connection.write_frame(&frame).await?;
+                    // A shutdown already requested is enough.
+                    let _ = request_shutdown.try_send(cmd.save());
+                    client.flush().await?;
+                    return Ok(());
+                }
+                // Commands carrying passwords are not shown to monitors, nor
+                // kept in the slow log.
+                let secret = match &cmd {
+                    Unknown(_) | Auth(_) | Hello(_) | my_redis::Command::Acl(_) => true,
+                    Migrate(cmd) => cmd.password().is_some(),
+                    _ => false,
+                };
+                if !secret {
+                    monitors.feed(client.index, client.handle.addr(), &args);
+                }
+                // Commands executed by the database workers are recorded there,
+                // along with their arguments.
+                let mut counted = true;
+                let started = Instant::now();
+                let (request, receiver) = span.in_scope(|| Request::new(cmd));
+                let response: Frame = async {
+                    Ok::<_, my_redis::Error>(match request.cmd {
+                        Select(cmd) => {
+                            let db_index = *cmd.db_index();
+                            if client.cluster.is_some() && db_index != 0 {
+                                Frame::Error("ERR SELECT is not allowed in cluster mode".to_string())
+                            } else if db_index >= client.all_dbs.len() {
+                                Frame::Error("ERR DB index is out of range".to_string())
+                            } else {
+                                client.index = db_index;
+                                Frame::Simple("OK".to_string())
+                            }
+                        }
+                        Asking(_) => {
+                            if client.cluster.is_some() {
+                                client.asking = true;
+                                Frame::Simple("OK".to_string())
+                            } else {
+                                Frame::Error("ERR This instance has cluster support disabled".to_string())
+                            }
+                        }
+                        Save(_) => {
+                            match save_snapshot(&client.all_dbs, config, stats) {
+                                Ok(_) => Frame::Simple("OK".to_string()),
+                                Err(e) => Frame::Error(format!("ERR {}", e)),
+                            }
+                        }
+                        my_redis::Command::Config(cmd) => config_command(cmd.subcommand(), shared),
+                        Slowlog(cmd) => match cmd.subcommand() {
+                            SlowlogSubcommand::Get(count) => {
+                                Frame::Array(slowlog.get(*count).iter().map(SlowLogEntry::to_frame).collect())
+                            }
+                            SlowlogSubcommand::Len => Frame::Integer(slowlog.len() as i64),
+                            SlowlogSubcommand::Reset => {
+                                slowlog.reset();
+                                Frame::Simple("OK".to_string())
+                            }
+                        },
+                        Latency(cmd) => latency_command(cmd.subcommand(), stats),
+                        my_redis::Command::Client(cmd) => client_command(cmd.subcommand(), &client.handle, clients),
+                        Auth(cmd) => {
+                            let username = cmd.username().unwrap_or("default");
+                            if cmd.username().is_none() && acl.default_is_open() {
+                                Frame::Error("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".to_string())
+                            } else if acl.authenticate(username, cmd.password()) {
+                                client.authenticated = true;
+                                client.handle.set_user(username);
+                                Frame::Simple("OK".to_string())
+                            } else {
+                                acl.log_denied("auth", "AUTH", username, client.handle.describe());
+                                Frame::Error("WRONGPASS invalid username-password pair or user is disabled.".to_string())
+                            }
+                        }
+                        Hello(cmd) => hello_command(&cmd, client, acl),
+                        my_redis::Command::Acl(cmd) => acl_command(cmd.subcommand(), &client.handle, acl, clients),
+                        my_redis::Command::Command(cmd) => command_command(cmd.subcommand()),
+                        Info(cmd) => {
+                            let config = config.read().unwrap();
+                            let ctx = InfoContext {
+                                stats,
+                                config: &config,
+                                all_dbs: &client.all_dbs,
+                            };
+                            Frame::Verbatim("txt".to_string(), Bytes::from(info::render(cmd.sections(), &ctx)))
+                        }
+                        Migrate(cmd) => {
+                            let db = client.all_dbs.get_instance(client.index).unwrap();
+                            cmd.apply(&db, client.cluster.is_some()).await
+                        }
+                        Cluster(cmd) => match &client.cluster {
+                            Some(cluster) => cluster_command(cmd.subcommand(), cluster, &client.all_dbs),
+                            None => Frame::Error("ERR This instance has cluster support disabled".to_string()),
+                        },
+                        _ => {
+                            counted = false;
+                            let request = request.with_client(std::mem::take(&mut args), client.handle.clone());
+                            channels.get(client.index).expect("REASON").send(request).await?;
+                            receiver.await?
+                        }
+                    })
+                }
+                .instrument(span.clone())
+                .await?;
+                let failed = matches!(response, Frame::Error(_));
+                span.record("outcome", if failed { "error" } else { "ok" });
+                if counted {
+                    let elapsed = started.elapsed();
+                    stats.record_command(&name, elapsed, failed);
+                    if !secret {
+                        slowlog.record(&args, elapsed, client.handle.addr(), &client.handle.name());
                     }
                 }
+                client.connection.queue_frame(&response);
             }
             Err(e) => {
-                client.connection.write_frame(&Frame::Error(e.to_string())).await?;
+                client.connection.queue_frame(&Frame::Error(e.to_string()));
             }
         }
+    }
+}
 
+/// Execute a `CLUSTER` subcommand against the cluster state. Only database 0
+/// is used in cluster mode.
+fn cluster_command(subcommand: &ClusterSubcommand, cluster: &Cluster, all_dbs: &AllDbs) -> Frame {
+    let mut state = cluster.lock().unwrap();
+    match subcommand {
+        ClusterSubcommand::Info => state.info_frame(),
+        ClusterSubcommand::Myid => Frame::Bulk(Bytes::from(state.myself().id.clone())),
+        ClusterSubcommand::Nodes => state.nodes_frame(),
+        ClusterSubcommand::Slots => state.slots_frame(),
+        ClusterSubcommand::Shards => state.shards_frame(),
+        ClusterSubcommand::Keyslot(key) => Frame::Integer(cluster::key_hash_slot(key.as_bytes()) as i64),
+        ClusterSubcommand::Countkeysinslot(slot) => {
+            let db = all_dbs.get_instance(0).unwrap();
+            Frame::Integer(cluster::keys_in_slot(&db, *slot).len() as i64)
+        }
+        ClusterSubcommand::Getkeysinslot(slot, count) => {
+            let db = all_dbs.get_instance(0).unwrap();
+            let mut keys = cluster::keys_in_slot(&db, *slot);
+            keys.sort();
+            keys.truncate(*count as usize);
+            Frame::Array(keys.into_iter().map(|key| Frame::Bulk(Bytes::from(key))).collect())
+        }
+        ClusterSubcommand::Setslot(slot, action) => {
+            let res = match action {
+                SetslotAction::Migrating(id) => state.set_migrating(*slot, id),
+                SetslotAction::Importing(id) => state.set_importing(*slot, id),
+                SetslotAction::Node(id) => state.set_node(*slot, id),
+                SetslotAction::Stable => {
+                    state.set_stable(*slot);
+                    Ok(())
+                }
+            };
+            match res {
+                Ok(()) => Frame::Simple("OK".to_string()),
+                Err(e) => Frame::Error(e.to_string()),
+            }
+        }
+    }
+}
+
+/// Store a restored `value` under `key`.
+///
+/// A key only holding the registrations of blocked clients counts as absent.
+/// Restoring a list into such a key hands the values to the waiting clients
+/// first, the same way `RPUSH` does.
+fn restore_value(db: &my_redis::db::Db, key: &str, value: DataTypes, replace: bool) -> Frame {
+    let mut db_lock = db.lock().unwrap();
+    let waiters = match db_lock.get(key) {
+        Some(DataTypes::SenderList(senders)) => senders.clone(),
+        Some(_) if !replace => return Frame::Error("BUSYKEY Target key name already exists.".to_string()),
+        _ => LinkedList::new(),
+    };
+
+    let value = match value {
+        DataTypes::List(mut data) if !waiters.is_empty() => {
+            let mut waiters = waiters;
+            while !data.is_empty() {
+                let special_sender = match waiters.pop_front() {
+                    Some(special_sender) => special_sender,
+                    None => break,
+                };
+                if special_sender.sender.is_closed() {
+                    continue;
+                }
+                let value = match special_sender.type_sender {
+                    fromBlpop => data.pop_front().unwrap(),
+                    fromBrpop => data.pop_back().unwrap(),
+                };
+                let package = KeyAndValue { key: key.to_string(), value };
+                tokio::spawn(async move {
+                    special_sender.sender.send(package).await.unwrap();
+                });
+            }
+            if data.is_empty() && !waiters.is_empty() {
+                DataTypes::SenderList(waiters)
+            } else {
+                DataTypes::List(data)
+            }
+        }
+        value => value,
+    };
+
+    if matches!(&value, DataTypes::List(data) if data.is_empty()) {
+        db_lock.remove(key);
+    } else {
+        db_lock.insert(key.to_string(), value);
+    }
+    Frame::Simple("OK".to_string())
+}
+
+/// Wait for a value pushed to one of the keys a `BLPOP` or `BRPOP` is
+/// blocked on, for at most `timeout` seconds, or forever when it is `0`.
+///
+/// The wait also ends when the client is released with `CLIENT UNBLOCK`, or
+/// when it went away and there is nobody left to reply to.
+async fn wait_for_push(receiver: &mut mpsc::Receiver<KeyAndValue>, timeout: f64, client: Option<&ClientHandle>, reply: &mut oneshot::Sender<Frame>) -> Frame {
+    let mut blocked = client.map(ClientHandle::block);
+    let unblocked = async {
+        match &mut blocked {
+            Some(blocked) => blocked.unblocked().await,
+            None => std::future::pending().await,
+        }
+    };
+    let expired = async {
+        if timeout == 0.0 {
+            std::future::pending().await
+        } else {
+            tokio::time::sleep(Duration::from_secs_f64(timeout)).await
+        }
+    };
+    tokio::select! {
+        // We hold a sender, so the channel never closes.
+        Some(res) = receiver.recv() => Frame::Array(vec![Frame::Bulk(Bytes::from(res.key)), Frame::Bulk(res.value)]),
+        _ = expired => Frame::NullArray,
+        how = unblocked => match how {
+            Unblock::Timeout => Frame::NullArray,
+            Unblock::Error => Frame::Error("UNBLOCKED client unblocked via CLIENT UNBLOCK".to_string()),
+            Unblock::Shutdown => Frame::Error("UNBLOCKED server is shutting down".to_string()),
+        },
+        _ = reply.closed() => Frame::NullArray,
+    }
+}
+
+/// Remove the registrations of the blocked client owning `sender` from
+/// `keys`, along with the ones of clients that are gone. Keys left without
+/// any waiter are deleted.
+fn remove_waiter(db: &my_redis::db::Db, keys: &[String], sender: &mpsc::Sender<KeyAndValue>) {
+    let mut db_lock = db.lock().unwrap();
+    for key in keys {
+        let remaining: LinkedList<SpecialSender> = match db_lock.get_mut(key) {
+            Some(DataTypes::SenderList(senders)) => std::mem::take(senders)
+                .into_iter()
+                .filter(|waiter| !waiter.sender.same_channel(sender) && !waiter.sender.is_closed())
+                .collect(),
+            _ => continue,
+        };
+        if remaining.is_empty() {
+            db_lock.remove(key);
+        } else {
+            db_lock.insert(key.clone(), DataTypes::SenderList(remaining));
+        }
     }
-    Ok(())
 }
 
-async fn process_commands_for_index_namespace(request: Request, index: usize, all_dbs: Arc<AllDbs>){
+async fn process_commands_for_index_namespace(mut request: Request, index: usize, all_dbs: Arc<AllDbs>, stats: Arc<Stats>, slowlog: Arc<SlowLog>){
+    let started = Instant::now();
+    // Time spent waiting in a blocking command is not execution time.
+    let mut blocked_at = None;
+    // Unknown commands are not real commands and are not counted.
+    let name = match &request.cmd {
+        Unknown(_) => None,
+        cmd => Some(cmd.get_name().to_string()),
+    };
     let response = match request.cmd {
         Set(cmd) => {
             all_dbs.get_instance(index).unwrap().lock().unwrap().insert(cmd.key().to_string(), DataTypes::BytesInDb(cmd.value().clone()));
@@ -127,7 +1539,7 @@ async fn process_commands_for_index_namespace(request: Request, index: usize, al
                     exists += 1;
                 }
             }
-            Frame::Integer(exists as u64)
+            Frame::Integer(exists as i64)
         }
         Lpush(cmd) => {
             let key = cmd.key().to_string();
@@ -149,7 +1561,7 @@ async fn process_commands_for_index_namespace(request: Request, index: usize, al
                             for value in values {
                                 data.push_front(Bytes::from(value));
                             }
-                            Frame::Integer(data.len() as u64)
+                            Frame::Integer(data.len() as i64)
                         }
                         _ => Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
                     }
@@ -208,7 +1620,7 @@ async fn process_commands_for_index_namespace(request: Request, index: usize, al
                             }
                         }
                     }
-                    Frame::Integer(return_number as u64)
+                    Frame::Integer(return_number as i64)
                 },
                 _ => Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
             }
@@ -233,7 +1645,7 @@ async fn process_commands_for_index_namespace(request: Request, index: usize, al
                             for value in values {
                                 data.push_back(Bytes::from(value));
                             }
-                            Frame::Integer(data.len() as u64)
+                            Frame::Integer(data.len() as i64)
                         }
                         _ => Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
                     }
@@ -292,11 +1704,42 @@ async fn process_commands_for_index_namespace(request: Request, index: usize, al
                             }
                         }
                     }
-                    Frame::Integer(return_number as u64)
+                    Frame::Integer(return_number as i64)
                 },
                 _ => Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
             }
         }
+        Dump(cmd) => {
+            match all_dbs.get_instance(index).unwrap().lock().unwrap().get(cmd.key()).and_then(dump::serialize) {
+                Some(payload) => Frame::Bulk(payload),
+                None => Frame::Null,
+            }
+        }
+        Restore(cmd) => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64;
+            match dump::deserialize(cmd.payload()) {
+                Err(e) => Frame::Error(e.to_string()),
+                // An absolute expiry in the past means the key would be
+                // expired right away, so it is not created at all.
+                Ok(_) if cmd.absttl() && cmd.ttl() != 0 && cmd.ttl() <= now => {
+                    let db = all_dbs.get_instance(index).unwrap();
+                    let mut db_lock = db.lock().unwrap();
+                    match db_lock.get(cmd.key()) {
+                        Some(DataTypes::SenderList(_)) | None => Frame::Simple("OK".to_string()),
+                        Some(_) if !cmd.replace() => Frame::Error("BUSYKEY Target key name already exists.".to_string()),
+                        Some(_) => {
+                            db_lock.remove(cmd.key());
+                            Frame::Simple("OK".to_string())
+                        }
+                    }
+                }
+                Ok(_) if cmd.ttl() != 0 => Frame::Error("ERR key expiration is not supported".to_string()),
+                Ok(value) => restore_value(&all_dbs.get_instance(index).unwrap(), cmd.key(), value, cmd.replace()),
+            }
+        }
         Unknown(cmd) => Frame::Simple(format!("{:?}", cmd)),
         Blpop(cmd) => {
             let keys = cmd.get_lists();
@@ -366,21 +1809,11 @@ async fn process_commands_for_index_namespace(request: Request, index: usize, al
                         }
                     }
                 }
-                if *timeout == 0.0{
-                    let res = rx2.recv().await.unwrap();
-                    Frame::Array(vec![Frame::Bulk(Bytes::from(res.key)), Frame::Bulk(res.value)])
-                }else{
-                    let res =
-                        tokio::select! {
-                        res = rx2.recv() => {
-                            Frame::Bulk(res.unwrap().value)
-                        },
-                        _ = tokio::time::sleep(std::time::Duration::from_secs_f64(*timeout))=> {
-                            Frame::Null
-                        }
-                   };
-                    res
-                }
+                let _blocked = stats.client_blocked();
+                blocked_at = Some(Instant::now());
+                let res = wait_for_push(&mut rx2, *timeout, request.client.as_deref(), &mut request.sender).await;
+                remove_waiter(&all_dbs.get_instance(index).unwrap(), keys, &tx2);
+                res
             }
         }
         Brpop(cmd) =>{
@@ -451,35 +1884,51 @@ async fn process_commands_for_index_namespace(request: Request, index: usize, al
                         }
                     }
                 }
-                if *timeout == 0.0{
-                    let res = rx2.recv().await.unwrap();
-                    Frame::Array(vec![Frame::Bulk(Bytes::from(res.key)), Frame::Bulk(res.value)])
-                }else{
-                    let res =
-                        tokio::select! {
-                        res = rx2.recv() => {
-                            Frame::Bulk(res.unwrap().value)
-                        },
-                        _ = tokio::time::sleep(std::time::Duration::from_secs_f64(*timeout))=> {
-                            Frame::Null
-                        }
-                   };
-                    res
-                }
+                let _blocked = stats.client_blocked();
+                blocked_at = Some(Instant::now());
+                let res = wait_for_push(&mut rx2, *timeout, request.client.as_deref(), &mut request.sender).await;
+                remove_waiter(&all_dbs.get_instance(index).unwrap(), keys, &tx2);
+                res
             }
         }
         cmd => panic!("unimplemented {:?}", cmd),
     };
-    request.sender.send(response).unwrap();
+    let elapsed = blocked_at.unwrap_or_else(Instant::now) - started;
+    if let Some(name) = name {
+        stats.record_command(&name, elapsed, matches!(response, Frame::Error(_)));
+    }
+    if let Some(client) = &request.client {
+        slowlog.record(&request.args, elapsed, client.addr(), &client.name());
+    }
+    // The client may have been killed in the meantime.
+    let _ = request.sender.send(response);
 }
 
-async fn initialize_server(mut receiver: Receiver<Request>, index: usize, all_dbs: Arc<AllDbs>) {
-    while let Some(request) = receiver.recv().await{
+async fn initialize_server(mut receiver: Receiver<Request>, index: usize, all_dbs: Arc<AllDbs>, stats: Arc<Stats>, slowlog: Arc<SlowLog>, mut shutdown: Shutdown, complete: mpsc::Sender<()>) {
+    loop {
+        let request = tokio::select! {
+            request = receiver.recv() => request,
+            _ = shutdown.recv(), if !shutdown.is_shutdown() => {
+                // Refuse new requests, but still execute the queued ones.
+                receiver.close();
+                continue;
+            }
+        };
+        let request = match request {
+            Some(request) => request,
+            None => break,
+        };
         // dbg!(&request);
         let adbs = all_dbs.clone();
+        let stats = stats.clone();
+        let slowlog = slowlog.clone();
+        let complete = complete.clone();
+        // Run under the span of the connection that sent the request.
+        let span = request.span.clone();
         tokio::task::spawn(async move {
-            process_commands_for_index_namespace(request, index, adbs).await;
-        });
+            process_commands_for_index_namespace(request, index, adbs, stats, slowlog).await;
+            drop(complete);
+        }.instrument(span));
     }
 
     // vec![arc::new(server::new()); 16]
@@ -0,0 +1,169 @@
+//! Minimal Redis client implementation
+//!
+//! Provides an async connect and methods for issuing the supported commands.
+
+use crate::cmd::{Get, Ping, Set};
+use crate::{Connection, Frame, Stream};
+
+use bytes::Bytes;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tracing::{debug, instrument};
+
+/// Established connection with a Redis server.
+///
+/// Backed by a single `TcpStream` or Unix socket, `Client` provides basic
+/// network client functionality (no pooling, retrying, ...). Connections are
+/// established using the [`connect`](fn@Client::connect) and
+/// [`connect_unix`](fn@Client::connect_unix) functions.
+///
+/// Requests are issued using the various methods of `Client`.
+#[derive(Debug)]
+pub struct Client {
+    /// The connection, decorated with the redis protocol encoder / decoder.
+    /// The stream is boxed so TCP and Unix socket clients are the same type.
+    connection: Connection<Box<dyn Stream>>,
+}
+
+impl Client {
+    /// Establish a connection with the Redis server located at `addr`.
+    ///
+    /// `addr` may be any type that can be asynchronously converted to a
+    /// `SocketAddr`. This includes `SocketAddr` and strings. The `ToSocketAddrs`
+    /// trait is the Tokio version and not the `std` version.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let client = match Client::connect("localhost:6379").await {
+    ///         Ok(client) => client,
+    ///         Err(_) => panic!("failed to establish connection"),
+    ///     };
+    /// # drop(client);
+    /// }
+    /// ```
+    pub async fn connect<T: ToSocketAddrs>(addr: T) -> crate::Result<Client> {
+        let socket = TcpStream::connect(addr).await?;
+        Ok(Client::new(Box::new(socket)))
+    }
+
+    /// Establish a connection with a Redis server listening on the Unix
+    /// socket at `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use my_redis::clients::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let client = match Client::connect_unix("/tmp/my-redis.sock").await {
+    ///         Ok(client) => client,
+    ///         Err(_) => panic!("failed to establish connection"),
+    ///     };
+    /// # drop(client);
+    /// }
+    /// ```
+    #[cfg(unix)]
+    pub async fn connect_unix(path: impl AsRef<Path>) -> crate::Result<Client> {
+        let socket = tokio::net::UnixStream::connect(path).await?;
+        Ok(Client::new(Box::new(socket)))
+    }
+
+    fn new(stream: Box<dyn Stream>) -> Client {
+        Client {
+            connection: Connection::new(stream),
+        }
+    }
+
+    /// Ping to the server.
+    ///
+    /// Returns the message the server echoes back, `Pong` if no message was
+    /// provided.
+    #[instrument(skip(self))]
+    pub async fn ping(&mut self, msg: Option<&str>) -> crate::Result<Bytes> {
+        let frame = Ping::new(msg.unwrap_or("Pong")).into_frame();
+        debug!(request = ?frame);
+        self.connection.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(value) => Ok(value.into()),
+            Frame::Bulk(value) => Ok(value),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Get the value of key.
+    ///
+    /// If the key does not exist the special value `None` is returned.
+    #[instrument(skip(self))]
+    pub async fn get(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        // Create a `Get` command for the `key` and convert it to a frame.
+        let frame = Get::new(key).into_frame();
+
+        debug!(request = ?frame);
+
+        // Write the frame to the socket. This writes the full frame to the
+        // socket, waiting if necessary.
+        self.connection.write_frame(&frame).await?;
+
+        // Wait for the response from the server
+        //
+        // Both `Simple` and `Bulk` frames are accepted. `Null` represents the
+        // key not being present and `None` is returned.
+        match self.read_response().await? {
+            Frame::Simple(value) => Ok(Some(value.into())),
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Set `key` to hold the given `value`.
+    ///
+    /// The `value` is associated with `key` until it is overwritten by the
+    /// next call to `set` or it is removed.
+    #[instrument(skip(self))]
+    pub async fn set(&mut self, key: &str, value: Bytes) -> crate::Result<()> {
+        let frame = Set::new(key, value, None).into_frame();
+
+        debug!(request = ?frame);
+
+        self.connection.write_frame(&frame).await?;
+
+        // Wait for the response from the server. On success, the server
+        // responds simply with `OK`. Any other response indicates an error.
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Reads a response frame from the socket.
+    ///
+    /// If an `Error` frame is received, it is converted to `Err`.
+    async fn read_response(&mut self) -> crate::Result<Frame> {
+        let response = self.connection.read_frame().await?;
+
+        debug!(?response);
+
+        match response {
+            // Error frames are converted to `Err`
+            Some(Frame::Error(msg)) => Err(msg.into()),
+            Some(frame) => Ok(frame),
+            None => {
+                // Receiving `None` here indicates the server has closed the
+                // connection without sending a frame. This is unexpected and is
+                // represented as a "connection reset by peer" error.
+                let err = Error::new(ErrorKind::ConnectionReset, "connection reset by server");
+
+                Err(err.into())
+            }
+        }
+    }
+}
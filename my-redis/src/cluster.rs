@@ -0,0 +1,602 @@
+//! Static cluster support.
+//!
+//! When cluster mode is enabled, the key space is divided into
+//! `CLUSTER_SLOTS` hash slots. Every slot is owned by exactly one node of a
+//! static node list that all instances are started with. Commands touching
+//! keys owned by another node are answered with a `MOVED` redirection, and
+//! slots that are being migrated produce `ASK` redirections for keys that have
+//! already left this node.
+
+use crate::db::{DataTypes, Db};
+use crate::Frame;
+
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Number of hash slots the key space is divided into.
+pub const CLUSTER_SLOTS: usize = 16384;
+
+/// Shared handle to the cluster state, mirroring the `Db` type.
+pub type Cluster = Arc<Mutex<ClusterState>>;
+
+/// A single node of the static cluster configuration.
+#[derive(Debug, Clone)]
+pub struct ClusterNode {
+    /// 40 character node id, derived from the node address so that every
+    /// instance started with the same node list agrees on the ids.
+    pub id: String,
+    pub host: String,
+    pub port: u16,
+    /// Inclusive slot ranges served by this node.
+    pub slots: Vec<(u16, u16)>,
+}
+
+/// Slot ownership and migration state of the whole cluster, as seen by this
+/// node.
+#[derive(Debug)]
+pub struct ClusterState {
+    nodes: Vec<ClusterNode>,
+    /// Index into `nodes` of the node this process is running as.
+    myself: usize,
+    /// Owner of every slot, as an index into `nodes`.
+    slot_owner: Vec<usize>,
+    /// Slots owned by this node that are being moved to another node.
+    migrating: HashMap<u16, usize>,
+    /// Slots owned by another node that are being moved to this node.
+    importing: HashMap<u16, usize>,
+}
+
+impl ClusterNode {
+    /// Returns the `host:port` address of the node
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+impl ClusterState {
+    /// Build the cluster state from a comma separated node list.
+    ///
+    /// Each entry has the form `host:port[@start-end[/start-end...]]`. When no
+    /// entry specifies slot ranges, the slots are split evenly between the
+    /// nodes in the order they are listed. The entry describing this process
+    /// is the one listening on `port`; when several entries share the port,
+    /// the one whose host is `bind` is picked.
+    ///
+    /// ```text
+    /// 127.0.0.1:7000,127.0.0.1:7001,127.0.0.1:7002
+    /// 127.0.0.1:7000@0-9999,127.0.0.1:7001@10000-16383
+    /// ```
+    pub fn from_spec(spec: &str, bind: &str, port: u16) -> crate::Result<ClusterState> {
+        let mut nodes = Vec::new();
+
+        for entry in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (addr, ranges) = match entry.split_once('@') {
+                Some((addr, ranges)) => (addr, Some(ranges)),
+                None => (entry, None),
+            };
+            let (host, port) = addr
+                .rsplit_once(':')
+                .ok_or_else(|| format!("invalid cluster node address `{}`", addr))?;
+            let port = port
+                .parse::<u16>()
+                .map_err(|_| format!("invalid cluster node port `{}`", port))?;
+
+            let mut slots = Vec::new();
+            for range in ranges.into_iter().flat_map(|r| r.split('/')) {
+                let (start, end) = match range.split_once('-') {
+                    Some((start, end)) => (start, end),
+                    None => (range, range),
+                };
+                let start = parse_slot(start)?;
+                let end = parse_slot(end)?;
+                if start > end {
+                    return Err(format!("invalid slot range `{}`", range).into());
+                }
+                slots.push((start, end));
+            }
+
+            nodes.push(ClusterNode {
+                id: node_id(host, port),
+                host: host.to_string(),
+                port,
+                slots,
+            });
+        }
+
+        if nodes.is_empty() {
+            return Err("cluster node list is empty".into());
+        }
+        let candidates: Vec<usize> = (0..nodes.len()).filter(|&i| nodes[i].port == port).collect();
+        let myself = match &candidates[..] {
+            [myself] => *myself,
+            _ => *candidates
+                .iter()
+                .find(|&&i| nodes[i].host == bind)
+                .ok_or_else(|| format!("no cluster node entry for {}:{}", bind, port))?,
+        };
+
+        // Without explicit ranges, split the slots evenly.
+        if nodes.iter().all(|node| node.slots.is_empty()) {
+            let count = nodes.len();
+            for (i, node) in nodes.iter_mut().enumerate() {
+                let start = CLUSTER_SLOTS * i / count;
+                let end = CLUSTER_SLOTS * (i + 1) / count - 1;
+                node.slots.push((start as u16, end as u16));
+            }
+        }
+
+        let mut slot_owner = vec![usize::MAX; CLUSTER_SLOTS];
+        for (i, node) in nodes.iter().enumerate() {
+            for &(start, end) in &node.slots {
+                for slot in start..=end {
+                    if slot_owner[slot as usize] != usize::MAX {
+                        return Err(format!("slot {} is assigned to more than one node", slot).into());
+                    }
+                    slot_owner[slot as usize] = i;
+                }
+            }
+        }
+        if let Some(slot) = slot_owner.iter().position(|&owner| owner == usize::MAX) {
+            return Err(format!("slot {} is not assigned to any node", slot).into());
+        }
+
+        Ok(ClusterState {
+            nodes,
+            myself,
+            slot_owner,
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+        })
+    }
+
+    /// The node this process is running as
+    pub fn myself(&self) -> &ClusterNode {
+        &self.nodes[self.myself]
+    }
+
+    /// All nodes of the cluster
+    pub fn nodes(&self) -> &[ClusterNode] {
+        &self.nodes
+    }
+
+    /// The node owning `slot`
+    pub fn slot_owner(&self, slot: u16) -> &ClusterNode {
+        &self.nodes[self.slot_owner[slot as usize]]
+    }
+
+    fn node_index(&self, id: &str) -> crate::Result<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.id == id)
+            .ok_or_else(|| format!("ERR I don't know about node {}", id).into())
+    }
+
+    /// Decide whether a command touching `keys` may run on this node.
+    ///
+    /// `asking` is set when the client sent `ASKING` right before the
+    /// command. `has_key` reports whether a key currently exists locally; it
+    /// is only consulted for slots that are being migrated away.
+    ///
+    /// Returns the redirection or error frame to reply with when the command
+    /// must not run here.
+    pub fn route(&self, keys: &[&str], asking: bool, has_key: impl Fn(&str) -> bool) -> Result<(), Frame> {
+        let slot = match keys.split_first() {
+            None => return Ok(()),
+            Some((first, rest)) => {
+                let slot = key_hash_slot(first.as_bytes());
+                if rest.iter().any(|key| key_hash_slot(key.as_bytes()) != slot) {
+                    return Err(Frame::Error(
+                        "CROSSSLOT Keys in request don't hash to the same slot".to_string(),
+                    ));
+                }
+                slot
+            }
+        };
+
+        if self.slot_owner[slot as usize] == self.myself {
+            if let Some(&target) = self.migrating.get(&slot) {
+                let missing = keys.iter().filter(|key| !has_key(key)).count();
+                if missing == keys.len() {
+                    return Err(Frame::Error(format!("ASK {} {}", slot, self.nodes[target].addr())));
+                } else if missing > 0 {
+                    return Err(Frame::Error(
+                        "TRYAGAIN Multiple keys request during rehashing of slot".to_string(),
+                    ));
+                }
+            }
+            return Ok(());
+        }
+
+        if asking && self.importing.contains_key(&slot) {
+            return Ok(());
+        }
+
+        Err(Frame::Error(format!("MOVED {} {}", slot, self.slot_owner(slot).addr())))
+    }
+
+    /// Mark `slot` as being migrated from this node to the node `id`.
+    pub fn set_migrating(&mut self, slot: u16, id: &str) -> crate::Result<()> {
+        let target = self.node_index(id)?;
+        if self.slot_owner[slot as usize] != self.myself {
+            return Err(format!("ERR I'm not the owner of hash slot {}", slot).into());
+        }
+        self.migrating.insert(slot, target);
+        Ok(())
+    }
+
+    /// Mark `slot` as being imported into this node from the node `id`.
+    pub fn set_importing(&mut self, slot: u16, id: &str) -> crate::Result<()> {
+        let source = self.node_index(id)?;
+        if self.slot_owner[slot as usize] == self.myself {
+            return Err(format!("ERR I'm already the owner of hash slot {}", slot).into());
+        }
+        self.importing.insert(slot, source);
+        Ok(())
+    }
+
+    /// Clear any migration state of `slot`.
+    pub fn set_stable(&mut self, slot: u16) {
+        self.migrating.remove(&slot);
+        self.importing.remove(&slot);
+    }
+
+    /// Assign `slot` to the node `id`, ending any migration of that slot.
+    pub fn set_node(&mut self, slot: u16, id: &str) -> crate::Result<()> {
+        let owner = self.node_index(id)?;
+        self.slot_owner[slot as usize] = owner;
+        self.set_stable(slot);
+        self.rebuild_ranges();
+        Ok(())
+    }
+
+    /// Recompute the per-node slot ranges from `slot_owner`.
+    fn rebuild_ranges(&mut self) {
+        for node in self.nodes.iter_mut() {
+            node.slots.clear();
+        }
+        let mut start = 0;
+        for slot in 1..=CLUSTER_SLOTS {
+            if slot == CLUSTER_SLOTS || self.slot_owner[slot] != self.slot_owner[start] {
+                let owner = self.slot_owner[start];
+                self.nodes[owner].slots.push((start as u16, (slot - 1) as u16));
+                start = slot;
+            }
+        }
+    }
+
+    /// Reply to `CLUSTER SLOTS`.
+    pub fn slots_frame(&self) -> Frame {
+        let mut ranges: Vec<(u16, u16, &ClusterNode)> = self
+            .nodes
+            .iter()
+            .flat_map(|node| node.slots.iter().map(move |&(start, end)| (start, end, node)))
+            .collect();
+        ranges.sort_by_key(|&(start, _, _)| start);
+
+        let mut frame = Frame::array();
+        for (start, end, node) in ranges {
+            let mut entry = Frame::array();
+            entry.push_int(start as i64);
+            entry.push_int(end as i64);
+            let mut master = Frame::array();
+            master.push_bulk(Bytes::from(node.host.clone()));
+            master.push_int(node.port as i64);
+            master.push_bulk(Bytes::from(node.id.clone()));
+            entry.push_frame(master);
+            frame.push_frame(entry);
+        }
+        frame
+    }
+
+    /// Reply to `CLUSTER SHARDS`.
+    pub fn shards_frame(&self) -> Frame {
+        let mut frame = Frame::array();
+        for node in &self.nodes {
+            let mut slots = Frame::array();
+            for &(start, end) in &node.slots {
+                slots.push_int(start as i64);
+                slots.push_int(end as i64);
+            }
+
+            let mut details = Frame::array();
+            details.push_bulk(Bytes::from("id"));
+            details.push_bulk(Bytes::from(node.id.clone()));
+            details.push_bulk(Bytes::from("port"));
+            details.push_int(node.port as i64);
+            details.push_bulk(Bytes::from("ip"));
+            details.push_bulk(Bytes::from(node.host.clone()));
+            details.push_bulk(Bytes::from("endpoint"));
+            details.push_bulk(Bytes::from(node.host.clone()));
+            details.push_bulk(Bytes::from("role"));
+            details.push_bulk(Bytes::from("master"));
+            details.push_bulk(Bytes::from("replication-offset"));
+            details.push_int(0);
+            details.push_bulk(Bytes::from("health"));
+            details.push_bulk(Bytes::from("online"));
+
+            let mut node_list = Frame::array();
+            node_list.push_frame(details);
+
+            let mut shard = Frame::array();
+            shard.push_bulk(Bytes::from("slots"));
+            shard.push_frame(slots);
+            shard.push_bulk(Bytes::from("nodes"));
+            shard.push_frame(node_list);
+            frame.push_frame(shard);
+        }
+        frame
+    }
+
+    /// Reply to `CLUSTER NODES`.
+    pub fn nodes_frame(&self) -> Frame {
+        let mut out = String::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            let flags = if i == self.myself { "myself,master" } else { "master" };
+            out.push_str(&format!(
+                "{} {}@{} {} - 0 0 {} connected",
+                node.id,
+                node.addr(),
+                node.port as u32 + 10000,
+                flags,
+                i + 1
+            ));
+            for &(start, end) in &node.slots {
+                if start == end {
+                    out.push_str(&format!(" {}", start));
+                } else {
+                    out.push_str(&format!(" {}-{}", start, end));
+                }
+            }
+            if i == self.myself {
+                for (slot, target) in &self.migrating {
+                    out.push_str(&format!(" [{}->-{}]", slot, self.nodes[*target].id));
+                }
+                for (slot, source) in &self.importing {
+                    out.push_str(&format!(" [{}-<-{}]", slot, self.nodes[*source].id));
+                }
+            }
+            out.push('\n');
+        }
+        Frame::Bulk(Bytes::from(out))
+    }
+
+    /// Reply to `CLUSTER INFO`.
+    pub fn info_frame(&self) -> Frame {
+        let info = format!(
+            "cluster_enabled:1\r\n\
+             cluster_state:ok\r\n\
+             cluster_slots_assigned:{slots}\r\n\
+             cluster_slots_ok:{slots}\r\n\
+             cluster_slots_pfail:0\r\n\
+             cluster_slots_fail:0\r\n\
+             cluster_known_nodes:{nodes}\r\n\
+             cluster_size:{nodes}\r\n\
+             cluster_current_epoch:{nodes}\r\n\
+             cluster_my_epoch:{epoch}\r\n",
+            slots = CLUSTER_SLOTS,
+            nodes = self.nodes.len(),
+            epoch = self.myself + 1,
+        );
+        Frame::Bulk(Bytes::from(info))
+    }
+}
+
+/// Returns the keys stored in `db` that hash to `slot`.
+///
+/// Waiter registrations of blocked clients are not keys and are skipped.
+pub fn keys_in_slot(db: &Db, slot: u16) -> Vec<String> {
+    db.lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, value)| !matches!(value, DataTypes::SenderList(_)))
+        .map(|(key, _)| key)
+        .filter(|key| key_hash_slot(key.as_bytes()) == slot)
+        .cloned()
+        .collect()
+}
+
+/// Compute the hash slot of `key`.
+///
+/// If the key contains a `{...}` section with at least one character between
+/// the braces, only that hash tag is hashed, which lets related keys be
+/// forced into the same slot.
+pub fn key_hash_slot(key: &[u8]) -> u16 {
+    let hashed = match key.iter().position(|&b| b == b'{') {
+        Some(open) => match key[open + 1..].iter().position(|&b| b == b'}') {
+            Some(len) if len > 0 => &key[open + 1..open + 1 + len],
+            _ => key,
+        },
+        None => key,
+    };
+    crc16(hashed) & (CLUSTER_SLOTS as u16 - 1)
+}
+
+/// Parse a slot number, checking it is in range.
+pub fn parse_slot(src: &str) -> crate::Result<u16> {
+    match src.trim().parse::<u16>() {
+        Ok(slot) if (slot as usize) < CLUSTER_SLOTS => Ok(slot),
+        _ => Err(format!("ERR Invalid or out of range slot `{}`", src).into()),
+    }
+}
+
+/// CRC16 (XMODEM variant), as used by Redis Cluster for key hashing.
+fn crc16(buf: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in buf {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Derive a stable 40 character hex id from the node address.
+fn node_id(host: &str, port: u16) -> String {
+    let addr = format!("{}:{}", host, port);
+    let mut id = String::with_capacity(40);
+    let mut seed: u64 = 0xcbf2_9ce4_8422_2325;
+    while id.len() < 40 {
+        // FNV-1a, chained so every round depends on the previous one.
+        let mut hash = seed;
+        for &byte in addr.as_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        // splitmix64 finalizer, so similar addresses give unrelated ids.
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+        id.push_str(&format!("{:016x}", hash));
+        seed = hash;
+    }
+    id.truncate(40);
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two nodes, this process being the first one, serving slots 0-8191.
+    fn two_nodes() -> ClusterState {
+        ClusterState::from_spec("127.0.0.1:7000,127.0.0.1:7001", "127.0.0.1", 7000).unwrap()
+    }
+
+    fn error(result: Result<(), Frame>) -> String {
+        match result {
+            Err(Frame::Error(msg)) => msg,
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
+    /// A key of `state` hashing to a slot owned by the node on `port`.
+    fn key_on(state: &ClusterState, port: u16) -> String {
+        (0..)
+            .map(|i| format!("key:{}", i))
+            .find(|key| state.slot_owner(key_hash_slot(key.as_bytes())).port == port)
+            .unwrap()
+    }
+
+    #[test]
+    fn crc16_xmodem() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(crc16(b""), 0);
+    }
+
+    #[test]
+    fn known_slots() {
+        assert_eq!(key_hash_slot(b"foo"), 12182);
+        assert_eq!(key_hash_slot(b"somekey"), 11058);
+    }
+
+    #[test]
+    fn hash_tags() {
+        let user = key_hash_slot(b"user1000");
+        assert_eq!(key_hash_slot(b"{user1000}.following"), user);
+        assert_eq!(key_hash_slot(b"{user1000}.followers"), user);
+
+        // Only the first tag counts, and only up to the first `}`.
+        assert_eq!(key_hash_slot(b"foo{bar}{zap}"), key_hash_slot(b"bar"));
+        assert_eq!(key_hash_slot(b"foo{{bar}}zap"), key_hash_slot(b"{bar"));
+
+        // An empty tag, or no closing brace, hashes the whole key.
+        assert_eq!(key_hash_slot(b"foo{}{bar}"), crc16(b"foo{}{bar}") & 16383);
+        assert_eq!(key_hash_slot(b"{}"), crc16(b"{}") & 16383);
+        assert_eq!(key_hash_slot(b"foo{bar"), crc16(b"foo{bar") & 16383);
+    }
+
+    #[test]
+    fn slots_split_evenly() {
+        let state = two_nodes();
+        assert_eq!(state.myself().port, 7000);
+        assert_eq!(state.nodes()[0].slots, vec![(0, 8191)]);
+        assert_eq!(state.nodes()[1].slots, vec![(8192, 16383)]);
+    }
+
+    #[test]
+    fn spec_errors() {
+        assert!(ClusterState::from_spec("", "127.0.0.1", 7000).is_err());
+        assert!(ClusterState::from_spec("127.0.0.1:7001", "127.0.0.1", 7000).is_err());
+        assert!(ClusterState::from_spec("127.0.0.1:7000@0-100", "127.0.0.1", 7000).is_err());
+        assert!(ClusterState::from_spec("127.0.0.1:7000@0-9000,127.0.0.1:7001@9000-16383", "127.0.0.1", 7000).is_err());
+        assert!(ClusterState::from_spec("127.0.0.1:7000@0-16384", "127.0.0.1", 7000).is_err());
+        assert!(ClusterState::from_spec("127.0.0.1:7000@0-9999,127.0.0.1:7001@10000-16383", "127.0.0.1", 7001).is_ok());
+    }
+
+    #[test]
+    fn route_local_and_moved() {
+        let state = two_nodes();
+        let local = key_on(&state, 7000);
+        let remote = key_on(&state, 7001);
+
+        assert!(state.route(&[], false, |_| true).is_ok());
+        assert!(state.route(&[&local], false, |_| true).is_ok());
+
+        let slot = key_hash_slot(remote.as_bytes());
+        assert_eq!(error(state.route(&[&remote], false, |_| true)), format!("MOVED {} 127.0.0.1:7001", slot));
+        // ASKING alone doesn't let a client use a slot that isn't imported.
+        assert_eq!(error(state.route(&[&remote], true, |_| true)), format!("MOVED {} 127.0.0.1:7001", slot));
+    }
+
+    #[test]
+    fn route_crossslot() {
+        let state = two_nodes();
+        let msg = error(state.route(&["foo", "bar"], false, |_| true));
+        assert!(msg.starts_with("CROSSSLOT"), "{}", msg);
+
+        // Keys sharing a hash tag are in the same slot. A single node serves
+        // every slot, so only CROSSSLOT could refuse them.
+        let state = ClusterState::from_spec("127.0.0.1:7000", "127.0.0.1", 7000).unwrap();
+        assert!(state.route(&["{user1000}.following", "{user1000}.followers"], false, |_| true).is_ok());
+    }
+
+    #[test]
+    fn route_while_migrating() {
+        let mut state = two_nodes();
+        let key = key_on(&state, 7000);
+        let slot = key_hash_slot(key.as_bytes());
+        let other = format!("{{{}}}.other", key);
+        let target = state.nodes()[1].id.clone();
+        state.set_migrating(slot, &target).unwrap();
+
+        // Keys still here are served, the ones that left are asked for on the
+        // target node.
+        assert!(state.route(&[&key], false, |_| true).is_ok());
+        assert_eq!(error(state.route(&[&key], false, |_| false)), format!("ASK {} 127.0.0.1:7001", slot));
+
+        // Some keys here and some gone can't be served by either node.
+        let msg = error(state.route(&[&key, &other], false, |k| k == key));
+        assert!(msg.starts_with("TRYAGAIN"), "{}", msg);
+
+        state.set_stable(slot);
+        assert!(state.route(&[&key], false, |_| false).is_ok());
+
+        // Slots not owned can't be migrated away.
+        let remote = key_hash_slot(key_on(&state, 7001).as_bytes());
+        assert!(state.set_migrating(remote, &target).is_err());
+    }
+
+    #[test]
+    fn route_while_importing() {
+        let mut state = two_nodes();
+        let key = key_on(&state, 7001);
+        let slot = key_hash_slot(key.as_bytes());
+        let source = state.nodes()[1].id.clone();
+        state.set_importing(slot, &source).unwrap();
+
+        // Only clients that sent ASKING are served.
+        assert!(state.route(&[&key], true, |_| false).is_ok());
+        assert!(error(state.route(&[&key], false, |_| false)).starts_with("MOVED"));
+
+        // Once the slot is assigned here, everybody is served.
+        let myself = state.myself().id.clone();
+        state.set_node(slot, &myself).unwrap();
+        assert!(state.route(&[&key], false, |_| false).is_ok());
+        assert!(state.myself().slots.iter().any(|&(start, end)| start <= slot && slot <= end));
+
+        assert!(state.set_importing(slot, &source).is_err());
+        assert!(state.set_importing(slot, "unknown").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use crate::Parse;
+
+/// Manage the users and their permissions.
+#[derive(Debug, Clone)]
+pub struct Acl {
+    subcommand: AclSubcommand,
+}
+
+/// The supported `ACL` subcommands.
+#[derive(Debug, Clone)]
+pub enum AclSubcommand {
+    /// User name and the rules to apply to it
+    Setuser(String, Vec<String>),
+    Getuser(String),
+    Deluser(Vec<String>),
+    List,
+    Users,
+    Whoami,
+    /// List the categories, or the commands of a category
+    Cat(Option<String>),
+    /// Number of entries to return
+    Log(usize),
+    LogReset,
+}
+
+/// Entries returned by `ACL LOG` without a count.
+const DEFAULT_LOG_COUNT: usize = 10;
+
+impl Acl {
+    pub fn new(subcommand: AclSubcommand) -> Acl {
+        Acl { subcommand }
+    }
+
+    /// Get the subcommand
+    pub fn subcommand(&self) -> &AclSubcommand {
+        &self.subcommand
+    }
+
+    /// Parse an `Acl` instance from a received frame.
+    ///
+    /// The `ACL` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// ACL SETUSER username [rule [rule ...]]
+    /// ACL GETUSER username
+    /// ACL DELUSER username [username ...]
+    /// ACL LIST
+    /// ACL USERS
+    /// ACL WHOAMI
+    /// ACL CAT [category]
+    /// ACL LOG [count | RESET]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Acl> {
+        let subcommand = match &parse.next_string()?.to_lowercase()[..] {
+            "setuser" => {
+                let name = parse.next_string()?;
+                let mut rules = Vec::new();
+                while let Ok(rule) = parse.next_string() {
+                    rules.push(rule);
+                }
+                AclSubcommand::Setuser(name, rules)
+            }
+            "getuser" => AclSubcommand::Getuser(parse.next_string()?),
+            "deluser" => {
+                let mut names = vec![parse.next_string()?];
+                while let Ok(name) = parse.next_string() {
+                    names.push(name);
+                }
+                AclSubcommand::Deluser(names)
+            }
+            "list" => AclSubcommand::List,
+            "users" => AclSubcommand::Users,
+            "whoami" => AclSubcommand::Whoami,
+            "cat" => AclSubcommand::Cat(parse.next_string().ok()),
+            "log" => match parse.next_string() {
+                Ok(arg) if arg.eq_ignore_ascii_case("reset") => AclSubcommand::LogReset,
+                Ok(arg) => AclSubcommand::Log(
+                    arg.parse()
+                        .map_err(|_| "ERR value is out of range, must be positive")?,
+                ),
+                Err(_) => AclSubcommand::Log(DEFAULT_LOG_COUNT),
+            },
+            other => return Err(format!("ERR Unknown ACL subcommand `{}`", other).into()),
+        };
+
+        Ok(Acl { subcommand })
+    }
+}
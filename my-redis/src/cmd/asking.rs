@@ -0,0 +1,17 @@
+use crate::Parse;
+
+/// Allow the next command to access a slot that is being imported into this
+/// node.
+///
+/// Sent by cluster clients after receiving an `ASK` redirection.
+#[derive(Debug, Clone)]
+pub struct Asking;
+
+impl Asking {
+    /// Parse an `Asking` instance from a received frame.
+    ///
+    /// The `ASKING` string has already been consumed and no arguments follow.
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Asking> {
+        Ok(Asking)
+    }
+}
@@ -0,0 +1,42 @@
+use crate::Parse;
+
+/// Authenticate the connection as a user.
+#[derive(Debug, Clone)]
+pub struct Auth {
+    /// `None` when only a password is given, authenticating as `default`
+    username: Option<String>,
+    password: String,
+}
+
+impl Auth {
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    /// Parse an `Auth` instance from a received frame.
+    ///
+    /// The `AUTH` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// AUTH [username] password
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Auth> {
+        let first = parse.next_string()?;
+        match parse.next_string() {
+            Ok(password) => Ok(Auth {
+                username: Some(first),
+                password,
+            }),
+            Err(_) => Ok(Auth {
+                username: None,
+                password: first,
+            }),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::registry::Unblock;
+use crate::Parse;
+
+use std::time::Duration;
+
+/// Inspect and manage client connections.
+#[derive(Debug, Clone)]
+pub struct Client {
+    subcommand: ClientSubcommand,
+}
+
+/// The supported `CLIENT` subcommands.
+#[derive(Debug, Clone)]
+pub enum ClientSubcommand {
+    /// Only list the clients with these ids, all of them when empty
+    List(Vec<u64>),
+    Info,
+    Setname(String),
+    Getname,
+    Id,
+    Kill(ClientKill),
+    /// Pause duration and whether only writes are paused
+    Pause(Duration, bool),
+    Unpause,
+    NoEvict(bool),
+    /// Client to release from a blocking command, and how
+    Unblock(u64, Unblock),
+}
+
+/// Which clients `CLIENT KILL` closes.
+#[derive(Debug, Clone, Default)]
+pub struct ClientKill {
+    /// Set when called with a single address, as in older Redis versions
+    pub legacy: bool,
+    pub id: Option<u64>,
+    pub addr: Option<String>,
+    pub laddr: Option<String>,
+    pub user: Option<String>,
+    /// Whether the calling client is spared, `yes` by default
+    pub skipme: bool,
+}
+
+impl Client {
+    pub fn new(subcommand: ClientSubcommand) -> Client {
+        Client { subcommand }
+    }
+
+    /// Get the subcommand
+    pub fn subcommand(&self) -> &ClientSubcommand {
+        &self.subcommand
+    }
+
+    /// Parse a `Client` instance from a received frame.
+    ///
+    /// The `CLIENT` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// CLIENT LIST [ID client-id [client-id ...]]
+    /// CLIENT INFO
+    /// CLIENT SETNAME connection-name
+    /// CLIENT GETNAME
+    /// CLIENT ID
+    /// CLIENT KILL ip:port
+    /// CLIENT KILL [ID client-id] [ADDR ip:port] [LADDR ip:port] [USER username] [SKIPME yes/no]
+    /// CLIENT PAUSE timeout [WRITE | ALL]
+    /// CLIENT UNPAUSE
+    /// CLIENT NO-EVICT ON|OFF
+    /// CLIENT UNBLOCK client-id [TIMEOUT | ERROR]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Client> {
+        let subcommand = match &parse.next_string()?.to_lowercase()[..] {
+            "list" => {
+                let mut ids = Vec::new();
+                if let Ok(option) = parse.next_string() {
+                    if !option.eq_ignore_ascii_case("id") {
+                        return Err("ERR syntax error".into());
+                    }
+                    while let Ok(id) = parse.next_string() {
+                        ids.push(id.parse().map_err(|_| "ERR Invalid client ID")?);
+                    }
+                    if ids.is_empty() {
+                        return Err("ERR syntax error".into());
+                    }
+                }
+                ClientSubcommand::List(ids)
+            }
+            "info" => ClientSubcommand::Info,
+            "setname" => {
+                let name = parse.next_string()?;
+                if name.bytes().any(|b| !(b'!'..=b'~').contains(&b)) {
+                    return Err("ERR Client names cannot contain spaces, newlines or special characters.".into());
+                }
+                ClientSubcommand::Setname(name)
+            }
+            "getname" => ClientSubcommand::Getname,
+            "id" => ClientSubcommand::Id,
+            "kill" => ClientSubcommand::Kill(parse_kill(parse)?),
+            "pause" => {
+                let timeout = parse
+                    .next_string()?
+                    .parse::<u64>()
+                    .map_err(|_| "ERR timeout is not an integer or out of range")?;
+                let write_only = match parse.next_string() {
+                    Ok(mode) => match &mode.to_lowercase()[..] {
+                        "write" => true,
+                        "all" => false,
+                        _ => return Err("ERR syntax error".into()),
+                    },
+                    Err(_) => false,
+                };
+                ClientSubcommand::Pause(Duration::from_millis(timeout), write_only)
+            }
+            "unpause" => ClientSubcommand::Unpause,
+            "no-evict" => match &parse.next_string()?.to_lowercase()[..] {
+                "on" => ClientSubcommand::NoEvict(true),
+                "off" => ClientSubcommand::NoEvict(false),
+                _ => return Err("ERR syntax error".into()),
+            },
+            "unblock" => {
+                let id = parse
+                    .next_string()?
+                    .parse()
+                    .map_err(|_| "ERR value is not an integer or out of range")?;
+                let how = match parse.next_string() {
+                    Ok(how) => match &how.to_lowercase()[..] {
+                        "timeout" => Unblock::Timeout,
+                        "error" => Unblock::Error,
+                        _ => return Err("ERR CLIENT UNBLOCK reason should be TIMEOUT or ERROR".into()),
+                    },
+                    Err(_) => Unblock::Timeout,
+                };
+                ClientSubcommand::Unblock(id, how)
+            }
+            other => return Err(format!("ERR Unknown CLIENT subcommand `{}`", other).into()),
+        };
+
+        Ok(Client { subcommand })
+    }
+}
+
+fn parse_kill(parse: &mut Parse) -> crate::Result<ClientKill> {
+    let mut kill = ClientKill {
+        skipme: true,
+        ..ClientKill::default()
+    };
+
+    let first = parse.next_string()?;
+    let mut option = first.clone();
+    let mut value = match parse.next_string() {
+        Ok(value) => value,
+        Err(_) => {
+            // `CLIENT KILL ip:port`
+            kill.legacy = true;
+            kill.addr = Some(first);
+            return Ok(kill);
+        }
+    };
+
+    loop {
+        match &option.to_lowercase()[..] {
+            "id" => kill.id = Some(value.parse().map_err(|_| "ERR client-id should be greater than 0")?),
+            "addr" => kill.addr = Some(value),
+            "laddr" => kill.laddr = Some(value),
+            "user" => kill.user = Some(value),
+            "skipme" => {
+                kill.skipme = match &value.to_lowercase()[..] {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err("ERR syntax error".into()),
+                }
+            }
+            _ => return Err("ERR syntax error".into()),
+        }
+
+        option = match parse.next_string() {
+            Ok(option) => option,
+            Err(_) => break,
+        };
+        value = parse.next_string().map_err(|_| "ERR syntax error")?;
+    }
+
+    Ok(kill)
+}
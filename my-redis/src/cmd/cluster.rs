@@ -0,0 +1,89 @@
+use crate::cluster::parse_slot;
+use crate::Parse;
+
+/// Inspect or change the cluster state.
+///
+/// Only available when the server runs in cluster mode.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    subcommand: ClusterSubcommand,
+}
+
+/// The supported `CLUSTER` subcommands.
+#[derive(Debug, Clone)]
+pub enum ClusterSubcommand {
+    Info,
+    Myid,
+    Nodes,
+    Slots,
+    Shards,
+    Keyslot(String),
+    Countkeysinslot(u16),
+    Getkeysinslot(u16, u64),
+    Setslot(u16, SetslotAction),
+}
+
+/// State change requested by `CLUSTER SETSLOT`.
+#[derive(Debug, Clone)]
+pub enum SetslotAction {
+    Migrating(String),
+    Importing(String),
+    Stable,
+    Node(String),
+}
+
+impl Cluster {
+    pub fn new(subcommand: ClusterSubcommand) -> Cluster {
+        Cluster { subcommand }
+    }
+
+    /// Get the subcommand
+    pub fn subcommand(&self) -> &ClusterSubcommand {
+        &self.subcommand
+    }
+
+    /// Parse a `Cluster` instance from a received frame.
+    ///
+    /// The `CLUSTER` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// CLUSTER INFO | MYID | NODES | SLOTS | SHARDS
+    /// CLUSTER KEYSLOT key
+    /// CLUSTER COUNTKEYSINSLOT slot
+    /// CLUSTER GETKEYSINSLOT slot count
+    /// CLUSTER SETSLOT slot MIGRATING|IMPORTING|NODE node-id
+    /// CLUSTER SETSLOT slot STABLE
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Cluster> {
+        let subcommand = match &parse.next_string()?.to_lowercase()[..] {
+            "info" => ClusterSubcommand::Info,
+            "myid" => ClusterSubcommand::Myid,
+            "nodes" => ClusterSubcommand::Nodes,
+            "slots" => ClusterSubcommand::Slots,
+            "shards" => ClusterSubcommand::Shards,
+            "keyslot" => ClusterSubcommand::Keyslot(parse.next_string()?),
+            "countkeysinslot" => ClusterSubcommand::Countkeysinslot(parse_slot(&parse.next_string()?)?),
+            "getkeysinslot" => {
+                let slot = parse_slot(&parse.next_string()?)?;
+                let count = parse.next_int()?;
+                ClusterSubcommand::Getkeysinslot(slot, count)
+            }
+            "setslot" => {
+                let slot = parse_slot(&parse.next_string()?)?;
+                let action = match &parse.next_string()?.to_lowercase()[..] {
+                    "migrating" => SetslotAction::Migrating(parse.next_string()?),
+                    "importing" => SetslotAction::Importing(parse.next_string()?),
+                    "node" => SetslotAction::Node(parse.next_string()?),
+                    "stable" => SetslotAction::Stable,
+                    other => return Err(format!("ERR Invalid CLUSTER SETSLOT action `{}`", other).into()),
+                };
+                ClusterSubcommand::Setslot(slot, action)
+            }
+            other => return Err(format!("ERR Unknown CLUSTER subcommand `{}`", other).into()),
+        };
+
+        Ok(Cluster { subcommand })
+    }
+}
@@ -0,0 +1,105 @@
+use crate::cmd::ParseError;
+use crate::Parse;
+
+use bytes::Bytes;
+
+/// Introspect the supported commands: their arity, flags, key positions,
+/// ACL categories and documentation.
+#[derive(Debug, Clone)]
+pub struct Commands {
+    subcommand: CommandsSubcommand,
+}
+
+/// The supported `COMMAND` subcommands.
+#[derive(Debug, Clone)]
+pub enum CommandsSubcommand {
+    Count,
+    /// Commands to describe, all of them when empty
+    Info(Vec<String>),
+    /// Commands to document, all of them when empty
+    Docs(Vec<String>),
+    /// A command line to find the keys of
+    Getkeys(Vec<Bytes>),
+    List(Option<ListFilter>),
+}
+
+/// Restricts the names returned by `COMMAND LIST`.
+#[derive(Debug, Clone)]
+pub enum ListFilter {
+    Module(String),
+    Aclcat(String),
+    Pattern(String),
+}
+
+impl Commands {
+    pub fn new(subcommand: CommandsSubcommand) -> Commands {
+        Commands { subcommand }
+    }
+
+    /// Get the subcommand
+    pub fn subcommand(&self) -> &CommandsSubcommand {
+        &self.subcommand
+    }
+
+    /// Parse a `Commands` instance from a received frame.
+    ///
+    /// The `COMMAND` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// COMMAND
+    /// COMMAND COUNT
+    /// COMMAND INFO [command-name [command-name ...]]
+    /// COMMAND DOCS [command-name [command-name ...]]
+    /// COMMAND GETKEYS command [arg [arg ...]]
+    /// COMMAND LIST [FILTERBY MODULE module-name|ACLCAT category|PATTERN pattern]
+    /// ```
+    ///
+    /// Plain `COMMAND` is `COMMAND INFO` without names.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Commands> {
+        let subcommand = match parse.next_string() {
+            Ok(subcommand) => subcommand.to_lowercase(),
+            Err(ParseError::EndOfStream) => return Ok(Commands::new(CommandsSubcommand::Info(vec![]))),
+            Err(e) => return Err(e.into()),
+        };
+        let subcommand = match &subcommand[..] {
+            "count" => CommandsSubcommand::Count,
+            "info" => CommandsSubcommand::Info(rest(parse)),
+            "docs" => CommandsSubcommand::Docs(rest(parse)),
+            "getkeys" => {
+                let mut args = vec![parse.next_bytes()?];
+                while let Ok(arg) = parse.next_bytes() {
+                    args.push(arg);
+                }
+                CommandsSubcommand::Getkeys(args)
+            }
+            "list" => match parse.next_string() {
+                Ok(filterby) if filterby.eq_ignore_ascii_case("filterby") => {
+                    let kind = parse.next_string()?.to_lowercase();
+                    let value = parse.next_string()?;
+                    CommandsSubcommand::List(Some(match &kind[..] {
+                        "module" => ListFilter::Module(value),
+                        "aclcat" => ListFilter::Aclcat(value),
+                        "pattern" => ListFilter::Pattern(value),
+                        _ => return Err("ERR syntax error".into()),
+                    }))
+                }
+                Ok(_) => return Err("ERR syntax error".into()),
+                Err(_) => CommandsSubcommand::List(None),
+            },
+            other => return Err(format!("ERR Unknown COMMAND subcommand `{}`", other).into()),
+        };
+
+        Ok(Commands { subcommand })
+    }
+}
+
+/// Consume the remaining arguments.
+fn rest(parse: &mut Parse) -> Vec<String> {
+    let mut args = Vec::new();
+    while let Ok(arg) = parse.next_string() {
+        args.push(arg);
+    }
+    args
+}
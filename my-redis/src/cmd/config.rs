@@ -0,0 +1,68 @@
+use crate::Parse;
+
+/// Inspect and change the server configuration at runtime.
+#[derive(Debug, Clone)]
+pub struct Config {
+    subcommand: ConfigSubcommand,
+}
+
+/// The supported `CONFIG` subcommands.
+#[derive(Debug, Clone)]
+pub enum ConfigSubcommand {
+    /// Glob patterns of the parameters to return
+    Get(Vec<String>),
+    /// Parameter name and value pairs
+    Set(Vec<(String, String)>),
+    Resetstat,
+    Rewrite,
+}
+
+impl Config {
+    pub fn new(subcommand: ConfigSubcommand) -> Config {
+        Config { subcommand }
+    }
+
+    /// Get the subcommand
+    pub fn subcommand(&self) -> &ConfigSubcommand {
+        &self.subcommand
+    }
+
+    /// Parse a `Config` instance from a received frame.
+    ///
+    /// The `CONFIG` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// CONFIG GET pattern [pattern ...]
+    /// CONFIG SET parameter value [parameter value ...]
+    /// CONFIG RESETSTAT
+    /// CONFIG REWRITE
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Config> {
+        let subcommand = match &parse.next_string()?.to_lowercase()[..] {
+            "get" => {
+                let mut patterns = vec![parse.next_string()?];
+                while let Ok(pattern) = parse.next_string() {
+                    patterns.push(pattern);
+                }
+                ConfigSubcommand::Get(patterns)
+            }
+            "set" => {
+                let mut pairs = vec![(parse.next_string()?, parse.next_string()?)];
+                while let Ok(name) = parse.next_string() {
+                    let value = parse
+                        .next_string()
+                        .map_err(|_| "ERR wrong number of arguments for 'config|set' command")?;
+                    pairs.push((name, value));
+                }
+                ConfigSubcommand::Set(pairs)
+            }
+            "resetstat" => ConfigSubcommand::Resetstat,
+            "rewrite" => ConfigSubcommand::Rewrite,
+            other => return Err(format!("ERR Unknown CONFIG subcommand `{}`", other).into()),
+        };
+
+        Ok(Config { subcommand })
+    }
+}
@@ -0,0 +1,39 @@
+use crate::Parse;
+
+/// Serialize the value stored at key and return it to the user.
+///
+/// The payload can be turned back into a key with `RESTORE`. If the key does
+/// not exist the special value nil is returned.
+#[derive(Debug, Clone)]
+pub struct Dump {
+    /// Name of the key to dump
+    key: String,
+}
+
+impl Dump {
+    /// Create a new `Dump` command which serializes `key`.
+    pub fn new(key: impl ToString) -> Dump {
+        Dump {
+            key: key.to_string(),
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Parse a `Dump` instance from a received frame.
+    ///
+    /// The `DUMP` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// DUMP key
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Dump> {
+        let key = parse.next_string()?;
+        Ok(Dump { key })
+    }
+}
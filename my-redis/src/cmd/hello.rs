@@ -0,0 +1,75 @@
+use crate::Parse;
+
+/// Switch the protocol version of the connection, optionally
+/// authenticating and naming it at the same time.
+#[derive(Debug, Clone)]
+pub struct Hello {
+    /// 2 or 3, `None` to keep the current version
+    protover: Option<u8>,
+    /// Username and password to authenticate with
+    auth: Option<(String, String)>,
+    setname: Option<String>,
+}
+
+impl Hello {
+    pub fn protover(&self) -> Option<u8> {
+        self.protover
+    }
+
+    pub fn auth(&self) -> Option<(&str, &str)> {
+        self.auth.as_ref().map(|(username, password)| (&username[..], &password[..]))
+    }
+
+    pub fn setname(&self) -> Option<&str> {
+        self.setname.as_deref()
+    }
+
+    /// Parse a `Hello` instance from a received frame.
+    ///
+    /// The `HELLO` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// HELLO [protover [AUTH username password] [SETNAME clientname]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Hello> {
+        let mut hello = Hello {
+            protover: None,
+            auth: None,
+            setname: None,
+        };
+        let protover = match parse.next_string() {
+            Ok(protover) => protover,
+            Err(_) => return Ok(hello),
+        };
+        match protover.parse::<u64>() {
+            Ok(version @ 2..=3) => hello.protover = Some(version as u8),
+            Ok(_) => return Err("NOPROTO unsupported protocol version".into()),
+            Err(_) => return Err("ERR Protocol version is not an integer or out of range".into()),
+        }
+
+        while let Ok(option) = parse.next_string() {
+            match &option.to_lowercase()[..] {
+                "auth" => {
+                    let username = parse.next_string();
+                    let password = parse.next_string();
+                    match (username, password) {
+                        (Ok(username), Ok(password)) => hello.auth = Some((username, password)),
+                        _ => return Err("ERR Syntax error in HELLO option 'auth'".into()),
+                    }
+                }
+                "setname" => {
+                    let name = parse.next_string().map_err(|_| "ERR Syntax error in HELLO option 'setname'")?;
+                    if name.bytes().any(|b| !(b'!'..=b'~').contains(&b)) {
+                        return Err("ERR Client names cannot contain spaces, newlines or special characters.".into());
+                    }
+                    hello.setname = Some(name);
+                }
+                _ => return Err(format!("ERR Syntax error in HELLO option '{}'", option).into()),
+            }
+        }
+
+        Ok(hello)
+    }
+}
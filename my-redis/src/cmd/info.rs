@@ -0,0 +1,40 @@
+use crate::Parse;
+
+/// Return information and statistics about the server.
+///
+/// Without arguments the default sections are returned. Sections can be
+/// selected by name, `all` and `everything` return every section.
+#[derive(Debug, Clone, Default)]
+pub struct Info {
+    /// Requested sections, empty for the default set
+    sections: Vec<String>,
+}
+
+impl Info {
+    /// Create a new `Info` command returning the given sections.
+    pub fn new(sections: Vec<String>) -> Info {
+        Info { sections }
+    }
+
+    /// Get the requested sections
+    pub fn sections(&self) -> &[String] {
+        &self.sections
+    }
+
+    /// Parse an `Info` instance from a received frame.
+    ///
+    /// The `INFO` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// INFO [section [section ...]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Info> {
+        let mut sections = Vec::new();
+        while let Ok(section) = parse.next_string() {
+            sections.push(section);
+        }
+        Ok(Info { sections })
+    }
+}
@@ -0,0 +1,67 @@
+use crate::Parse;
+
+/// Inspect latency spikes of internal events and per-command latency
+/// distributions.
+#[derive(Debug, Clone)]
+pub struct Latency {
+    subcommand: LatencySubcommand,
+}
+
+/// The supported `LATENCY` subcommands.
+#[derive(Debug, Clone)]
+pub enum LatencySubcommand {
+    Latest,
+    /// Name of the event
+    History(String),
+    /// Events to reset, all of them when empty
+    Reset(Vec<String>),
+    Doctor,
+    /// Commands to report, all of them when empty
+    Histogram(Vec<String>),
+}
+
+impl Latency {
+    pub fn new(subcommand: LatencySubcommand) -> Latency {
+        Latency { subcommand }
+    }
+
+    /// Get the subcommand
+    pub fn subcommand(&self) -> &LatencySubcommand {
+        &self.subcommand
+    }
+
+    /// Parse a `Latency` instance from a received frame.
+    ///
+    /// The `LATENCY` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// LATENCY LATEST
+    /// LATENCY HISTORY event
+    /// LATENCY RESET [event [event ...]]
+    /// LATENCY DOCTOR
+    /// LATENCY HISTOGRAM [command [command ...]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Latency> {
+        let subcommand = match &parse.next_string()?.to_lowercase()[..] {
+            "latest" => LatencySubcommand::Latest,
+            "history" => LatencySubcommand::History(parse.next_string()?),
+            "reset" => LatencySubcommand::Reset(rest(parse)),
+            "doctor" => LatencySubcommand::Doctor,
+            "histogram" => LatencySubcommand::Histogram(rest(parse).iter().map(|name| name.to_lowercase()).collect()),
+            other => return Err(format!("ERR Unknown LATENCY subcommand `{}`", other).into()),
+        };
+
+        Ok(Latency { subcommand })
+    }
+}
+
+/// Consume the remaining arguments.
+fn rest(parse: &mut Parse) -> Vec<String> {
+    let mut args = Vec::new();
+    while let Ok(arg) = parse.next_string() {
+        args.push(arg);
+    }
+    args
+}
@@ -0,0 +1,253 @@
+use crate::cmd::{ParseError, Restore};
+use crate::db::Db;
+use crate::{dump, Connection, Frame, Parse};
+
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Transfer keys from this instance to a destination instance.
+///
+/// The values are restored on the destination with `RESTORE` and, unless
+/// `COPY` is given, deleted locally once the destination acknowledged them.
+#[derive(Debug, Clone)]
+pub struct Migrate {
+    host: String,
+    port: u16,
+    keys: Vec<String>,
+    destination_db: u64,
+    /// Timeout in milliseconds for every I/O operation with the destination
+    timeout: u64,
+    copy: bool,
+    replace: bool,
+    /// Credentials to authenticate with on the destination, given by `AUTH`
+    /// or `AUTH2`
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl Migrate {
+    /// Get the destination host
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Get the destination port
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Get the keys to migrate
+    pub fn keys(&self) -> &Vec<String> {
+        &self.keys
+    }
+
+    /// Get the database index on the destination
+    pub fn destination_db(&self) -> u64 {
+        self.destination_db
+    }
+
+    /// Get the I/O timeout in milliseconds
+    pub fn timeout(&self) -> u64 {
+        self.timeout
+    }
+
+    /// Whether local keys are kept
+    pub fn copy(&self) -> bool {
+        self.copy
+    }
+
+    /// Whether existing keys on the destination are overwritten
+    pub fn replace(&self) -> bool {
+        self.replace
+    }
+
+    /// Get the user to authenticate as on the destination, `None` for the
+    /// default user
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    /// Get the password to authenticate with on the destination
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+
+    /// Parse a `Migrate` instance from a received frame.
+    ///
+    /// The `MIGRATE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// `key` must be the empty string when `KEYS` is used.
+    ///
+    /// ```text
+    /// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE]
+    ///     [AUTH password | AUTH2 username password] [KEYS key [key ...]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Migrate> {
+        let host = parse.next_string()?;
+        let port = parse.next_string()?
+            .parse::<u16>()
+            .map_err(|_| "ERR Invalid port")?;
+        let key = parse.next_string()?;
+        let destination_db = parse.next_int()?;
+        let timeout = parse.next_int()?;
+
+        let mut copy = false;
+        let mut replace = false;
+        let mut username = None;
+        let mut password = None;
+        let mut keys = Vec::new();
+        loop {
+            match parse.next_string() {
+                Ok(option) => match &option.to_lowercase()[..] {
+                    "copy" => copy = true,
+                    "replace" => replace = true,
+                    "auth" => password = Some(parse.next_string()?),
+                    "auth2" => {
+                        username = Some(parse.next_string()?);
+                        password = Some(parse.next_string()?);
+                    }
+                    "keys" => {
+                        if !key.is_empty() {
+                            return Err("ERR When using MIGRATE KEYS option, the key argument must be set to the empty string".into());
+                        }
+                        while let Ok(key) = parse.next_string() {
+                            keys.push(key);
+                        }
+                        break;
+                    }
+                    _ => return Err(format!("ERR syntax error near `{}`", option).into()),
+                },
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        if !key.is_empty() {
+            keys.push(key);
+        }
+        if keys.is_empty() {
+            return Err("ERR MIGRATE requires a key or the KEYS option".into());
+        }
+
+        Ok(Migrate {
+            host,
+            port,
+            keys,
+            destination_db,
+            timeout,
+            copy,
+            replace,
+            username,
+            password,
+        })
+    }
+
+    /// Apply the `Migrate` command to `db`.
+    ///
+    /// Opens a connection to the destination, restores every existing key
+    /// there and deletes each key locally once the destination acknowledged
+    /// it, unless it was modified in the meantime. When `asking` is set,
+    /// every `RESTORE` is preceded by `ASKING` so that a cluster node
+    /// importing the slot accepts it.
+    ///
+    /// Returns the frame to reply with: `OK`, `NOKEY` when none of the keys
+    /// exist, or an error.
+    pub async fn apply(&self, db: &Db, asking: bool) -> Frame {
+        // Serialize everything up front. Keys that do not exist are skipped.
+        let payloads: Vec<(String, Bytes)> = {
+            let db = db.lock().unwrap();
+            self.keys
+                .iter()
+                .filter_map(|key| {
+                    db.get(key)
+                        .and_then(dump::serialize)
+                        .map(|payload| (key.clone(), payload))
+                })
+                .collect()
+        };
+        if payloads.is_empty() {
+            return Frame::Simple("NOKEY".to_string());
+        }
+
+        match self.transfer(db, payloads, asking).await {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+
+    async fn transfer(&self, db: &Db, payloads: Vec<(String, Bytes)>, asking: bool) -> crate::Result<()> {
+        // Redis treats a non-positive timeout as one second.
+        let io_timeout = Duration::from_millis(if self.timeout == 0 { 1000 } else { self.timeout });
+        let addr = format!("{}:{}", self.host, self.port);
+
+        let socket = match timeout(io_timeout, TcpStream::connect(&addr)).await {
+            Ok(Ok(socket)) => socket,
+            Ok(Err(e)) => return Err(format!("IOERR error connecting to {}: {}", addr, e).into()),
+            Err(_) => return Err(format!("IOERR timeout connecting to {}", addr).into()),
+        };
+        let mut connection = Connection::new(socket);
+
+        if let Some(password) = &self.password {
+            let mut auth = Frame::array();
+            auth.push_bulk(Bytes::from("auth".as_bytes()));
+            if let Some(username) = &self.username {
+                auth.push_bulk(Bytes::from(username.clone()));
+            }
+            auth.push_bulk(Bytes::from(password.clone()));
+            round_trip(&mut connection, &auth, io_timeout).await?;
+        }
+
+        if self.destination_db != 0 {
+            let mut select = Frame::array();
+            select.push_bulk(Bytes::from("select".as_bytes()));
+            select.push_bulk(Bytes::from(self.destination_db.to_string()));
+            round_trip(&mut connection, &select, io_timeout).await?;
+        }
+
+        for (key, payload) in payloads {
+            if asking {
+                let mut frame = Frame::array();
+                frame.push_bulk(Bytes::from("asking".as_bytes()));
+                round_trip(&mut connection, &frame, io_timeout).await?;
+            }
+
+            let restore = Restore::new(&key, 0, payload.clone(), self.replace).into_frame();
+            round_trip(&mut connection, &restore, io_timeout).await?;
+
+            // The destination owns the key now, unless it was written to
+            // while it was being transferred: only the value that was sent
+            // is deleted. Clients blocked on the key are not values, they
+            // don't serialize and are left alone.
+            if !self.copy {
+                let mut db = db.lock().unwrap();
+                if db.get(&key).and_then(dump::serialize).as_ref() == Some(&payload) {
+                    db.remove(&key);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Send `frame` to the destination and wait for its reply, failing on error
+/// replies, a closed connection or when `io_timeout` elapses.
+async fn round_trip(connection: &mut Connection, frame: &Frame, io_timeout: Duration) -> crate::Result<Frame> {
+    let reply = timeout(io_timeout, async {
+        connection.write_frame(frame).await?;
+        connection.read_frame().await
+    })
+    .await;
+
+    match reply {
+        Ok(Ok(Some(Frame::Error(msg)))) => Err(format!("ERR Target instance replied with error: {}", msg).into()),
+        Ok(Ok(Some(frame))) => Ok(frame),
+        Ok(Ok(None)) => Err("IOERR connection closed by target instance".into()),
+        Ok(Err(e)) => Err(format!("IOERR error communicating with target instance: {}", e).into()),
+        Err(_) => Err("IOERR error or timeout reading from target instance".into()),
+    }
+}
@@ -28,6 +28,60 @@ pub use blpop::Blpop;
 
 pub use exists::Exists;
 
+mod cluster;
+pub use cluster::{Cluster, ClusterSubcommand, SetslotAction};
+
+mod asking;
+pub use asking::Asking;
+
+mod migrate;
+pub use migrate::Migrate;
+
+mod restore;
+pub use restore::Restore;
+
+mod dump;
+pub use dump::Dump;
+
+mod save;
+pub use save::Save;
+
+mod config;
+pub use config::{Config, ConfigSubcommand};
+
+mod info;
+pub use info::Info;
+
+mod slowlog;
+pub use slowlog::{Slowlog, SlowlogSubcommand};
+
+mod latency;
+pub use latency::{Latency, LatencySubcommand};
+
+mod monitor;
+pub use monitor::Monitor;
+
+mod client;
+pub use client::{Client, ClientKill, ClientSubcommand};
+
+mod shutdown;
+pub use shutdown::Shutdown;
+
+mod auth;
+pub use auth::Auth;
+
+mod hello;
+pub use hello::Hello;
+
+mod acl;
+pub use acl::{Acl, AclSubcommand};
+
+mod command;
+pub use command::{Commands, CommandsSubcommand, ListFilter};
+
+mod table;
+pub use table::{command_keys, CommandSpec, COMMANDS};
+
 pub use unknown::Unknown;
 
 use crate::{Connection, Db, Frame, Parse, ParseError};
@@ -54,6 +108,23 @@ pub enum Command {
     Rpush(Rpush),
     Blpop(Blpop),
     Brpop(Brpop),
+    Cluster(Cluster),
+    Asking(Asking),
+    Migrate(Migrate),
+    Restore(Restore),
+    Dump(Dump),
+    Save(Save),
+    Config(Config),
+    Info(Info),
+    Slowlog(Slowlog),
+    Latency(Latency),
+    Monitor(Monitor),
+    Client(Client),
+    Shutdown(Shutdown),
+    Auth(Auth),
+    Hello(Hello),
+    Acl(Acl),
+    Command(Commands),
 }
 
 
@@ -80,6 +151,18 @@ impl Command {
         // matching.
         let command_name = parse.next_string()?.to_lowercase();
 
+        // The number of arguments is checked against the command table
+        // before parsing, so every command reports it the same way.
+        if let Some(spec) = CommandSpec::lookup(&command_name) {
+            let argc = parse.remaining() + 1;
+            let sub = parse.peek_bytes().and_then(|name| spec.subcommand(name));
+            for spec in std::iter::once(spec).chain(sub) {
+                if !spec.accepts(argc) {
+                    return Err(format!("ERR wrong number of arguments for '{}' command", spec.name).into());
+                }
+            }
+        }
+
         // Match the command name, delegating the rest of the parsing to the
         // specific command.
         let command = match &command_name[..] {
@@ -95,6 +178,23 @@ impl Command {
             "exists" => Command::Exists(Exists::parse_frames(&mut parse)?),
             "blpop" => Command::Blpop(Blpop::parse_frames(&mut parse)?),
             "brpop" => Command::Brpop(Brpop::parse_frames(&mut parse)?),
+            "cluster" => Command::Cluster(Cluster::parse_frames(&mut parse)?),
+            "asking" => Command::Asking(Asking::parse_frames(&mut parse)?),
+            "migrate" => Command::Migrate(Migrate::parse_frames(&mut parse)?),
+            "restore" => Command::Restore(Restore::parse_frames(&mut parse)?),
+            "dump" => Command::Dump(Dump::parse_frames(&mut parse)?),
+            "save" => Command::Save(Save::parse_frames(&mut parse)?),
+            "config" => Command::Config(Config::parse_frames(&mut parse)?),
+            "info" => Command::Info(Info::parse_frames(&mut parse)?),
+            "slowlog" => Command::Slowlog(Slowlog::parse_frames(&mut parse)?),
+            "latency" => Command::Latency(Latency::parse_frames(&mut parse)?),
+            "monitor" => Command::Monitor(Monitor::parse_frames(&mut parse)?),
+            "client" => Command::Client(Client::parse_frames(&mut parse)?),
+            "shutdown" => Command::Shutdown(Shutdown::parse_frames(&mut parse)?),
+            "auth" => Command::Auth(Auth::parse_frames(&mut parse)?),
+            "hello" => Command::Hello(Hello::parse_frames(&mut parse)?),
+            "acl" => Command::Acl(Acl::parse_frames(&mut parse)?),
+            "command" => Command::Command(Commands::parse_frames(&mut parse)?),
             _ => {
                 // The command is not recognized and an Unknown command is
                 // returned.
@@ -142,7 +242,7 @@ impl Command {
     // }
 
     /// Returns the command name
-    pub(crate) fn get_name(&self) -> &str {
+    pub fn get_name(&self) -> &str {
         match self {
             Command::Get(_) => "get",
             // Command::Publish(_) => "pub",
@@ -156,7 +256,37 @@ impl Command {
             Command::Rpush(_) => "rpush",
             Command::Blpop(_) => "blpop",
             Command::Brpop(_) => "brpop",
+            Command::Cluster(_) => "cluster",
+            Command::Asking(_) => "asking",
+            Command::Migrate(_) => "migrate",
+            Command::Restore(_) => "restore",
+            Command::Dump(_) => "dump",
+            Command::Save(_) => "save",
+            Command::Config(_) => "config",
+            Command::Info(_) => "info",
+            Command::Slowlog(_) => "slowlog",
+            Command::Latency(_) => "latency",
+            Command::Monitor(_) => "monitor",
+            Command::Client(_) => "client",
+            Command::Shutdown(_) => "shutdown",
+            Command::Auth(_) => "auth",
+            Command::Hello(_) => "hello",
+            Command::Acl(_) => "acl",
+            Command::Command(_) => "command",
             Command::Unknown(cmd) => cmd.get_name(),
         }
     }
-}
\ No newline at end of file
+
+    /// Returns the entry of the command table describing the command, `None`
+    /// for an unknown command.
+    pub fn spec(&self) -> Option<&'static CommandSpec> {
+        CommandSpec::lookup(self.get_name())
+    }
+
+    /// Returns `true` if the command may modify the data set.
+    ///
+    /// Used to hold such commands back during `CLIENT PAUSE WRITE`.
+    pub fn is_write(&self) -> bool {
+        self.spec().is_some_and(|spec| spec.has_flag("write"))
+    }
+}
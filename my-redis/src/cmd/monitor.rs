@@ -0,0 +1,17 @@
+use crate::Parse;
+
+/// Stream every command processed by the server back to the client.
+///
+/// Once issued, the connection only receives monitor lines until it is
+/// closed.
+#[derive(Debug, Clone)]
+pub struct Monitor;
+
+impl Monitor {
+    /// Parse a `Monitor` instance from a received frame.
+    ///
+    /// The `MONITOR` string has already been consumed and no arguments follow.
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Monitor> {
+        Ok(Monitor)
+    }
+}
@@ -22,4 +22,15 @@ impl Ping {
         let key = key.unwrap_or_else(|_| "Pong".to_string());
         Ok(Ping { key: Bytes::from(key)})
     }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by the client when encoding a `Ping` command to send
+    /// to the server.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("ping".as_bytes()));
+        frame.push_bulk(self.key);
+        frame
+    }
 }
\ No newline at end of file
@@ -0,0 +1,103 @@
+use crate::cmd::ParseError;
+use crate::{Frame, Parse};
+
+use bytes::Bytes;
+
+/// Create `key` from a value serialized by another instance.
+///
+/// Fails with `BUSYKEY` if `key` already exists, unless `REPLACE` is given.
+/// The payload must come from `DUMP` (or `MIGRATE`) of a compatible version.
+#[derive(Debug, Clone)]
+pub struct Restore {
+    key: String,
+    ttl: u64,
+    payload: Bytes,
+    replace: bool,
+    /// `ttl` is an absolute Unix timestamp in milliseconds
+    absttl: bool,
+}
+
+impl Restore {
+    pub fn new(key: impl ToString, ttl: u64, payload: Bytes, replace: bool) -> Restore {
+        Restore {
+            key: key.to_string(),
+            ttl,
+            payload,
+            replace,
+            absttl: false,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Time to live in milliseconds, `0` for no expiry
+    pub fn ttl(&self) -> u64 {
+        self.ttl
+    }
+
+    /// Whether `ttl` is an absolute Unix time in milliseconds
+    pub fn absttl(&self) -> bool {
+        self.absttl
+    }
+
+    /// Get the serialized value
+    pub fn payload(&self) -> &Bytes {
+        &self.payload
+    }
+
+    /// Whether an existing key is overwritten
+    pub fn replace(&self) -> bool {
+        self.replace
+    }
+
+    /// Parse a `Restore` instance from a received frame.
+    ///
+    /// The `RESTORE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// RESTORE key ttl serialized-value [REPLACE] [ABSTTL]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Restore> {
+        let key = parse.next_string()?;
+        let ttl = parse.next_int()?;
+        let payload = parse.next_bytes()?;
+
+        let mut replace = false;
+        let mut absttl = false;
+        loop {
+            match parse.next_string() {
+                Ok(option) if option.eq_ignore_ascii_case("replace") => replace = true,
+                Ok(option) if option.eq_ignore_ascii_case("absttl") => absttl = true,
+                Ok(option) => return Err(format!("ERR syntax error near `{}`", option).into()),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(Restore { key, ttl, payload, replace, absttl })
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    ///
+    /// This is called by `MIGRATE` when sending values to the target
+    /// instance.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("restore".as_bytes()));
+        frame.push_bulk(Bytes::from(self.key.into_bytes()));
+        frame.push_bulk(Bytes::from(self.ttl.to_string()));
+        frame.push_bulk(self.payload);
+        if self.replace {
+            frame.push_bulk(Bytes::from("replace".as_bytes()));
+        }
+        if self.absttl {
+            frame.push_bulk(Bytes::from("absttl".as_bytes()));
+        }
+        frame
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use crate::{Connection, Db, Frame, Parse};
 
 use bytes::Bytes;
@@ -5,14 +6,14 @@ use bytes::Bytes;
 #[derive(Debug,Clone)]
 pub struct Rpush {
     key: String,
-    list: Vec<String>,
+    list: VecDeque<String>,
 }
 
 impl Rpush {
     pub fn new(key: impl ToString) -> Rpush {
         Rpush {
             key: key.to_string(),
-            list: vec![],
+            list: VecDeque::new(),
         }
     }
 
@@ -20,15 +21,15 @@ impl Rpush {
         &self.key
     }
 
-    pub fn get_lists(&self) -> &Vec<String> {
+    pub fn get_lists(&self) -> &VecDeque<String> {
         &self.list
     }
 
     pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Rpush> {
-        let mut values = Vec::new();
+        let mut values = VecDeque::new();
         let key = parse.next_string()?;
         while let Ok(value) = parse.next_string() {
-            values.push(value);
+            values.push_back(value);
         }
         Ok(Rpush { key, list: values })
     }
@@ -0,0 +1,14 @@
+use crate::Parse;
+
+/// Synchronously write a snapshot of all databases to disk.
+#[derive(Debug, Clone)]
+pub struct Save;
+
+impl Save {
+    /// Parse a `Save` instance from a received frame.
+    ///
+    /// The `SAVE` string has already been consumed and no arguments follow.
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<Save> {
+        Ok(Save)
+    }
+}
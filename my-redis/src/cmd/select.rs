@@ -5,7 +5,6 @@ use bytes::Bytes;
 use std::time::Duration;
 use tracing::{debug, instrument};
 use crate::db::AllDbs;
-use crate::db::NUM_DBS;
 
 #[derive(Debug,Clone)]
 pub struct Select {
@@ -37,10 +36,6 @@ impl Select {
             .parse::<usize>()
             .map_err(|_| "Invalid database index")?;
 
-        if !(0..NUM_DBS).contains(&db_index) {
-            return Err("Database index must be between 0 and 15".into());
-        }
-
         // Attempt to parse another string.
         match parse.next_string() {
             Ok(_) => return Err("currently `Select` only support Select {DB}".into()),
@@ -0,0 +1,54 @@
+use crate::Parse;
+
+/// Stop the server.
+///
+/// New connections are refused, in-flight commands are given
+/// `shutdown-timeout` seconds to finish, and the data set is saved to disk
+/// before exiting.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    /// `Some(true)` for `SAVE`, `Some(false)` for `NOSAVE`, `None` to save
+    /// only when periodic snapshots are configured
+    save: Option<bool>,
+    abort: bool,
+}
+
+impl Shutdown {
+    /// Whether to save before exiting, see the `save` field
+    pub fn save(&self) -> Option<bool> {
+        self.save
+    }
+
+    /// Whether the command cancels a shutdown instead of starting one
+    pub fn abort(&self) -> bool {
+        self.abort
+    }
+
+    /// Parse a `Shutdown` instance from a received frame.
+    ///
+    /// The `SHUTDOWN` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// SHUTDOWN [NOSAVE | SAVE] [ABORT]
+    /// ```
+    ///
+    /// `ABORT` can't be combined with the other options.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Shutdown> {
+        let mut shutdown = Shutdown { save: None, abort: false };
+        while let Ok(option) = parse.next_string() {
+            match &option.to_lowercase()[..] {
+                "save" if shutdown.save.is_none() => shutdown.save = Some(true),
+                "nosave" if shutdown.save.is_none() => shutdown.save = Some(false),
+                "abort" => shutdown.abort = true,
+                _ => return Err("ERR syntax error".into()),
+            }
+        }
+        if shutdown.abort && shutdown.save.is_some() {
+            return Err("ERR syntax error".into());
+        }
+
+        Ok(shutdown)
+    }
+}
@@ -0,0 +1,61 @@
+use crate::Parse;
+
+/// Inspect the log of commands that exceeded the configured execution time.
+#[derive(Debug, Clone)]
+pub struct Slowlog {
+    subcommand: SlowlogSubcommand,
+}
+
+/// The supported `SLOWLOG` subcommands.
+#[derive(Debug, Clone)]
+pub enum SlowlogSubcommand {
+    /// Number of entries to return, `None` for all of them
+    Get(Option<usize>),
+    Len,
+    Reset,
+}
+
+/// Entries returned by `SLOWLOG GET` without a count.
+const DEFAULT_COUNT: usize = 10;
+
+impl Slowlog {
+    pub fn new(subcommand: SlowlogSubcommand) -> Slowlog {
+        Slowlog { subcommand }
+    }
+
+    /// Get the subcommand
+    pub fn subcommand(&self) -> &SlowlogSubcommand {
+        &self.subcommand
+    }
+
+    /// Parse a `Slowlog` instance from a received frame.
+    ///
+    /// The `SLOWLOG` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// SLOWLOG GET [count]
+    /// SLOWLOG LEN
+    /// SLOWLOG RESET
+    /// ```
+    ///
+    /// A count of `-1` returns every entry.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Slowlog> {
+        let subcommand = match &parse.next_string()?.to_lowercase()[..] {
+            "get" => match parse.next_string() {
+                Ok(count) => match count.parse::<i64>() {
+                    Ok(-1) => SlowlogSubcommand::Get(None),
+                    Ok(count) if count >= 0 => SlowlogSubcommand::Get(Some(count as usize)),
+                    _ => return Err("ERR count should be greater than or equal to -1".into()),
+                },
+                Err(_) => SlowlogSubcommand::Get(Some(DEFAULT_COUNT)),
+            },
+            "len" => SlowlogSubcommand::Len,
+            "reset" => SlowlogSubcommand::Reset,
+            other => return Err(format!("ERR Unknown SLOWLOG subcommand `{}`", other).into()),
+        };
+
+        Ok(Slowlog { subcommand })
+    }
+}
@@ -0,0 +1,496 @@
+//! The table describing every supported command, as reported by `COMMAND`.
+//!
+//! Besides introspection, the table is where the server learns how a command
+//! is called: its arity is checked before parsing, the keys checked by ACLs
+//! and routed on in cluster mode are found at the positions it gives, and
+//! ACL categories are looked up in it.
+
+use crate::Frame;
+
+use bytes::Bytes;
+
+/// Finds the positions of the keys in a command line.
+type FindKeys = fn(&[Bytes]) -> Vec<usize>;
+
+/// Description of a command, or of a subcommand of a container command such
+/// as `CONFIG`.
+#[derive(Debug)]
+pub struct CommandSpec {
+    /// Lower case name, `container|subcommand` for a subcommand
+    pub name: &'static str,
+    /// Number of arguments, the name included. `-n` stands for at least `n`.
+    pub arity: i64,
+    pub flags: &'static [&'static str],
+    /// Position of the first key, `0` if the command takes none
+    pub first_key: i64,
+    /// Position of the last key, negative when counted from the end
+    pub last_key: i64,
+    /// Step between two keys
+    pub step: i64,
+    /// ACL categories, without the leading `@`
+    pub categories: &'static [&'static str],
+    pub group: &'static str,
+    pub summary: &'static str,
+    pub subcommands: &'static [CommandSpec],
+    /// Finds the keys of a command flagged `movablekeys`, which are not at
+    /// fixed positions
+    find_keys: Option<FindKeys>,
+}
+
+const fn command(
+    name: &'static str,
+    arity: i64,
+    flags: &'static [&'static str],
+    categories: &'static [&'static str],
+    group: &'static str,
+    summary: &'static str,
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity,
+        flags,
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        categories,
+        group,
+        summary,
+        subcommands: &[],
+        find_keys: None,
+    }
+}
+
+impl CommandSpec {
+    const fn keys(self, first_key: i64, last_key: i64, step: i64) -> CommandSpec {
+        CommandSpec {
+            first_key,
+            last_key,
+            step,
+            ..self
+        }
+    }
+
+    const fn movable_keys(self, find_keys: FindKeys) -> CommandSpec {
+        CommandSpec {
+            find_keys: Some(find_keys),
+            ..self
+        }
+    }
+
+    const fn subcommands(self, subcommands: &'static [CommandSpec]) -> CommandSpec {
+        CommandSpec { subcommands, ..self }
+    }
+
+    /// Look a command up by name, case insensitively.
+    pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+        COMMANDS.iter().find(|spec| spec.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Look a command up by its full name, case insensitively: a
+    /// subcommand is named after its container, such as `config|get`.
+    pub fn lookup_full(name: &str) -> Option<&'static CommandSpec> {
+        match name.split_once('|') {
+            Some((container, sub)) => CommandSpec::lookup(container)?.subcommand(sub.as_bytes()),
+            None => CommandSpec::lookup(name),
+        }
+    }
+
+    /// The subcommand `name` of a container command.
+    pub fn subcommand(&'static self, name: &[u8]) -> Option<&'static CommandSpec> {
+        self.subcommands.iter().find(|sub| {
+            sub.name
+                .split_once('|')
+                .is_some_and(|(_, sub)| sub.as_bytes().eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Whether the command may be called with `argc` arguments, its name
+    /// included.
+    pub fn accepts(&self, argc: usize) -> bool {
+        match self.arity {
+            arity if arity < 0 => argc as i64 >= -arity,
+            arity => argc as i64 == arity,
+        }
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    /// Positions of the keys in `args`, a command line starting with the
+    /// command name.
+    pub fn key_positions(&self, args: &[Bytes]) -> Vec<usize> {
+        if let Some(find_keys) = self.find_keys {
+            return find_keys(args);
+        }
+        if self.first_key == 0 {
+            return vec![];
+        }
+        let last = if self.last_key < 0 {
+            args.len() as i64 + self.last_key
+        } else {
+            self.last_key.min(args.len() as i64 - 1)
+        };
+        (self.first_key..=last)
+            .step_by(self.step as usize)
+            .map(|position| position as usize)
+            .collect()
+    }
+
+    /// The command as described by `COMMAND INFO`.
+    pub fn info_frame(&self) -> Frame {
+        let simple = |value: &str| Frame::Simple(value.to_string());
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from(self.name)),
+            Frame::Integer(self.arity),
+            Frame::Set(self.flags.iter().map(|flag| simple(flag)).collect()),
+            Frame::Integer(self.first_key),
+            Frame::Integer(self.last_key),
+            Frame::Integer(self.step),
+            Frame::Set(self.categories.iter().map(|category| simple(&format!("@{}", category))).collect()),
+            // No command comes with tips for clients.
+            Frame::Array(vec![]),
+            Frame::Array(self.key_specs()),
+            Frame::Array(self.subcommands.iter().map(CommandSpec::info_frame).collect()),
+        ])
+    }
+
+    /// The key specifications of `COMMAND INFO`, derived from the key
+    /// positions. The keys of commands flagged `movablekeys` can't be
+    /// described that way.
+    fn key_specs(&self) -> Vec<Frame> {
+        if self.first_key == 0 {
+            return vec![];
+        }
+        let field = |name: &'static str, value: Frame| (Frame::Bulk(Bytes::from(name)), value);
+        let bulk = |value: &'static str| Frame::Bulk(Bytes::from(value));
+        let access = if self.has_flag("write") { "RW" } else { "RO" };
+        let (begin_search, find_keys) = if self.find_keys.is_some() {
+            (
+                vec![field("type", bulk("unknown")), field("spec", Frame::Map(vec![]))],
+                vec![field("type", bulk("unknown")), field("spec", Frame::Map(vec![]))],
+            )
+        } else {
+            // The last key is relative to the first one, unless counted
+            // from the end.
+            let last_key = if self.last_key < 0 { self.last_key } else { self.last_key - self.first_key };
+            (
+                vec![
+                    field("type", bulk("index")),
+                    field("spec", Frame::Map(vec![field("index", Frame::Integer(self.first_key))])),
+                ],
+                vec![
+                    field("type", bulk("range")),
+                    field(
+                        "spec",
+                        Frame::Map(vec![
+                            field("lastkey", Frame::Integer(last_key)),
+                            field("keystep", Frame::Integer(self.step)),
+                            field("limit", Frame::Integer(0)),
+                        ]),
+                    ),
+                ],
+            )
+        };
+        vec![Frame::Map(vec![
+            field("flags", Frame::Set(vec![Frame::Simple(access.to_string())])),
+            field("begin_search", Frame::Map(begin_search)),
+            field("find_keys", Frame::Map(find_keys)),
+        ])]
+    }
+
+    /// The command as documented by `COMMAND DOCS`.
+    pub fn docs_frame(&self) -> Frame {
+        let field = |name: &'static str, value: Frame| (Frame::Bulk(Bytes::from(name)), value);
+        let mut docs = vec![
+            field("summary", Frame::Bulk(Bytes::from(self.summary))),
+            field("group", Frame::Bulk(Bytes::from(self.group))),
+        ];
+        if !self.subcommands.is_empty() {
+            let subcommands = self
+                .subcommands
+                .iter()
+                .map(|sub| (Frame::Bulk(Bytes::from(sub.name)), sub.docs_frame()))
+                .collect();
+            docs.push(field("subcommands", Frame::Map(subcommands)));
+        }
+        Frame::Map(docs)
+    }
+}
+
+/// The keys of a command line, starting with the command name. Unknown
+/// commands have none.
+pub fn command_keys(args: &[Bytes]) -> Vec<String> {
+    let spec = match args.first().and_then(|name| CommandSpec::lookup(&String::from_utf8_lossy(name))) {
+        Some(spec) => spec,
+        None => return vec![],
+    };
+    spec.key_positions(args)
+        .into_iter()
+        .map(|position| String::from_utf8_lossy(&args[position]).into_owned())
+        .collect()
+}
+
+/// `MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE]
+/// [AUTH password | AUTH2 username password] [KEYS key [key ...]]`
+fn migrate_keys(args: &[Bytes]) -> Vec<usize> {
+    if args.get(3).is_some_and(|key| !key.is_empty()) {
+        return vec![3];
+    }
+    // Skip the options, and the credentials of AUTH and AUTH2, up to KEYS.
+    let mut at = 6;
+    while let Some(option) = args.get(at) {
+        match &option.to_ascii_lowercase()[..] {
+            b"keys" => return (at + 1..args.len()).collect(),
+            b"auth" => at += 2,
+            b"auth2" => at += 3,
+            _ => at += 1,
+        }
+    }
+    vec![]
+}
+
+/// Every supported command, in the order of `Command`.
+pub static COMMANDS: &[CommandSpec] = &[
+    command("get", 2, &["readonly", "fast"], &["read", "string", "fast"], "string",
+        "Returns the string value of a key.")
+        .keys(1, 1, 1),
+    command("set", -3, &["write", "denyoom"], &["write", "string", "slow"], "string",
+        "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.")
+        .keys(1, 1, 1),
+    command("ping", -1, &["fast"], &["fast", "connection"], "connection",
+        "Returns the server's liveliness response."),
+    command("select", 2, &["loading", "stale", "fast"], &["fast", "connection"], "connection",
+        "Changes the selected database."),
+    command("exists", -2, &["readonly", "fast"], &["keyspace", "read", "fast"], "generic",
+        "Determines whether one or more keys exist.")
+        .keys(1, -1, 1),
+    command("lpush", -3, &["write", "denyoom", "fast"], &["write", "list", "fast"], "list",
+        "Prepends one or more elements to a list. Creates the key if it doesn't exist.")
+        .keys(1, 1, 1),
+    command("rpush", -3, &["write", "denyoom", "fast"], &["write", "list", "fast"], "list",
+        "Appends one or more elements to a list. Creates the key if it doesn't exist.")
+        .keys(1, 1, 1),
+    command("blpop", -3, &["write", "blocking"], &["write", "list", "slow", "blocking"], "list",
+        "Removes and returns the first element in a list. Blocks until an element is available otherwise.")
+        .keys(1, -2, 1),
+    command("brpop", -3, &["write", "blocking"], &["write", "list", "slow", "blocking"], "list",
+        "Removes and returns the last element in a list. Blocks until an element is available otherwise.")
+        .keys(1, -2, 1),
+    command("cluster", -2, &[], &["slow"], "cluster",
+        "A container for Redis Cluster commands.")
+        .subcommands(&[
+            command("cluster|info", 2, &["stale"], &["slow"], "cluster",
+                "Returns information about the state of a node."),
+            command("cluster|myid", 2, &["stale"], &["slow"], "cluster",
+                "Returns the ID of a node."),
+            command("cluster|nodes", 2, &["stale"], &["slow"], "cluster",
+                "Returns the cluster configuration for a node."),
+            command("cluster|slots", 2, &["loading", "stale"], &["slow"], "cluster",
+                "Returns the mapping of cluster slots to nodes."),
+            command("cluster|shards", 2, &["loading", "stale"], &["slow"], "cluster",
+                "Returns the mapping of cluster slots to shards."),
+            command("cluster|keyslot", 3, &["stale"], &["slow"], "cluster",
+                "Returns the hash slot for a key."),
+            command("cluster|countkeysinslot", 3, &["stale"], &["slow"], "cluster",
+                "Returns the number of keys in a hash slot."),
+            command("cluster|getkeysinslot", 4, &["stale"], &["slow"], "cluster",
+                "Returns the key names in a hash slot."),
+            command("cluster|setslot", -4, &["admin", "noscript", "stale"], &["admin", "slow", "dangerous"], "cluster",
+                "Binds a hash slot to a node."),
+        ]),
+    command("asking", 1, &["fast"], &["fast", "connection"], "cluster",
+        "Signals that a cluster client is following an -ASK redirect."),
+    command("migrate", -6, &["write", "movablekeys"], &["keyspace", "write", "slow", "dangerous"], "generic",
+        "Atomically transfers a key from one Redis instance to another.")
+        .keys(3, 3, 1)
+        .movable_keys(migrate_keys),
+    command("restore", -4, &["write", "denyoom"], &["keyspace", "write", "slow", "dangerous"], "generic",
+        "Creates a key from the serialized representation of a value.")
+        .keys(1, 1, 1),
+    command("dump", 2, &["readonly"], &["keyspace", "read", "slow"], "generic",
+        "Returns a serialized representation of the value stored at a key.")
+        .keys(1, 1, 1),
+    command("save", 1, &["admin", "noscript", "no_async_loading", "no_multi"], &["admin", "slow", "dangerous"], "server",
+        "Synchronously saves the database(s) to disk."),
+    command("config", -2, &[], &["admin", "slow", "dangerous"], "server",
+        "A container for server configuration commands.")
+        .subcommands(&[
+            command("config|get", -3, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Returns the effective values of configuration parameters."),
+            command("config|set", -4, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Sets configuration parameters in-flight."),
+            command("config|resetstat", 2, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Resets the server's statistics."),
+            command("config|rewrite", 2, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Persists the effective configuration to file."),
+        ]),
+    command("info", -1, &["loading", "stale"], &["slow", "dangerous"], "server",
+        "Returns information and statistics about the server."),
+    command("slowlog", -2, &[], &["admin", "slow", "dangerous"], "server",
+        "A container for slow log commands.")
+        .subcommands(&[
+            command("slowlog|get", -2, &["admin", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Returns the slow log's entries."),
+            command("slowlog|len", 2, &["admin", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Returns the number of entries in the slow log."),
+            command("slowlog|reset", 2, &["admin", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Clears all entries from the slow log."),
+        ]),
+    command("latency", -2, &[], &["admin", "slow", "dangerous"], "server",
+        "A container for latency diagnostics commands.")
+        .subcommands(&[
+            command("latency|latest", 2, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Returns the latest latency samples for all events."),
+            command("latency|history", 3, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Returns timestamp-latency samples for an event."),
+            command("latency|reset", -2, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Resets the latency data for one or more events."),
+            command("latency|doctor", 2, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Returns a human-readable latency analysis report."),
+            command("latency|histogram", -2, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Returns the cumulative distribution of latencies of a subset or all commands."),
+        ]),
+    command("monitor", 1, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+        "Listens for all requests received by the server in real-time."),
+    command("client", -2, &[], &["slow", "connection"], "connection",
+        "A container for client connection commands.")
+        .subcommands(&[
+            command("client|list", -2, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous", "connection"], "connection",
+                "Lists open connections."),
+            command("client|info", 2, &["noscript", "loading", "stale"], &["slow", "connection"], "connection",
+                "Returns information about the connection."),
+            command("client|setname", 3, &["noscript", "loading", "stale"], &["slow", "connection"], "connection",
+                "Sets the connection name."),
+            command("client|getname", 2, &["noscript", "loading", "stale"], &["slow", "connection"], "connection",
+                "Returns the name of the connection."),
+            command("client|id", 2, &["noscript", "loading", "stale"], &["slow", "connection"], "connection",
+                "Returns the unique client ID of the connection."),
+            command("client|kill", -3, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous", "connection"], "connection",
+                "Terminates open connections."),
+            command("client|pause", -3, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous", "connection"], "connection",
+                "Suspends commands processing."),
+            command("client|unpause", 2, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous", "connection"], "connection",
+                "Resumes processing commands from paused clients."),
+            command("client|no-evict", 3, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous", "connection"], "connection",
+                "Sets the client eviction mode of the connection."),
+            command("client|unblock", -3, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous", "connection"], "connection",
+                "Unblocks a client blocked by a blocking command from a different connection."),
+        ]),
+    command("shutdown", -1, &["admin", "noscript", "loading", "stale", "no_multi", "allow_busy"], &["admin", "slow", "dangerous"], "server",
+        "Synchronously saves the database(s) to disk and shuts down the Redis server."),
+    command("auth", -2, &["noscript", "loading", "stale", "fast", "no_auth", "allow_busy"], &["fast", "connection"], "connection",
+        "Authenticates the connection."),
+    command("hello", -1, &["noscript", "loading", "stale", "fast", "no_auth", "allow_busy"], &["fast", "connection"], "connection",
+        "Handshakes with the Redis server."),
+    command("acl", -2, &[], &["admin", "slow", "dangerous"], "server",
+        "A container for Access List Control commands.")
+        .subcommands(&[
+            command("acl|setuser", -3, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Creates and modifies an ACL user and its rules."),
+            command("acl|getuser", 3, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Lists the ACL rules of a user."),
+            command("acl|deluser", -3, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Deletes ACL users, and terminates their connections."),
+            command("acl|list", 2, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Dumps the effective rules in ACL file format."),
+            command("acl|users", 2, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Lists all ACL users."),
+            command("acl|whoami", 2, &["noscript", "loading", "stale"], &["slow"], "server",
+                "Returns the authenticated username of the current connection."),
+            command("acl|cat", -2, &["noscript", "loading", "stale"], &["slow"], "server",
+                "Lists the ACL categories, or the commands inside a category."),
+            command("acl|log", -2, &["admin", "noscript", "loading", "stale"], &["admin", "slow", "dangerous"], "server",
+                "Lists recent security events generated due to ACL rules."),
+        ]),
+    command("command", -1, &["loading", "stale"], &["slow", "connection"], "server",
+        "Returns detailed information about all commands.")
+        .subcommands(&[
+            command("command|count", 2, &["loading", "stale"], &["slow", "connection"], "server",
+                "Returns a count of commands."),
+            command("command|info", -2, &["loading", "stale"], &["slow", "connection"], "server",
+                "Returns information about one, multiple or all commands."),
+            command("command|docs", -2, &["loading", "stale"], &["slow", "connection"], "server",
+                "Returns documentary information about one, multiple or all commands."),
+            command("command|getkeys", -3, &["loading", "stale"], &["slow", "connection"], "server",
+                "Extracts the key names from an arbitrary command."),
+            command("command|list", -2, &["loading", "stale"], &["slow", "connection"], "server",
+                "Returns a list of command names."),
+        ]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<Bytes> {
+        line.split(' ').map(|arg| Bytes::from(arg.replace("''", ""))).collect()
+    }
+
+    fn keys(line: &str) -> Vec<String> {
+        command_keys(&args(line))
+    }
+
+    #[test]
+    fn fixed_key_positions() {
+        assert_eq!(keys("get key"), ["key"]);
+        assert_eq!(keys("SET key value EX 10"), ["key"]);
+        assert_eq!(keys("exists a b c"), ["a", "b", "c"]);
+        assert_eq!(keys("blpop a b 0"), ["a", "b"]);
+        assert_eq!(keys("restore key 0 payload REPLACE"), ["key"]);
+        assert!(keys("ping key").is_empty());
+        assert!(keys("no-such-command key").is_empty());
+    }
+
+    #[test]
+    fn migrate_key_positions() {
+        assert_eq!(keys("migrate host 6379 key 0 1000"), ["key"]);
+        assert_eq!(keys("migrate host 6379 '' 0 1000 COPY REPLACE KEYS a b"), ["a", "b"]);
+        // Credentials are never taken for keys, even when named like the
+        // KEYS option.
+        assert_eq!(keys("migrate host 6379 '' 0 1000 AUTH keys KEYS a"), ["a"]);
+        assert_eq!(keys("migrate host 6379 '' 0 1000 AUTH2 keys keys KEYS a"), ["a"]);
+        assert!(keys("migrate host 6379 '' 0 1000").is_empty());
+    }
+
+    #[test]
+    fn lookups() {
+        assert_eq!(CommandSpec::lookup("GeT").unwrap().name, "get");
+        assert_eq!(CommandSpec::lookup_full("CONFIG|Get").unwrap().name, "config|get");
+        assert!(CommandSpec::lookup("config|get").is_none());
+        assert!(CommandSpec::lookup_full("get|key").is_none());
+        assert!(CommandSpec::lookup_full("config|nope").is_none());
+    }
+
+    #[test]
+    fn arity() {
+        let get = CommandSpec::lookup("get").unwrap();
+        assert!(!get.accepts(1));
+        assert!(get.accepts(2));
+        assert!(!get.accepts(3));
+        let set = CommandSpec::lookup("set").unwrap();
+        assert!(!set.accepts(2));
+        assert!(set.accepts(3));
+        assert!(set.accepts(10));
+    }
+
+    #[test]
+    fn table_is_consistent() {
+        for spec in COMMANDS {
+            assert_eq!(spec.name, spec.name.to_lowercase());
+            assert!(!spec.name.contains('|'), "{}", spec.name);
+            assert_eq!(spec.has_flag("movablekeys"), spec.find_keys.is_some(), "{}", spec.name);
+            assert_eq!(spec.first_key == 0, spec.step == 0, "{}", spec.name);
+            for sub in spec.subcommands {
+                assert_eq!(CommandSpec::lookup_full(sub.name).map(|found| found.name), Some(sub.name));
+                assert!(sub.subcommands.is_empty(), "{}", sub.name);
+            }
+            for spec in std::iter::once(spec).chain(spec.subcommands) {
+                assert_ne!(spec.arity, 0, "{}", spec.name);
+                for category in spec.categories {
+                    assert!(crate::acl::CATEGORIES.contains(category), "{} in {}", category, spec.name);
+                }
+            }
+        }
+    }
+}
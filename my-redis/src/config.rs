@@ -0,0 +1,961 @@
+//! Server configuration.
+//!
+//! The configuration starts from built-in defaults, is then updated from an
+//! optional redis.conf-style file and finally from command line flags. The
+//! file holds one directive per line, a parameter name followed by its
+//! arguments:
+//!
+//! ```text
+//! # comments and blank lines are ignored
+//! port 7000
+//! dir "/var/lib/my-redis"
+//! cluster-enabled yes
+//! ```
+//!
+//! Every parameter is described by an entry of a static registry, which is
+//! also what `CONFIG GET`, `CONFIG SET` and `CONFIG REWRITE` work from. Only
+//! parameters marked as mutable can be changed while the server is running.
+
+use crate::server::MAX_CONNECTIONS;
+use crate::DEFAULT_PORT;
+use crate::db::NUM_DBS;
+use crate::frame::Limits;
+use crate::OutputLimits;
+
+use crate::glob;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Longest inline command accepted from clients, as there is no header
+/// announcing its length.
+const MAX_INLINE_LEN: usize = 64 * 1024;
+
+/// Most databases the server runs. Each one is allocated at startup, along
+/// with the task and request channel serving it.
+const MAX_DATABASES: usize = 1024;
+
+/// The running configuration, shared by every part of the server that
+/// consults it.
+pub type SharedConfig = Arc<RwLock<Config>>;
+
+/// Settings of a server instance.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Address the TCP listeners bind to
+    pub bind: String,
+    /// Port of the plaintext listener, `0` to disable it
+    pub port: u16,
+    /// Port of the TLS listener, `0` to disable it
+    pub tls_port: u16,
+    /// Path of the Unix socket to listen on, if any
+    pub unixsocket: Option<PathBuf>,
+    /// Permissions of the Unix socket, `0` to leave them to the umask
+    pub unixsocketperm: u32,
+    /// PEM files holding the server certificate chain and its private key
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+    /// PEM file holding the CA certificates client certificates are checked
+    /// against
+    pub tls_ca_cert_file: Option<PathBuf>,
+    /// Whether TLS clients must present a certificate: `yes`, `no` or
+    /// `optional`
+    pub tls_auth_clients: String,
+    /// Number of logical databases
+    pub databases: usize,
+    /// One of `debug`, `verbose`, `notice` or `warning`
+    pub loglevel: String,
+    /// Directory the snapshot file is written to
+    pub dir: PathBuf,
+    /// Name of the snapshot file inside `dir`
+    pub dbfilename: String,
+    /// Interval in seconds between automatic snapshots, `0` to disable
+    pub save: u64,
+    /// Maximum number of connected clients
+    pub maxclients: usize,
+    /// Longest bulk string a client may send
+    pub proto_max_bulk_len: u64,
+    /// Most arguments a client may send in a single command
+    pub proto_max_multibulk_len: u64,
+    /// Most bytes buffered for a client while waiting for a complete command
+    pub client_query_buffer_limit: usize,
+    /// Bounds on the replies buffered for a client that doesn't read them,
+    /// indexed by `ClientClass`
+    pub client_output_buffer_limit: [OutputLimits; 3],
+    /// Seconds after which an idle client is disconnected, `0` to disable
+    pub timeout: u64,
+    /// Interval in seconds of the TCP keepalive probes sent to clients, `0`
+    /// to disable
+    pub tcp_keepalive: u64,
+    /// Seconds given to in-flight commands to finish when shutting down
+    pub shutdown_timeout: u64,
+    /// Password of the `default` user, empty if clients don't need to
+    /// authenticate
+    pub requirepass: String,
+    /// File the ACL users are loaded from at startup
+    pub aclfile: Option<PathBuf>,
+    /// Execution time in microseconds above which commands are added to the
+    /// slow log, `0` logs every command and a negative value disables it
+    pub slowlog_log_slower_than: i64,
+    /// Number of entries kept in the slow log
+    pub slowlog_max_len: usize,
+    /// Milliseconds from which operations are recorded as latency events,
+    /// `0` disables the latency monitor
+    pub latency_monitor_threshold: u64,
+    pub cluster_enabled: bool,
+    /// Static node list, see `ClusterState::from_spec`
+    pub cluster_nodes: String,
+    /// The file the configuration was read from, if any
+    pub config_file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bind: "127.0.0.1".to_string(),
+            port: DEFAULT_PORT,
+            tls_port: 0,
+            unixsocket: None,
+            unixsocketperm: 0,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: "yes".to_string(),
+            databases: NUM_DBS,
+            loglevel: "notice".to_string(),
+            dir: PathBuf::from("."),
+            dbfilename: "dump.snapshot".to_string(),
+            save: 0,
+            maxclients: MAX_CONNECTIONS,
+            proto_max_bulk_len: 512 * 1024 * 1024,
+            proto_max_multibulk_len: 1024 * 1024,
+            client_query_buffer_limit: 1024 * 1024 * 1024,
+            client_output_buffer_limit: [
+                OutputLimits::default(),
+                OutputLimits {
+                    hard: 256 * 1024 * 1024,
+                    soft: 64 * 1024 * 1024,
+                    soft_duration: Duration::from_secs(60),
+                },
+                OutputLimits {
+                    hard: 32 * 1024 * 1024,
+                    soft: 8 * 1024 * 1024,
+                    soft_duration: Duration::from_secs(60),
+                },
+            ],
+            timeout: 0,
+            tcp_keepalive: 300,
+            shutdown_timeout: 10,
+            requirepass: String::new(),
+            aclfile: None,
+            slowlog_log_slower_than: 10_000,
+            slowlog_max_len: 128,
+            latency_monitor_threshold: 0,
+            cluster_enabled: false,
+            cluster_nodes: String::new(),
+            config_file: None,
+        }
+    }
+}
+
+impl Config {
+    /// Read the configuration file at `path`, starting from the defaults.
+    pub fn from_file(path: &Path) -> crate::Result<Config> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("can't open config file {}: {}", path.display(), e))?;
+
+        let mut config = Config {
+            config_file: Some(path.to_path_buf()),
+            ..Config::default()
+        };
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut args = split_args(line)
+                .map_err(|e| format!("config file {}, line {}: {}", path.display(), number + 1, e))?
+                .into_iter();
+            let name = args.next().unwrap();
+            let args: Vec<String> = args.collect();
+
+            config
+                .set(&name, &args)
+                .map_err(|e| format!("config file {}, line {}: {}", path.display(), number + 1, e))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Set the parameter `name` from its arguments.
+    ///
+    /// Used while reading the configuration file and command line flags, so
+    /// every parameter may be set.
+    pub fn set(&mut self, name: &str, args: &[String]) -> crate::Result<()> {
+        let param = find_param(name).ok_or_else(|| format!("unknown configuration parameter `{}`", name))?;
+        let value = match args {
+            [value] => value.clone(),
+            // Like in Redis, the limits of each class may follow the name
+            // unquoted.
+            [_, ..] if param.name == "client-output-buffer-limit" => args.join(" "),
+            _ => return Err(format!("wrong number of arguments for `{}`", name).into()),
+        };
+        (param.set)(self, &value)
+    }
+
+    /// Apply `CONFIG SET` to the running configuration.
+    ///
+    /// All pairs are validated and applied together: if any of them fails,
+    /// the configuration is left untouched.
+    pub fn set_at_runtime(&mut self, pairs: &[(String, String)]) -> crate::Result<()> {
+        let mut updated = self.clone();
+        for (name, value) in pairs {
+            let param = match find_param(name) {
+                Some(param) => param,
+                None => return Err(format!("ERR Unknown option or number of arguments for CONFIG SET - '{}'", name).into()),
+            };
+            if !param.mutable {
+                return Err(format!(
+                    "ERR CONFIG SET failed (possibly related to argument '{}') - can't set immutable config",
+                    param.name
+                )
+                .into());
+            }
+            (param.set)(&mut updated, value).map_err(|e| {
+                format!("ERR CONFIG SET failed (possibly related to argument '{}') - {}", param.name, e)
+            })?;
+        }
+        *self = updated;
+        Ok(())
+    }
+
+    /// Returns the name and current value of every parameter matching the
+    /// glob `pattern`, as used by `CONFIG GET`.
+    pub fn get_matching(&self, pattern: &str) -> Vec<(&'static str, String)> {
+        PARAMS
+            .iter()
+            .filter(|param| glob::matches(pattern.as_bytes(), param.name.as_bytes(), true))
+            .map(|param| (param.name, (param.get)(self)))
+            .collect()
+    }
+
+    /// Write the running configuration back to the file it was read from.
+    ///
+    /// Lines setting a known parameter are updated in place, comments and
+    /// unknown lines are preserved, and parameters that differ from their
+    /// default but are missing from the file are appended.
+    pub fn rewrite(&self) -> crate::Result<()> {
+        let path = self
+            .config_file
+            .as_ref()
+            .ok_or("ERR The server is running without a config file")?;
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("ERR Rewriting config file: {}", e).into()),
+        };
+
+        let defaults = Config::default();
+        let mut written = Vec::new();
+        let mut out = String::new();
+        for line in contents.lines() {
+            let name = split_args(line.trim())
+                .ok()
+                .and_then(|args| args.into_iter().next())
+                .filter(|_| !line.trim_start().starts_with('#'));
+            match name.as_deref().and_then(find_param) {
+                // Only the first occurrence is kept, later ones would
+                // override it when the file is read again.
+                Some(param) if written.contains(&param.name) => continue,
+                Some(param) => {
+                    out.push_str(&format_directive(param, self));
+                    written.push(param.name);
+                }
+                None => out.push_str(line),
+            }
+            out.push('\n');
+        }
+
+        for param in PARAMS {
+            if !written.contains(&param.name) && (param.get)(self) != (param.get)(&defaults) {
+                out.push_str(&format_directive(param, self));
+                out.push('\n');
+            }
+        }
+
+        let tmp = path.with_extension(format!("tmp-{}", std::process::id()));
+        fs::write(&tmp, out)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| format!("ERR Rewriting config file: {}", e))?;
+        Ok(())
+    }
+
+    /// The limits client connections are held to
+    pub fn client_limits(&self) -> Limits {
+        Limits {
+            max_bulk_len: self.proto_max_bulk_len,
+            max_multibulk_len: self.proto_max_multibulk_len,
+            max_query_buffer: self.client_query_buffer_limit,
+            max_inline_len: Some(MAX_INLINE_LEN),
+        }
+    }
+
+    /// The output buffer limits clients of `class` are held to
+    pub fn output_limits(&self, class: ClientClass) -> OutputLimits {
+        self.client_output_buffer_limit[class as usize]
+    }
+
+    /// Full path of the snapshot file
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.dir.join(&self.dbfilename)
+    }
+
+    /// The `tracing` filter directive matching `loglevel`
+    pub fn tracing_level(&self) -> &'static str {
+        match &self.loglevel[..] {
+            "debug" => "debug",
+            "verbose" => "info",
+            "warning" => "warn",
+            _ => "info",
+        }
+    }
+}
+
+/// The classes of clients `client-output-buffer-limit` sets limits for.
+///
+/// `MONITOR` clients are held to the `pubsub` limits, as they too are fed
+/// replies they never asked for. There are no replicas yet, their limits
+/// are only kept for compatibility.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientClass {
+    Normal = 0,
+    Replica = 1,
+    Pubsub = 2,
+}
+
+impl ClientClass {
+    const ALL: [ClientClass; 3] = [ClientClass::Normal, ClientClass::Replica, ClientClass::Pubsub];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ClientClass::Normal => "normal",
+            ClientClass::Replica => "replica",
+            ClientClass::Pubsub => "pubsub",
+        }
+    }
+
+    /// Parse a class name. `slave` is accepted as an alias of `replica`.
+    fn parse(name: &str) -> Option<ClientClass> {
+        match &name.to_lowercase()[..] {
+            "normal" => Some(ClientClass::Normal),
+            "replica" | "slave" => Some(ClientClass::Replica),
+            "pubsub" => Some(ClientClass::Pubsub),
+            _ => None,
+        }
+    }
+}
+
+/// A configuration parameter known to the registry.
+struct Param {
+    name: &'static str,
+    /// Whether `CONFIG SET` may change the parameter on a running server
+    mutable: bool,
+    get: fn(&Config) -> String,
+    set: fn(&mut Config, &str) -> crate::Result<()>,
+}
+
+/// The registry of all configuration parameters.
+static PARAMS: &[Param] = &[
+    Param {
+        name: "bind",
+        mutable: false,
+        get: |config| config.bind.clone(),
+        set: |config, value| {
+            config.bind = value.to_string();
+            Ok(())
+        },
+    },
+    Param {
+        name: "port",
+        mutable: false,
+        get: |config| config.port.to_string(),
+        set: |config, value| {
+            config.port = parse_number(value, 0, u16::MAX)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-port",
+        mutable: false,
+        get: |config| config.tls_port.to_string(),
+        set: |config, value| {
+            config.tls_port = parse_number(value, 0, u16::MAX)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "unixsocket",
+        mutable: false,
+        get: |config| config.unixsocket.as_ref().map(|path| path.display().to_string()).unwrap_or_default(),
+        set: |config, value| {
+            config.unixsocket = if value.is_empty() { None } else { Some(PathBuf::from(value)) };
+            Ok(())
+        },
+    },
+    Param {
+        name: "unixsocketperm",
+        mutable: false,
+        get: |config| format!("{:o}", config.unixsocketperm),
+        set: |config, value| {
+            config.unixsocketperm = match u32::from_str_radix(value, 8) {
+                Ok(perm) if perm <= 0o777 => perm,
+                _ => return Err("argument must be an octal number between 0 and 777".into()),
+            };
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-cert-file",
+        mutable: true,
+        get: |config| config.tls_cert_file.as_ref().map(|path| path.display().to_string()).unwrap_or_default(),
+        set: |config, value| {
+            config.tls_cert_file = if value.is_empty() { None } else { Some(PathBuf::from(value)) };
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-key-file",
+        mutable: true,
+        get: |config| config.tls_key_file.as_ref().map(|path| path.display().to_string()).unwrap_or_default(),
+        set: |config, value| {
+            config.tls_key_file = if value.is_empty() { None } else { Some(PathBuf::from(value)) };
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-ca-cert-file",
+        mutable: true,
+        get: |config| config.tls_ca_cert_file.as_ref().map(|path| path.display().to_string()).unwrap_or_default(),
+        set: |config, value| {
+            config.tls_ca_cert_file = if value.is_empty() { None } else { Some(PathBuf::from(value)) };
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-auth-clients",
+        mutable: true,
+        get: |config| config.tls_auth_clients.clone(),
+        set: |config, value| {
+            let value = value.to_lowercase();
+            if !["yes", "no", "optional"].contains(&&value[..]) {
+                return Err("argument must be 'yes', 'no' or 'optional'".into());
+            }
+            config.tls_auth_clients = value;
+            Ok(())
+        },
+    },
+    Param {
+        name: "databases",
+        mutable: false,
+        get: |config| config.databases.to_string(),
+        set: |config, value| {
+            config.databases = parse_number(value, 1, MAX_DATABASES)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "loglevel",
+        mutable: true,
+        get: |config| config.loglevel.clone(),
+        set: |config, value| {
+            let level = value.to_lowercase();
+            if !matches!(&level[..], "debug" | "verbose" | "notice" | "warning") {
+                return Err(format!("invalid log level `{}`", value).into());
+            }
+            config.loglevel = level;
+            Ok(())
+        },
+    },
+    Param {
+        name: "dir",
+        mutable: true,
+        get: |config| config.dir.display().to_string(),
+        set: |config, value| {
+            config.dir = PathBuf::from(value);
+            Ok(())
+        },
+    },
+    Param {
+        name: "dbfilename",
+        mutable: true,
+        get: |config| config.dbfilename.clone(),
+        set: |config, value| {
+            if value.is_empty() || value.contains('/') {
+                return Err("`dbfilename` can't be a path, just a filename".into());
+            }
+            config.dbfilename = value.to_string();
+            Ok(())
+        },
+    },
+    Param {
+        name: "save",
+        mutable: true,
+        get: |config| config.save.to_string(),
+        set: |config, value| {
+            config.save = parse_number(value, 0, u64::MAX)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxclients",
+        mutable: true,
+        get: |config| config.maxclients.to_string(),
+        set: |config, value| {
+            config.maxclients = parse_number(value, 1, 1_000_000)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "proto-max-bulk-len",
+        mutable: true,
+        get: |config| config.proto_max_bulk_len.to_string(),
+        set: |config, value| {
+            config.proto_max_bulk_len = parse_memory(value, 1024 * 1024, i64::MAX as u64)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "proto-max-multibulk-len",
+        mutable: true,
+        get: |config| config.proto_max_multibulk_len.to_string(),
+        set: |config, value| {
+            config.proto_max_multibulk_len = parse_number(value, 1, i32::MAX as u64)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "client-query-buffer-limit",
+        mutable: true,
+        get: |config| config.client_query_buffer_limit.to_string(),
+        set: |config, value| {
+            config.client_query_buffer_limit = parse_memory(value, 1024 * 1024, i64::MAX as u64)? as usize;
+            Ok(())
+        },
+    },
+    Param {
+        name: "client-output-buffer-limit",
+        mutable: true,
+        get: |config| {
+            ClientClass::ALL
+                .iter()
+                .map(|&class| {
+                    let limits = config.output_limits(class);
+                    format!("{} {} {} {}", class.name(), limits.hard, limits.soft, limits.soft_duration.as_secs())
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+        set: |config, value| {
+            let args: Vec<&str> = value.split_whitespace().collect();
+            if args.is_empty() || !args.len().is_multiple_of(4) {
+                return Err("wrong number of arguments in buffer limit configuration".into());
+            }
+            // Only the classes given are changed.
+            for limit in args.chunks(4) {
+                let class = ClientClass::parse(limit[0])
+                    .ok_or_else(|| format!("invalid client class `{}` in buffer limit configuration", limit[0]))?;
+                config.client_output_buffer_limit[class as usize] = OutputLimits {
+                    hard: parse_memory(limit[1], 0, i64::MAX as u64)? as usize,
+                    soft: parse_memory(limit[2], 0, i64::MAX as u64)? as usize,
+                    soft_duration: Duration::from_secs(parse_number(limit[3], 0, i32::MAX as u64)?),
+                };
+            }
+            Ok(())
+        },
+    },
+    Param {
+        name: "timeout",
+        mutable: true,
+        get: |config| config.timeout.to_string(),
+        set: |config, value| {
+            config.timeout = parse_number(value, 0, i32::MAX as u64)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "tcp-keepalive",
+        mutable: true,
+        get: |config| config.tcp_keepalive.to_string(),
+        set: |config, value| {
+            config.tcp_keepalive = parse_number(value, 0, i32::MAX as u64)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "shutdown-timeout",
+        mutable: true,
+        get: |config| config.shutdown_timeout.to_string(),
+        set: |config, value| {
+            config.shutdown_timeout = parse_number(value, 0, i32::MAX as u64)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "requirepass",
+        mutable: true,
+        get: |config| config.requirepass.clone(),
+        set: |config, value| {
+            config.requirepass = value.to_string();
+            Ok(())
+        },
+    },
+    Param {
+        name: "aclfile",
+        mutable: false,
+        get: |config| config.aclfile.as_ref().map(|path| path.display().to_string()).unwrap_or_default(),
+        set: |config, value| {
+            config.aclfile = if value.is_empty() { None } else { Some(PathBuf::from(value)) };
+            Ok(())
+        },
+    },
+    Param {
+        name: "slowlog-log-slower-than",
+        mutable: true,
+        get: |config| config.slowlog_log_slower_than.to_string(),
+        set: |config, value| {
+            config.slowlog_log_slower_than = parse_number(value, -1, i64::MAX)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "slowlog-max-len",
+        mutable: true,
+        get: |config| config.slowlog_max_len.to_string(),
+        set: |config, value| {
+            config.slowlog_max_len = parse_number(value, 0, i64::MAX as usize)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "latency-monitor-threshold",
+        mutable: true,
+        get: |config| config.latency_monitor_threshold.to_string(),
+        set: |config, value| {
+            config.latency_monitor_threshold = parse_number(value, 0, i64::MAX as u64)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "cluster-enabled",
+        mutable: false,
+        get: |config| if config.cluster_enabled { "yes" } else { "no" }.to_string(),
+        set: |config, value| {
+            config.cluster_enabled = parse_bool(value)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "cluster-nodes",
+        mutable: false,
+        get: |config| config.cluster_nodes.clone(),
+        set: |config, value| {
+            config.cluster_nodes = value.to_string();
+            Ok(())
+        },
+    },
+];
+
+fn find_param(name: &str) -> Option<&'static Param> {
+    PARAMS.iter().find(|param| param.name.eq_ignore_ascii_case(name))
+}
+
+/// Render `param` as a configuration file line.
+fn format_directive(param: &Param, config: &Config) -> String {
+    let value = (param.get)(config);
+    if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+        format!("{} \"{}\"", param.name, value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        format!("{} {}", param.name, value)
+    }
+}
+
+/// Parse a number, checking it lies within `min..=max`.
+fn parse_number<T>(value: &str, min: T, max: T) -> crate::Result<T>
+where
+    T: std::str::FromStr + PartialOrd + std::fmt::Display,
+{
+    match value.parse::<T>() {
+        Ok(n) if n >= min && n <= max => Ok(n),
+        Ok(_) => Err(format!("argument must be between {} and {} inclusive", min, max).into()),
+        Err(_) => Err(format!("argument couldn't be parsed into an integer: `{}`", value).into()),
+    }
+}
+
+/// Parse a number of bytes, checking it lies within `min..=max`. Like in
+/// Redis, it may carry a unit: `k`, `m` and `g` are powers of 1000, `kb`,
+/// `mb` and `gb` powers of 1024.
+fn parse_memory(value: &str, min: u64, max: u64) -> crate::Result<u64> {
+    let lower = value.to_lowercase();
+    let (digits, unit) = match lower.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => lower.split_at(at),
+        None => (&lower[..], ""),
+    };
+    let unit: u64 = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err(format!("argument must be a memory value: `{}`", value).into()),
+    };
+    match digits.parse::<u64>().ok().and_then(|n| n.checked_mul(unit)) {
+        Some(n) if n >= min && n <= max => Ok(n),
+        Some(_) => Err(format!("argument must be between {} and {} inclusive", min, max).into()),
+        None => Err(format!("argument must be a memory value: `{}`", value).into()),
+    }
+}
+
+fn parse_bool(value: &str) -> crate::Result<bool> {
+    match &value.to_lowercase()[..] {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("argument must be 'yes' or 'no'".into()),
+    }
+}
+
+/// Split a configuration line, or an inline command, into arguments,
+/// honouring single and double quotes.
+pub(crate) fn split_args(line: &str) -> crate::Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let quote = match chars.peek() {
+            None => break,
+            Some(&c) if c == '"' || c == '\'' => {
+                chars.next();
+                Some(c)
+            }
+            Some(_) => None,
+        };
+
+        let mut arg = String::new();
+        loop {
+            match (chars.next(), quote) {
+                (None, Some(_)) => return Err("unbalanced quotes".into()),
+                (None, None) => break,
+                (Some(c), Some(q)) if c == q => {
+                    if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                        return Err("closing quote must be followed by a space".into());
+                    }
+                    break;
+                }
+                (Some('\\'), Some('"')) => match chars.next() {
+                    Some('n') => arg.push('\n'),
+                    Some('t') => arg.push('\t'),
+                    Some(c) => arg.push(c),
+                    None => return Err("unbalanced quotes".into()),
+                },
+                (Some(c), None) if c.is_whitespace() => break,
+                (Some(c), _) => arg.push(c),
+            }
+        }
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file named `name` holding `contents`, in a directory of its own.
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("my-redis-config-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("redis.conf");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn memory_units() {
+        assert_eq!(parse_memory("100", 0, u64::MAX).unwrap(), 100);
+        assert_eq!(parse_memory("100b", 0, u64::MAX).unwrap(), 100);
+        assert_eq!(parse_memory("1k", 0, u64::MAX).unwrap(), 1000);
+        assert_eq!(parse_memory("1kb", 0, u64::MAX).unwrap(), 1024);
+        assert_eq!(parse_memory("2m", 0, u64::MAX).unwrap(), 2_000_000);
+        assert_eq!(parse_memory("2MB", 0, u64::MAX).unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_memory("3g", 0, u64::MAX).unwrap(), 3_000_000_000);
+        assert_eq!(parse_memory("3Gb", 0, u64::MAX).unwrap(), 3 * 1024 * 1024 * 1024);
+    }
+
+    #[test]
+    fn invalid_memory_values() {
+        for value in ["", "kb", "-1", "1.5mb", "1tb", "12 mb", "99999999999gb"] {
+            assert!(parse_memory(value, 0, u64::MAX).is_err(), "{:?} was accepted", value);
+        }
+        let err = parse_memory("1kb", 2048, 4096).unwrap_err();
+        assert_eq!(err.to_string(), "argument must be between 2048 and 4096 inclusive");
+    }
+
+    #[test]
+    fn numbers_are_bounded() {
+        assert_eq!(parse_number::<u16>("6379", 0, u16::MAX).unwrap(), 6379);
+        assert!(parse_number::<u16>("65536", 0, u16::MAX).is_err());
+        assert!(parse_number::<usize>("0", 1, 10).is_err());
+        assert!(parse_number::<i64>("-1", -1, 10).is_ok());
+        assert!(parse_number::<u64>("ten", 0, 10).is_err());
+    }
+
+    #[test]
+    fn booleans() {
+        assert!(parse_bool("yes").unwrap());
+        assert!(parse_bool("YES").unwrap());
+        assert!(!parse_bool("no").unwrap());
+        for value in ["", "true", "1", "y"] {
+            assert!(parse_bool(value).is_err(), "{:?} was accepted", value);
+        }
+    }
+
+    #[test]
+    fn split_plain_and_quoted_args() {
+        assert_eq!(split_args("  port   7000 ").unwrap(), ["port", "7000"]);
+        assert_eq!(split_args("").unwrap(), Vec::<String>::new());
+        assert_eq!(split_args(r#"dir "/var/lib/my redis""#).unwrap(), ["dir", "/var/lib/my redis"]);
+        assert_eq!(split_args(r#"requirepass """#).unwrap(), ["requirepass", ""]);
+        assert_eq!(split_args(r#"set "a\"b\n\t\\" x"#).unwrap(), ["set", "a\"b\n\t\\", "x"]);
+        // Nothing is escaped between single quotes.
+        assert_eq!(split_args(r"set 'a\nb'").unwrap(), ["set", r"a\nb"]);
+    }
+
+    #[test]
+    fn split_rejects_bad_quotes() {
+        assert_eq!(split_args(r#"dir "/tmp"#).unwrap_err().to_string(), "unbalanced quotes");
+        assert_eq!(split_args("dir '/tmp").unwrap_err().to_string(), "unbalanced quotes");
+        assert_eq!(split_args(r#"dir "\"#).unwrap_err().to_string(), "unbalanced quotes");
+        assert_eq!(
+            split_args(r#"dir "/tmp"x"#).unwrap_err().to_string(),
+            "closing quote must be followed by a space"
+        );
+    }
+
+    #[test]
+    fn read_config_file() {
+        let path = temp_file(
+            "read",
+            "# a comment\n\nport 7000\ndir \"/tmp/my redis\"\ncluster-enabled yes\n\
+             client-output-buffer-limit pubsub 32mb 8mb 60\n",
+        );
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(config.port, 7000);
+        assert_eq!(config.dir, PathBuf::from("/tmp/my redis"));
+        assert!(config.cluster_enabled);
+        assert_eq!(config.output_limits(ClientClass::Pubsub).hard, 32 * 1024 * 1024);
+        assert_eq!(config.config_file.as_deref(), Some(path.as_path()));
+        // Untouched parameters keep their default.
+        assert_eq!(config.maxclients, Config::default().maxclients);
+    }
+
+    #[test]
+    fn config_file_errors_name_the_line() {
+        let path = temp_file("unknown", "port 7000\nno-such-param 1\n");
+        let err = Config::from_file(&path).unwrap_err().to_string();
+        assert!(err.ends_with("line 2: unknown configuration parameter `no-such-param`"), "{}", err);
+
+        let path = temp_file("invalid", "maxclients 0\n");
+        let err = Config::from_file(&path).unwrap_err().to_string();
+        assert!(err.ends_with("line 1: argument must be between 1 and 1000000 inclusive"), "{}", err);
+
+        let path = temp_file("databases", "databases 100000000\n");
+        let err = Config::from_file(&path).unwrap_err().to_string();
+        assert!(err.ends_with("line 1: argument must be between 1 and 1024 inclusive"), "{}", err);
+
+        let path = temp_file("arity", "port\n");
+        assert!(Config::from_file(&path).is_err());
+    }
+
+    #[test]
+    fn set_at_runtime_is_all_or_nothing() {
+        let mut config = Config::default();
+        let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect()
+        };
+
+        config.set_at_runtime(&pairs(&[("maxclients", "10"), ("TIMEOUT", "30")])).unwrap();
+        assert_eq!((config.maxclients, config.timeout), (10, 30));
+
+        let err = config.set_at_runtime(&pairs(&[("maxclients", "20"), ("timeout", "soon")])).unwrap_err();
+        assert!(err.to_string().contains("argument 'timeout'"), "{}", err);
+        assert_eq!((config.maxclients, config.timeout), (10, 30));
+
+        let err = config.set_at_runtime(&pairs(&[("port", "7000")])).unwrap_err();
+        assert!(err.to_string().contains("can't set immutable config"), "{}", err);
+        let err = config.set_at_runtime(&pairs(&[("no-such-param", "1")])).unwrap_err();
+        assert!(err.to_string().starts_with("ERR Unknown option"), "{}", err);
+    }
+
+    #[test]
+    fn get_matching_globs_names() {
+        let config = Config::default();
+        let names: Vec<&str> = config.get_matching("slowlog-*").into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["slowlog-log-slower-than", "slowlog-max-len"]);
+        assert_eq!(config.get_matching("MAXCLIENTS"), [("maxclients", MAX_CONNECTIONS.to_string())]);
+        assert!(config.get_matching("nothing*").is_empty());
+    }
+
+    #[test]
+    fn rewrite_updates_the_file_in_place() {
+        let path = temp_file(
+            "rewrite",
+            "# keep this comment\nport 7000\nunknown-directive stays\nmaxclients 10\nmaxclients 20\n",
+        );
+        let config = Config {
+            port: 7000,
+            maxclients: 30,
+            dir: PathBuf::from("/tmp/my redis"),
+            config_file: Some(path.clone()),
+            ..Config::default()
+        };
+        config.rewrite().unwrap();
+
+        // Only the first `maxclients` is kept, parameters missing from the
+        // file are appended.
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            contents,
+            "# keep this comment\nport 7000\nunknown-directive stays\nmaxclients 30\ndir \"/tmp/my redis\"\n"
+        );
+    }
+
+    #[test]
+    fn rewrite_round_trips_every_parameter() {
+        let path = temp_file("round-trip", "");
+        let mut config = Config::from_file(&path).unwrap();
+        config.requirepass = "with \"quotes\" and \\".to_string();
+        config.dbfilename = "it's.snapshot".to_string();
+        config.client_output_buffer_limit[ClientClass::Normal as usize].hard = 1024;
+        config.rewrite().unwrap();
+
+        let reread = Config::from_file(&path).unwrap();
+        for param in PARAMS {
+            assert_eq!((param.get)(&reread), (param.get)(&config), "{}", param.name);
+        }
+    }
+
+    #[test]
+    fn rewrite_needs_a_config_file() {
+        let err = Config::default().rewrite().unwrap_err();
+        assert_eq!(err.to_string(), "ERR The server is running without a config file");
+    }
+}
@@ -1,15 +1,27 @@
-use crate::frame::{self, Frame};
+use crate::config;
+use crate::frame::{self, Frame, Limits};
 
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::collections::VecDeque;
+use std::fmt;
 use std::io::{self, Cursor};
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio::time::Instant;
+
+/// A transport a `Connection` can run over, such as a `TcpStream` or a TLS
+/// stream. Boxed, it lets a server handle every kind of client the same way.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send + fmt::Debug {}
+
+impl<T> Stream for T where T: AsyncRead + AsyncWrite + Unpin + Send + fmt::Debug {}
 
 /// Send and receive `Frame` values from a remote peer.
 ///
 /// When implementing networking protocols, a message on that protocol is
 /// often composed of several smaller messages known as frames. The purpose of
-/// `Connection` is to read and write frames on the underlying `TcpStream`.
+/// `Connection` is to read and write frames on the underlying stream, a
+/// `TcpStream` unless specified otherwise.
 ///
 /// To read frames, the `Connection` uses an internal buffer, which is filled
 /// up until there are enough bytes to create a full frame. Once this happens,
@@ -18,30 +30,114 @@ use tokio::net::TcpStream;
 /// When sending frames, the frame is first encoded into the write buffer.
 /// The contents of the write buffer are then written to the socket.
 #[derive(Debug)]
-pub struct Connection {
-    // The `TcpStream`. It is decorated with a `BufWriter`, which provides write
-    // level buffering. The `BufWriter` implementation provided by Tokio is
-    // sufficient for our needs.
-    stream: BufWriter<TcpStream>,
+pub struct Connection<S = TcpStream> {
+    // The stream.
+    stream: S,
 
     // The buffer for reading frames.
     buffer: BytesMut,
+
+    // The buffer replies are encoded into before being written. It is kept
+    // around to reuse its allocation.
+    write_buffer: BytesMut,
+
+    // Where each reply still in the write buffer ends, counted in bytes
+    // queued since the connection was created, and how many of those bytes
+    // were written.
+    reply_ends: VecDeque<usize>,
+    written: usize,
+
+    // The RESP version frames are written with, 2 or 3.
+    protocol: u8,
+
+    // Bounds on the frames the peer may send.
+    limits: Limits,
+
+    // Bounds on the frames queued for the peer, and since when they have
+    // been over the soft limit.
+    output_limits: OutputLimits,
+    soft_limit_since: Option<Instant>,
+}
+
+/// Bounds on the bytes a `Connection` holds queued for a peer that doesn't
+/// read them fast enough. A limit of `0` is no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputLimits {
+    /// Queued bytes above which the peer is given up on right away
+    pub hard: usize,
+    /// Queued bytes the peer may stay above for at most `soft_duration`
+    pub soft: usize,
+    pub soft_duration: Duration,
 }
 
-impl Connection {
+/// Error writes fail with, wrapped in an `io::Error`, once the frames queued
+/// for the peer go beyond the `OutputLimits`.
+#[derive(Debug)]
+pub struct OutputLimitExceeded {
+    /// Bytes queued when the limit was found exceeded
+    pub queued: usize,
+}
+
+impl fmt::Display for OutputLimitExceeded {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "output buffer limits exceeded with {} bytes queued", self.queued)
+    }
+}
+
+impl std::error::Error for OutputLimitExceeded {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
     /// Create a new `Connection`, backed by `socket`. Read and write buffers
     /// are initialized.
-    pub fn new(socket: TcpStream) -> Connection {
+    pub fn new(socket: S) -> Connection<S> {
         Connection {
-            stream: BufWriter::new(socket),
+            stream: socket,
             // Default to a 4KB read buffer. For the use case of mini redis,
             // this is fine. However, real applications will want to tune this
             // value to their specific use case. There is a high likelihood that
             // a larger read buffer will work better.
             buffer: BytesMut::with_capacity(4 * 1024),
+            write_buffer: BytesMut::with_capacity(4 * 1024),
+            reply_ends: VecDeque::new(),
+            written: 0,
+            protocol: 2,
+            limits: Limits::default(),
+            output_limits: OutputLimits::default(),
+            soft_limit_since: None,
         }
     }
 
+    /// Bound what the peer may send. A peer going beyond the limits gets a
+    /// protocol error from `read_frame`. Until this is called, there are no
+    /// limits.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Bound the frames queued for the peer. Writes fail with
+    /// `OutputLimitExceeded` once the peer lets them go beyond the limits.
+    /// Until this is called, there are no limits.
+    pub fn set_output_limits(&mut self, limits: OutputLimits) {
+        self.output_limits = limits;
+    }
+
+    /// The RESP version frames are written with.
+    pub fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Write frames with RESP `version`, 2 or 3. Until this is called, a
+    /// connection speaks RESP2.
+    pub fn set_protocol(&mut self, version: u8) {
+        self.protocol = version;
+    }
+
+    /// Bytes waiting in the read buffer, and the room left in it before it
+    /// has to grow.
+    pub fn read_buffer(&self) -> (usize, usize) {
+        (self.buffer.len(), self.buffer.capacity() - self.buffer.len())
+    }
+
     /// Read a single `Frame` value from the underlying stream.
     ///
     /// The function waits until it has retrieved enough data to parse a frame.
@@ -77,6 +173,10 @@ impl Connection {
                     return Err("connection reset by peer".into());
                 }
             }
+
+            if self.buffer.len() > self.limits.max_query_buffer {
+                return Err(frame::Error::from("protocol error; query buffer limit exceeded").into());
+            }
         }
     }
 
@@ -87,18 +187,27 @@ impl Connection {
     fn parse_frame(&mut self) -> crate::Result<Option<Frame>> {
         use frame::Error::Incomplete;
 
+        // Clients such as telnet or health-check probes send commands inline:
+        // a line of space separated arguments rather than an array frame.
+        if let Some(max_len) = self.limits.max_inline_len {
+            if self.buffer.first().is_some_and(|&byte| byte != b'*') {
+                return self.parse_inline(max_len);
+            }
+        }
+
         // Cursor is used to track the "current" location in the
         // buffer. Cursor also implements `Buf` from the `bytes` crate
         // which provides a number of helpful utilities for working
         // with bytes.
         let mut buf = Cursor::new(&self.buffer[..]);
+        let limits = self.limits;
 
         // The first step is to check if enough data has been buffered to parse
         // a single frame. This step is usually much faster than doing a full
         // parse of the frame, and allows us to skip allocating data structures
         // to hold the frame data unless we know the full frame has been
         // received.
-        match Frame::check(&mut buf) {
+        match Frame::check(&mut buf, &limits) {
             Ok(_) => {
                 // The `check` function will have advanced the cursor until the
                 // end of the frame. Since the cursor had position set to zero
@@ -145,92 +254,607 @@ impl Connection {
         }
     }
 
+    /// Tries to parse an inline command from the buffer, turning it into the
+    /// array frame the command would have been sent as. Empty lines are
+    /// skipped. Lines longer than `max_len` are a protocol error.
+    fn parse_inline(&mut self, max_len: usize) -> crate::Result<Option<Frame>> {
+        loop {
+            let end = match self.buffer.iter().position(|&byte| byte == b'\n') {
+                Some(end) => end,
+                None if self.buffer.len() > max_len => {
+                    return Err(frame::Error::from("protocol error; too big inline request").into());
+                }
+                None => return Ok(None),
+            };
+            let line = &self.buffer[..end];
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let line = std::str::from_utf8(line).map_err(|_| frame::Error::from("protocol error; invalid inline command"))?;
+            let args = config::split_args(line)
+                .map_err(|e| frame::Error::from(format!("protocol error; {} in inline command", e)))?;
+            self.buffer.advance(end + 1);
+
+            if !args.is_empty() {
+                return Ok(Some(Frame::Array(args.into_iter().map(|arg| Frame::Bulk(Bytes::from(arg))).collect())));
+            }
+            // A frame may follow the empty line.
+            if self.buffer.first() == Some(&b'*') {
+                return self.parse_frame();
+            }
+        }
+    }
+
+    /// Returns the next frame if it has already been received in full,
+    /// without reading from the stream.
+    ///
+    /// Used to process the frames a client pipelined as a batch.
+    pub fn read_buffered_frame(&mut self) -> crate::Result<Option<Frame>> {
+        self.parse_frame()
+    }
+
     /// Write a single `Frame` value to the underlying stream.
     ///
-    /// The `Frame` value is written to the socket using the various `write_*`
-    /// functions provided by `AsyncWrite`. Calling these functions directly on
-    /// a `TcpStream` is **not** advised, as this will result in a large number of
-    /// syscalls. However, it is fine to call these functions on a *buffered*
-    /// write stream. The data will be written to the buffer. Once the buffer is
-    /// full, it is flushed to the underlying socket.
+    /// The frame is queued with `queue_frame`, then written along with the
+    /// frames queued before it by `flush`.
     pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
-        // Arrays are encoded by encoding each entry. All other frame types are
-        // considered literals. For now, mini-redis is not able to encode
-        // recursive frame structures. See below for more details.
-        match frame {
-            Frame::Array(val) => {
-                // Encode the frame type prefix. For an array, it is `*`.
-                self.stream.write_u8(b'*').await?;
+        self.queue_frame(frame);
+        self.flush().await
+    }
+
+    /// Queue a frame, to be written by the next call to `flush`.
+    ///
+    /// The frame, however deeply nested, is encoded into the write buffer
+    /// without awaiting. On a RESP2 connection, frames of the RESP3 types
+    /// are written as the closest RESP2 type: maps as flat arrays of keys
+    /// and values, booleans as integers, doubles as bulk strings, and so on.
+    /// Attributes are dropped.
+    pub fn queue_frame(&mut self, frame: &Frame) {
+        let queued = self.write_buffer.len();
+        encode(frame, self.protocol, &mut self.write_buffer);
+        if self.write_buffer.len() > queued {
+            self.reply_ends.push_back(self.written + self.write_buffer.len());
+        }
+    }
 
-                // Encode the length of the array.
-                self.write_decimal(val.len() as u64).await?;
+    /// Bytes queued and not written to the stream yet.
+    pub fn queued_len(&self) -> usize {
+        self.write_buffer.len()
+    }
 
-                // Iterate and encode each entry in the array.
-                for entry in &**val {
-                    self.write_value(entry).await?;
-                }
+    /// Replies queued and not entirely written to the stream yet.
+    pub fn queued_replies(&self) -> usize {
+        self.reply_ends.len()
+    }
+
+    /// Write the queued frames to the stream.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        while !self.write_buffer.is_empty() {
+            if let Err(e) = self.write_queued().await {
+                self.write_buffer.clear();
+                self.reply_ends.clear();
+                return Err(e);
             }
-            // The frame type is a literal. Encode the value directly.
-            _ => self.write_value(frame).await?,
+        }
+        Ok(())
+    }
+
+    /// Write as much of the queued frames as the stream takes at once.
+    ///
+    /// Lets a caller keep queueing frames for a peer slower than it. The
+    /// write gives up once the soft output limit has been exceeded for too
+    /// long.
+    pub async fn write_queued(&mut self) -> io::Result<()> {
+        self.check_output_limits()?;
+        let deadline = self.soft_limit_since.map(|since| since + self.output_limits.soft_duration);
+
+        let write = self.stream.write(&self.write_buffer);
+        let written = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, write).await {
+                Ok(res) => res?,
+                Err(_) => return Err(self.output_limit_exceeded()),
+            },
+            None => write.await?,
+        };
+        if written == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        self.write_buffer.advance(written);
+        self.written += written;
+        while self.reply_ends.front().is_some_and(|&end| end <= self.written) {
+            self.reply_ends.pop_front();
         }
 
-        // Ensure the encoded frame is written to the socket. The calls above
-        // are to the buffered stream and writes. Calling `flush` writes the
-        // remaining contents of the buffer to the socket.
-        self.stream.flush().await
+        if self.write_buffer.is_empty() {
+            // Streams such as TLS ones buffer internally. Flushing makes sure
+            // the frames reach the socket.
+            self.stream.flush().await?;
+        }
+        self.check_output_limits()
     }
 
-    /// Write a frame literal to the stream
-    async fn write_value(&mut self, frame: &Frame) -> io::Result<()> {
-        match frame {
-            Frame::Simple(val) => {
-                self.stream.write_u8(b'+').await?;
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(b"\r\n").await?;
+    /// Check the queued frames against the output limits. Fails if they are
+    /// over the hard limit, or have been over the soft limit for longer than
+    /// it allows.
+    pub fn check_output_limits(&mut self) -> io::Result<()> {
+        let queued = self.write_buffer.len();
+        let limits = self.output_limits;
+        if limits.hard != 0 && queued > limits.hard {
+            return Err(self.output_limit_exceeded());
+        }
+        if limits.soft != 0 && queued > limits.soft {
+            let since = *self.soft_limit_since.get_or_insert_with(Instant::now);
+            if since.elapsed() > limits.soft_duration {
+                return Err(self.output_limit_exceeded());
             }
-            Frame::Error(val) => {
-                self.stream.write_u8(b'-').await?;
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(b"\r\n").await?;
+        } else {
+            self.soft_limit_since = None;
+        }
+        Ok(())
+    }
+
+    fn output_limit_exceeded(&self) -> io::Error {
+        io::Error::other(OutputLimitExceeded {
+            queued: self.write_buffer.len(),
+        })
+    }
+}
+
+/// Encode `frame` at the end of `dst`, as RESP `protocol`.
+fn encode(frame: &Frame, protocol: u8, dst: &mut BytesMut) {
+    match frame {
+        Frame::Simple(val) => encode_line(b'+', val.as_bytes(), dst),
+        Frame::Error(val) => encode_line(b'-', val.as_bytes(), dst),
+        Frame::Integer(val) => encode_decimal(b':', *val, dst),
+        Frame::Null if protocol == 2 => dst.put_slice(b"$-1\r\n"),
+        Frame::NullArray if protocol == 2 => dst.put_slice(b"*-1\r\n"),
+        Frame::Null | Frame::NullArray => dst.put_slice(b"_\r\n"),
+        Frame::Bulk(val) => {
+            encode_decimal(b'$', val.len() as u64, dst);
+            dst.put_slice(val);
+            dst.put_slice(b"\r\n");
+        }
+        Frame::Array(val) | Frame::Set(val) | Frame::Push(val) => {
+            let prefix = match (protocol, frame) {
+                (2, _) | (_, Frame::Array(_)) => b'*',
+                (_, Frame::Set(_)) => b'~',
+                _ => b'>',
+            };
+            encode_decimal(prefix, count(val), dst);
+
+            for entry in val {
+                encode(entry, protocol, dst);
             }
-            Frame::Integer(val) => {
-                self.stream.write_u8(b':').await?;
-                self.write_decimal(*val).await?;
+        }
+        Frame::Map(pairs) => {
+            if protocol == 2 {
+                encode_decimal(b'*', pairs.len() as u64 * 2, dst);
+            } else {
+                encode_decimal(b'%', pairs.len() as u64, dst);
             }
-            Frame::Null => {
-                self.stream.write_all(b"$-1\r\n").await?;
+
+            for (key, value) in pairs {
+                encode(key, protocol, dst);
+                encode(value, protocol, dst);
             }
-            Frame::Bulk(val) => {
-                let len = val.len();
+        }
+        Frame::Attribute(_) if protocol == 2 => {}
+        Frame::Attribute(pairs) => {
+            encode_decimal(b'|', pairs.len() as u64, dst);
 
-                self.stream.write_u8(b'$').await?;
-                self.write_decimal(len as u64).await?;
-                self.stream.write_all(val).await?;
-                self.stream.write_all(b"\r\n").await?;
+            for (key, value) in pairs {
+                encode(key, protocol, dst);
+                encode(value, protocol, dst);
             }
-            // Encoding an `Array` from within a value cannot be done using a
-            // recursive strategy. In general, async fns do not support
-            // recursion. Mini-redis has not needed to encode nested arrays yet,
-            // so for now it is skipped.
-            Frame::Array(_val) => unreachable!(),
         }
+        Frame::Boolean(val) if protocol == 2 => encode_decimal(b':', *val as u64, dst),
+        Frame::Boolean(val) => dst.put_slice(if *val { b"#t\r\n" } else { b"#f\r\n" }),
+        Frame::Double(val) => {
+            let text = if val.is_nan() { "nan".to_string() } else { val.to_string() };
+            encode_text(b',', text.as_bytes(), protocol, dst);
+        }
+        Frame::BigNumber(digits) => encode_text(b'(', digits.as_bytes(), protocol, dst),
+        Frame::Verbatim(_, val) if protocol == 2 => encode(&Frame::Bulk(val.clone()), protocol, dst),
+        Frame::Verbatim(format, val) => {
+            encode_decimal(b'=', val.len() as u64 + 4, dst);
+            dst.put_slice(format.as_bytes());
+            dst.put_u8(b':');
+            dst.put_slice(val);
+            dst.put_slice(b"\r\n");
+        }
+    }
+}
 
-        Ok(())
+/// Encode a RESP3 line type, such as a double, with `prefix`. RESP2 has no
+/// such types, so there it is encoded as a bulk string.
+fn encode_text(prefix: u8, text: &[u8], protocol: u8, dst: &mut BytesMut) {
+    if protocol == 2 {
+        encode_decimal(b'$', text.len() as u64, dst);
+        dst.put_slice(text);
+        dst.put_slice(b"\r\n");
+    } else {
+        encode_line(prefix, text, dst);
+    }
+}
+
+/// Encode a line starting with `prefix`.
+fn encode_line(prefix: u8, line: &[u8], dst: &mut BytesMut) {
+    dst.put_u8(prefix);
+    dst.put_slice(line);
+    dst.put_slice(b"\r\n");
+}
+
+/// Encode a decimal line starting with `prefix`, such as an integer or the
+/// header of a bulk string or an array.
+fn encode_decimal(prefix: u8, val: impl fmt::Display, dst: &mut BytesMut) {
+    use std::fmt::Write;
+
+    dst.put_u8(prefix);
+    // Writing to a `BytesMut` can't fail.
+    let _ = write!(dst, "{}", val);
+    dst.put_slice(b"\r\n");
+}
+
+/// The number of entries of an aggregate frame, as announced in its header.
+/// Attributes don't count: they annotate the entry that follows them.
+fn count(entries: &[Frame]) -> u64 {
+    entries.iter().filter(|entry| !matches!(entry, Frame::Attribute(_))).count() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::DuplexStream;
+
+    /// The limits of a server reading commands.
+    fn requests() -> Limits {
+        Limits {
+            max_inline_len: Some(64),
+            ..Limits::default()
+        }
     }
 
-    /// Write a decimal frame to the stream
-    async fn write_decimal(&mut self, val: u64) -> io::Result<()> {
-        use std::io::Write;
+    /// A connection that reads `input`, then the end of the stream.
+    async fn open(input: &[u8], limits: Limits) -> Connection<DuplexStream> {
+        let (ours, mut theirs) = tokio::io::duplex(64 * 1024);
+        theirs.write_all(input).await.unwrap();
+        drop(theirs);
+        let mut connection = Connection::new(ours);
+        connection.set_limits(limits);
+        connection
+    }
 
-        // Convert the value to a string
-        let mut buf = [0u8; 20];
-        let mut buf = Cursor::new(&mut buf[..]);
-        write!(&mut buf, "{}", val)?;
+    fn args(frame: Frame) -> Vec<String> {
+        match frame {
+            Frame::Array(entries) => entries
+                .into_iter()
+                .map(|entry| match entry {
+                    Frame::Bulk(bytes) => String::from_utf8(bytes.to_vec()).unwrap(),
+                    entry => panic!("unexpected entry {:?}", entry),
+                })
+                .collect(),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    /// Every command in `input`, read with the limits of a server.
+    async fn commands(input: &[u8]) -> crate::Result<Vec<Vec<String>>> {
+        let mut connection = open(input, requests()).await;
+        let mut commands = Vec::new();
+        while let Some(frame) = connection.read_frame().await? {
+            commands.push(args(frame));
+        }
+        Ok(commands)
+    }
 
-        let pos = buf.position() as usize;
-        self.stream.write_all(&buf.get_ref()[..pos]).await?;
-        self.stream.write_all(b"\r\n").await?;
+    #[tokio::test]
+    async fn inline_commands() {
+        assert_eq!(commands(b"set key value\r\n").await.unwrap(), vec![vec!["set", "key", "value"]]);
+        assert_eq!(commands(b"  get   key  \n").await.unwrap(), vec![vec!["get", "key"]]);
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn inline_quotes_and_escapes() {
+        let parsed = commands(b"set \"a key\" 'single \"quoted\"' \"\"\r\n").await.unwrap();
+        assert_eq!(parsed, vec![vec!["set", "a key", "single \"quoted\"", ""]]);
+
+        let parsed = commands(b"set k \"line\\nnext\\t\\\"\\\\\"\r\n").await.unwrap();
+        assert_eq!(parsed, vec![vec!["set", "k", "line\nnext\t\"\\"]]);
+
+        // Backslashes are only special between double quotes.
+        let parsed = commands(b"set k 'a\\nb' c\\n\r\n").await.unwrap();
+        assert_eq!(parsed, vec![vec!["set", "k", "a\\nb", "c\\n"]]);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn inline_unbalanced_quotes() {
+        let err = commands(b"set k \"value\r\n").await.unwrap_err();
+        assert!(err.to_string().contains("unbalanced quotes"), "{}", err);
+
+        let err = commands(b"set k 'value\r\n").await.unwrap_err();
+        assert!(err.to_string().contains("unbalanced quotes"), "{}", err);
+
+        let err = commands(b"set \"k\"v value\r\n").await.unwrap_err();
+        assert!(err.to_string().contains("closing quote"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn inline_empty_lines_are_skipped() {
+        assert!(commands(b"\r\n\n   \r\n").await.unwrap().is_empty());
+
+        let parsed = commands(b"\r\nping\r\n\r\n*2\r\n$3\r\nget\r\n$1\r\nk\r\nexists k\r\n").await.unwrap();
+        assert_eq!(parsed, vec![vec!["ping"], vec!["get", "k"], vec!["exists", "k"]]);
+    }
+
+    #[tokio::test]
+    async fn inline_length_limit() {
+        let mut line = vec![b'a'; 65];
+        let err = commands(&line).await.unwrap_err();
+        assert!(err.to_string().contains("too big inline request"), "{}", err);
+
+        line.truncate(60);
+        line.extend_from_slice(b"\r\n");
+        assert_eq!(commands(&line).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn only_arrays_are_frames_on_requests() {
+        // On the request side, any other byte starts an inline command.
+        for input in [&b"+ping\r\n"[..], b":1\r\n", b"$4\r\n", b"-err\r\n"] {
+            let parsed = commands(input).await.unwrap();
+            let line = std::str::from_utf8(&input[..input.len() - 2]).unwrap();
+            assert_eq!(parsed, vec![vec![line]]);
+        }
+    }
+
+    #[tokio::test]
+    async fn query_buffer_limit() {
+        let limits = Limits {
+            max_query_buffer: 16,
+            ..requests()
+        };
+
+        // A frame can't grow the buffer past the limit while it arrives.
+        let mut connection = open(b"*1\r\n$100\r\naaaaaaaaaaaaaaaaaaaa", limits).await;
+        let err = connection.read_frame().await.unwrap_err();
+        assert!(err.to_string().contains("query buffer limit exceeded"), "{}", err);
+
+        let mut connection = open(b"*1\r\n$4\r\nping\r\n", limits).await;
+        assert_eq!(args(connection.read_frame().await.unwrap().unwrap()), vec!["ping"]);
+    }
+
+    #[tokio::test]
+    async fn oversized_requests_are_rejected() {
+        let limits = Limits {
+            max_bulk_len: 8,
+            max_multibulk_len: 2,
+            ..requests()
+        };
+
+        let mut connection = open(b"*1\r\n$9\r\n", limits).await;
+        let err = connection.read_frame().await.unwrap_err();
+        assert!(err.to_string().contains("invalid bulk length"), "{}", err);
+
+        let mut connection = open(b"*3\r\n", limits).await;
+        let err = connection.read_frame().await.unwrap_err();
+        assert!(err.to_string().contains("invalid multibulk length"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn replies_are_never_inline() {
+        let mut connection = open(b"+OK\r\n:-1\r\n$-1\r\nnot a frame\r\n", Limits::default()).await;
+        assert_eq!(connection.read_frame().await.unwrap().unwrap(), "OK");
+        assert!(matches!(connection.read_frame().await.unwrap(), Some(Frame::Integer(-1))));
+        assert!(matches!(connection.read_frame().await.unwrap(), Some(Frame::Null)));
+        assert!(connection.read_frame().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn queued_replies_are_counted_until_written() {
+        // The peer takes at most 8 bytes at a time.
+        let (ours, mut theirs) = tokio::io::duplex(8);
+        let mut connection = Connection::new(ours);
+        connection.queue_frame(&Frame::Simple("OK".to_string()));
+        connection.queue_frame(&Frame::Bulk(Bytes::from("hello")));
+        assert_eq!(connection.queued_len(), 16);
+        assert_eq!(connection.queued_replies(), 2);
+
+        // `+OK\r\n` and the start of the bulk string.
+        connection.write_queued().await.unwrap();
+        assert_eq!(connection.queued_len(), 8);
+        assert_eq!(connection.queued_replies(), 1);
+
+        let mut buf = [0; 8];
+        theirs.read_exact(&mut buf).await.unwrap();
+        connection.write_queued().await.unwrap();
+        assert_eq!(connection.queued_len(), 0);
+        assert_eq!(connection.queued_replies(), 0);
+    }
+
+    #[tokio::test]
+    async fn null_arrays() {
+        let (ours, mut theirs) = tokio::io::duplex(64);
+        let mut connection = Connection::new(ours);
+        connection.write_frame(&Frame::NullArray).await.unwrap();
+        connection.set_protocol(3);
+        connection.write_frame(&Frame::NullArray).await.unwrap();
+        let mut buf = [0; 8];
+        theirs.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"*-1\r\n_\r\n");
+
+        let mut connection = open(b"*-1\r\n*-2\r\n", Limits::default()).await;
+        assert!(matches!(connection.read_frame().await.unwrap(), Some(Frame::NullArray)));
+        assert!(connection.read_frame().await.is_err());
+    }
+
+    fn encoded(frame: &Frame, protocol: u8) -> String {
+        let mut dst = BytesMut::new();
+        encode(frame, protocol, &mut dst);
+        String::from_utf8(dst.to_vec()).unwrap()
+    }
+
+    /// Frames of every RESP3 type, nested.
+    fn resp3_frames() -> Frame {
+        Frame::Map(vec![
+            (Frame::Simple("set".to_string()), Frame::Set(vec![Frame::Integer(1)])),
+            (Frame::Simple("push".to_string()), Frame::Push(vec![Frame::Bulk(Bytes::from("msg"))])),
+            (Frame::Simple("bool".to_string()), Frame::Boolean(true)),
+            (Frame::Simple("double".to_string()), Frame::Double(-1.5)),
+            (Frame::Simple("inf".to_string()), Frame::Double(f64::INFINITY)),
+            (Frame::Simple("big".to_string()), Frame::BigNumber("12345678901234567890".to_string())),
+            (Frame::Simple("text".to_string()), Frame::Verbatim("txt".to_string(), Bytes::from("hi"))),
+            (Frame::Simple("null".to_string()), Frame::Null),
+            (
+                Frame::Simple("annotated".to_string()),
+                Frame::Array(vec![
+                    Frame::Attribute(vec![(Frame::Simple("ttl".to_string()), Frame::Integer(10))]),
+                    Frame::Simple("value".to_string()),
+                ]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn resp3_encoding() {
+        assert_eq!(
+            encoded(&resp3_frames(), 3),
+            "%9\r\n\
+             +set\r\n~1\r\n:1\r\n\
+             +push\r\n>1\r\n$3\r\nmsg\r\n\
+             +bool\r\n#t\r\n\
+             +double\r\n,-1.5\r\n\
+             +inf\r\n,inf\r\n\
+             +big\r\n(12345678901234567890\r\n\
+             +text\r\n=6\r\ntxt:hi\r\n\
+             +null\r\n_\r\n\
+             +annotated\r\n*1\r\n|1\r\n+ttl\r\n:10\r\n+value\r\n"
+        );
+    }
+
+    #[test]
+    fn resp3_frames_are_downgraded_for_resp2() {
+        assert_eq!(
+            encoded(&resp3_frames(), 2),
+            "*18\r\n\
+             +set\r\n*1\r\n:1\r\n\
+             +push\r\n*1\r\n$3\r\nmsg\r\n\
+             +bool\r\n:1\r\n\
+             +double\r\n$4\r\n-1.5\r\n\
+             +inf\r\n$3\r\ninf\r\n\
+             +big\r\n$20\r\n12345678901234567890\r\n\
+             +text\r\n$2\r\nhi\r\n\
+             +null\r\n$-1\r\n\
+             +annotated\r\n*1\r\n+value\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn resp3_round_trip() {
+        let (ours, theirs) = tokio::io::duplex(64 * 1024);
+        let mut writer = Connection::new(ours);
+        let mut reader = Connection::new(theirs);
+        writer.set_protocol(3);
+        writer.write_frame(&resp3_frames()).await.unwrap();
+        let frame = reader.read_frame().await.unwrap().unwrap();
+        assert_eq!(format!("{:?}", frame), format!("{:?}", resp3_frames()));
+    }
+
+    #[tokio::test]
+    async fn batches_are_read_from_the_buffer_and_flushed_once() {
+        let (ours, mut theirs) = tokio::io::duplex(1024);
+        let mut connection = Connection::new(ours);
+        theirs.write_all(b"*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nPI").await.unwrap();
+
+        // The first read fills the buffer, the rest of the batch is taken
+        // from it without touching the stream.
+        assert_eq!(args(connection.read_frame().await.unwrap().unwrap()), ["PING"]);
+        connection.queue_frame(&Frame::Simple("PONG".to_string()));
+        assert_eq!(args(connection.read_buffered_frame().unwrap().unwrap()), ["PING"]);
+        connection.queue_frame(&Frame::Simple("PONG".to_string()));
+        assert!(connection.read_buffered_frame().unwrap().is_none());
+
+        // Nothing is written until the batch is flushed.
+        let mut buf = [0; 14];
+        assert!(tokio::time::timeout(Duration::from_millis(10), theirs.read(&mut buf)).await.is_err());
+        connection.flush().await.unwrap();
+        theirs.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"+PONG\r\n+PONG\r\n");
+    }
+
+    fn is_output_limit_exceeded(err: &io::Error) -> bool {
+        err.get_ref().is_some_and(|e| e.is::<OutputLimitExceeded>())
+    }
+
+    /// A connection to a peer that never reads, with `bytes` bytes queued.
+    fn stalled(limits: OutputLimits, bytes: usize) -> (Connection<DuplexStream>, DuplexStream) {
+        let (ours, theirs) = tokio::io::duplex(8);
+        let mut connection = Connection::new(ours);
+        connection.set_output_limits(limits);
+        connection.queue_frame(&Frame::Simple("x".repeat(bytes - 3)));
+        assert_eq!(connection.queued_len(), bytes);
+        (connection, theirs)
+    }
+
+    #[tokio::test]
+    async fn hard_output_limit() {
+        let limits = OutputLimits {
+            hard: 100,
+            ..OutputLimits::default()
+        };
+        let (mut connection, _peer) = stalled(limits, 100);
+        assert!(connection.check_output_limits().is_ok());
+
+        connection.queue_frame(&Frame::Integer(1));
+        let err = connection.write_queued().await.unwrap_err();
+        assert!(is_output_limit_exceeded(&err), "{}", err);
+        assert_eq!(err.to_string(), "output buffer limits exceeded with 104 bytes queued");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn soft_output_limit() {
+        let limits = OutputLimits {
+            soft: 50,
+            soft_duration: Duration::from_secs(10),
+            ..OutputLimits::default()
+        };
+        let (mut connection, _peer) = stalled(limits, 100);
+
+        // Over the soft limit is fine for a while.
+        connection.write_queued().await.unwrap();
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(connection.check_output_limits().is_ok());
+
+        // The write waiting for the peer gives up once the time is up.
+        let waiting = Instant::now();
+        let err = connection.write_queued().await.unwrap_err();
+        assert!(is_output_limit_exceeded(&err), "{}", err);
+        assert_eq!(waiting.elapsed(), Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn soft_output_limit_resets_below_it() {
+        let limits = OutputLimits {
+            soft: 50,
+            soft_duration: Duration::from_secs(10),
+            ..OutputLimits::default()
+        };
+        let (mut connection, mut peer) = stalled(limits, 60);
+        assert!(connection.check_output_limits().is_ok());
+        tokio::time::advance(Duration::from_secs(9)).await;
+
+        // The peer catches up.
+        let reader = tokio::spawn(async move {
+            let mut buf = [0; 60];
+            peer.read_exact(&mut buf).await.unwrap();
+            peer
+        });
+        connection.flush().await.unwrap();
+        let _peer = reader.await.unwrap();
+
+        // Going over again starts a new period.
+        connection.queue_frame(&Frame::Simple("x".repeat(57)));
+        assert!(connection.check_output_limits().is_ok());
+        tokio::time::advance(Duration::from_secs(9)).await;
+        assert!(connection.check_output_limits().is_ok());
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert!(connection.check_output_limits().is_err());
+    }
+}
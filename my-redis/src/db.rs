@@ -6,6 +6,7 @@ use std::collections::{BTreeSet, HashMap, LinkedList};
 
 use std::sync::{Arc, Mutex};
 use tracing::debug;
+/// Default number of databases.
 pub const NUM_DBS: usize = 16;
 use tokio::sync::mpsc::{Receiver, Sender};
 #[derive( Debug)]
@@ -46,74 +47,31 @@ impl Clone for DataTypes {
 
 pub type Db = Arc<Mutex<HashMap<String, DataTypes>>>;
 
+/// All logical databases of the server, addressed by index through `SELECT`.
 #[derive(Debug, Clone)]
 pub struct AllDbs {
-    db0: Db,
-    db1: Db,
-    db2: Db,
-    db3: Db,
-    db4: Db,
-    db5: Db,
-    db6: Db,
-    db7: Db,
-    db8: Db,
-    db9: Db,
-    db10: Db,
-    db11: Db,
-    db12: Db,
-    db13: Db,
-    db14: Db,
-    db15: Db,
+    dbs: Vec<Db>,
 }
 
 
 impl AllDbs {
-    pub fn new() -> AllDbs {
+    /// Create `count` empty databases.
+    pub fn new(count: usize) -> AllDbs {
         AllDbs {
-            db0: Db::new(Mutex::new(Default::default())),
-            db1: Db::new(Mutex::new(Default::default())),
-            db2: Db::new(Mutex::new(Default::default())),
-            db3: Db::new(Mutex::new(Default::default())),
-            db4: Db::new(Mutex::new(Default::default())),
-            db5: Db::new(Mutex::new(Default::default())),
-            db6: Db::new(Mutex::new(Default::default())),
-            db7: Db::new(Mutex::new(Default::default())),
-            db8: Db::new(Mutex::new(Default::default())),
-            db9: Db::new(Mutex::new(Default::default())),
-            db10: Db::new(Mutex::new(Default::default())),
-            db11: Db::new(Mutex::new(Default::default())),
-            db12: Db::new(Mutex::new(Default::default())),
-            db13: Db::new(Mutex::new(Default::default())),
-            db14: Db::new(Mutex::new(Default::default())),
-            db15: Db::new(Mutex::new(Default::default())),
+            dbs: (0..count).map(|_| Db::new(Mutex::new(Default::default()))).collect(),
         }
     }
 
     pub fn get_instance(&self, index: usize) -> Option<Db> {
-        match index {
-            0 => Some(Arc::clone(&self.db0)),
-            1 => Some(Arc::clone(&self.db1)),
-            2 => Some(Arc::clone(&self.db2)),
-            3 => Some(Arc::clone(&self.db3)),
-            4 => Some(Arc::clone(&self.db4)),
-            5 => Some(Arc::clone(&self.db5)),
-            6 => Some(Arc::clone(&self.db6)),
-            7 => Some(Arc::clone(&self.db7)),
-            8 => Some(Arc::clone(&self.db8)),
-            9 => Some(Arc::clone(&self.db9)),
-            10 => Some(Arc::clone(&self.db10)),
-            11 => Some(Arc::clone(&self.db11)),
-            12 => Some(Arc::clone(&self.db12)),
-            13 => Some(Arc::clone(&self.db13)),
-            14 => Some(Arc::clone(&self.db14)),
-            15 => Some(Arc::clone(&self.db15)),
-            _ => None,
-        }
+        self.dbs.get(index).map(Arc::clone)
     }
 
+    /// Number of databases
+    pub fn len(&self) -> usize {
+        self.dbs.len()
+    }
 
-
+    pub fn is_empty(&self) -> bool {
+        self.dbs.is_empty()
+    }
 }
-
-
-
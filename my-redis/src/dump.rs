@@ -0,0 +1,224 @@
+//! Serialization of stored values.
+//!
+//! Used by `DUMP`, `RESTORE` and `MIGRATE` to move values between server
+//! instances. A payload is laid out as follows:
+//!
+//! ```text
+//! | type tag (1) | value | format version (2, LE) | CRC64 (8, LE) |
+//! ```
+//!
+//! The value encoding depends on the type tag. Lengths inside the value are
+//! encoded as big-endian `u32`. The CRC64 (Jones polynomial, as used by Redis)
+//! covers everything before it.
+
+use crate::db::DataTypes;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::collections::LinkedList;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+
+/// Version of the value encoding. Payloads from a newer version are rejected.
+pub const DUMP_VERSION: u16 = 1;
+
+/// Size of the version and checksum footer.
+const FOOTER_LEN: usize = 2 + 8;
+
+/// Serialize `value` into a self-describing payload.
+///
+/// Returns `None` for values that cannot leave the server, i.e. the waiter
+/// registrations of blocked clients.
+pub fn serialize(value: &DataTypes) -> Option<Bytes> {
+    let mut buf = BytesMut::new();
+    match value {
+        DataTypes::BytesInDb(bytes) => {
+            buf.put_u8(TYPE_STRING);
+            put_bytes(&mut buf, bytes);
+        }
+        DataTypes::List(list) => {
+            buf.put_u8(TYPE_LIST);
+            buf.put_u32(list.len() as u32);
+            for item in list {
+                put_bytes(&mut buf, item);
+            }
+        }
+        DataTypes::SenderList(_) => return None,
+    }
+    buf.put_u16_le(DUMP_VERSION);
+    let crc = crc64(&buf);
+    buf.put_u64_le(crc);
+    Some(buf.freeze())
+}
+
+/// Decode a payload produced by `serialize`.
+///
+/// The checksum and format version are verified before the value is
+/// decoded.
+pub fn deserialize(payload: &[u8]) -> crate::Result<DataTypes> {
+    if payload.len() < 1 + FOOTER_LEN {
+        return Err("ERR DUMP payload version or checksum are wrong".into());
+    }
+    let (body, mut crc) = payload.split_at(payload.len() - 8);
+    if crc.get_u64_le() != crc64(body) {
+        return Err("ERR DUMP payload version or checksum are wrong".into());
+    }
+    let (mut src, mut version) = body.split_at(body.len() - 2);
+    if version.get_u16_le() > DUMP_VERSION {
+        return Err("ERR DUMP payload version or checksum are wrong".into());
+    }
+
+    let value = match src.get_u8() {
+        TYPE_STRING => DataTypes::BytesInDb(get_bytes(&mut src)?),
+        TYPE_LIST => {
+            let len = get_u32(&mut src)?;
+            let mut list = LinkedList::new();
+            for _ in 0..len {
+                list.push_back(get_bytes(&mut src)?);
+            }
+            DataTypes::List(list)
+        }
+        tag => return Err(format!("ERR Bad data format, unknown type tag {}", tag).into()),
+    };
+
+    if src.has_remaining() {
+        return Err("ERR Bad data format, trailing bytes".into());
+    }
+    Ok(value)
+}
+
+fn put_bytes(buf: &mut BytesMut, bytes: &[u8]) {
+    buf.put_u32(bytes.len() as u32);
+    buf.put_slice(bytes);
+}
+
+fn get_u32(src: &mut &[u8]) -> crate::Result<u32> {
+    if src.remaining() < 4 {
+        return Err("ERR Bad data format, truncated payload".into());
+    }
+    Ok(src.get_u32())
+}
+
+fn get_bytes(src: &mut &[u8]) -> crate::Result<Bytes> {
+    let len = get_u32(src)? as usize;
+    if src.remaining() < len {
+        return Err("ERR Bad data format, truncated payload".into());
+    }
+    let bytes = Bytes::copy_from_slice(&src[..len]);
+    src.advance(len);
+    Ok(bytes)
+}
+
+/// CRC64 with the Jones polynomial, reflected input and output, as used by
+/// Redis for DUMP payloads.
+fn crc64(buf: &[u8]) -> u64 {
+    // 0xad93d23594c935a9 with its bits reversed.
+    const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+    let mut crc: u64 = 0;
+    for &byte in buf {
+        crc ^= byte as u64;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ POLY } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a payload around `body`, with a valid checksum.
+    fn payload(body: &[u8], version: u16) -> Vec<u8> {
+        let mut buf = BytesMut::from(body);
+        buf.put_u16_le(version);
+        let crc = crc64(&buf);
+        buf.put_u64_le(crc);
+        buf.to_vec()
+    }
+
+    fn error(result: crate::Result<DataTypes>) -> String {
+        match result {
+            Ok(value) => panic!("expected an error, got {:?}", value),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn crc64_jones() {
+        assert_eq!(crc64(b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+        assert_eq!(crc64(b""), 0);
+    }
+
+    #[test]
+    fn string_round_trip() {
+        for value in [&b""[..], b"hello", &[0, 255, b'\r', b'\n']] {
+            let payload = serialize(&DataTypes::BytesInDb(Bytes::copy_from_slice(value))).unwrap();
+            match deserialize(&payload).unwrap() {
+                DataTypes::BytesInDb(bytes) => assert_eq!(bytes, value),
+                other => panic!("unexpected value {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn list_round_trip() {
+        let list: LinkedList<Bytes> = ["a", "", "ccc"].iter().map(|item| Bytes::from(*item)).collect();
+        let payload = serialize(&DataTypes::List(list.clone())).unwrap();
+        match deserialize(&payload).unwrap() {
+            DataTypes::List(decoded) => assert_eq!(decoded, list),
+            other => panic!("unexpected value {:?}", other),
+        }
+
+        let payload = serialize(&DataTypes::List(LinkedList::new())).unwrap();
+        assert!(matches!(deserialize(&payload).unwrap(), DataTypes::List(list) if list.is_empty()));
+    }
+
+    #[test]
+    fn layout() {
+        let payload = serialize(&DataTypes::BytesInDb(Bytes::from("ab"))).unwrap();
+        assert_eq!(&payload[..7], &[TYPE_STRING, 0, 0, 0, 2, b'a', b'b']);
+        assert_eq!(&payload[7..9], &DUMP_VERSION.to_le_bytes());
+        assert_eq!(&payload[9..], &crc64(&payload[..9]).to_le_bytes());
+    }
+
+    #[test]
+    fn corrupted_payloads_are_rejected() {
+        let payload = serialize(&DataTypes::BytesInDb(Bytes::from("hello"))).unwrap().to_vec();
+
+        for at in 0..payload.len() {
+            let mut corrupted = payload.clone();
+            corrupted[at] ^= 0x01;
+            let msg = error(deserialize(&corrupted));
+            assert!(msg.contains("checksum"), "byte {}: {}", at, msg);
+        }
+        for len in 0..payload.len() {
+            assert!(deserialize(&payload[..len]).is_err(), "accepted {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let body = [TYPE_STRING, 0, 0, 0, 1, b'x'];
+        assert!(deserialize(&payload(&body, DUMP_VERSION)).is_ok());
+        let msg = error(deserialize(&payload(&body, DUMP_VERSION + 1)));
+        assert!(msg.contains("version"), "{}", msg);
+    }
+
+    #[test]
+    fn bad_data_is_rejected() {
+        // A valid checksum doesn't make the value valid.
+        let msg = error(deserialize(&payload(&[9, 0, 0, 0, 0], DUMP_VERSION)));
+        assert!(msg.contains("unknown type tag 9"), "{}", msg);
+
+        let msg = error(deserialize(&payload(&[TYPE_STRING, 0, 0, 0, 5, b'x'], DUMP_VERSION)));
+        assert!(msg.contains("truncated"), "{}", msg);
+
+        let msg = error(deserialize(&payload(&[TYPE_LIST, 0, 0, 0, 2, 0, 0, 0, 0], DUMP_VERSION)));
+        assert!(msg.contains("truncated"), "{}", msg);
+
+        let msg = error(deserialize(&payload(&[TYPE_STRING, 0, 0, 0, 0, b'x'], DUMP_VERSION)));
+        assert!(msg.contains("trailing"), "{}", msg);
+    }
+}
@@ -1,5 +1,9 @@
 //! Provides a type representing a Redis protocol frame as well as utilities for
 //! parsing frames from a byte array.
+//!
+//! Both RESP2 and RESP3 frames are understood. Frames of the types RESP3
+//! added can still be sent to a RESP2 client: `Connection` encodes them as
+//! the closest RESP2 type.
 
 use bytes::{Buf, Bytes};
 use std::convert::TryInto;
@@ -13,10 +17,56 @@ use std::string::FromUtf8Error;
 pub enum Frame {
     Simple(String),
     Error(String),
-    Integer(u64),
+    Integer(i64),
     Bulk(Bytes),
     Null,
+    /// The null RESP2 has for arrays, written `*-1`. RESP3 has a single null.
+    NullArray,
     Array(Vec<Frame>),
+    /// RESP3 only, like the other variants below
+    Map(Vec<(Frame, Frame)>),
+    Set(Vec<Frame>),
+    Double(f64),
+    Boolean(bool),
+    /// The decimal digits, with an optional leading `-`
+    BigNumber(String),
+    /// A string with a three letter format, such as `txt` or `mkd`
+    Verbatim(String, Bytes),
+    /// Out of band data, such as a pub/sub message
+    Push(Vec<Frame>),
+    /// Auxiliary information about the reply that follows
+    Attribute(Vec<(Frame, Frame)>),
+}
+
+/// Bounds on what a peer may send.
+///
+/// The lengths announced in frame headers are checked before the data they
+/// announce is waited for, so a peer can't make a connection buffer without
+/// limit.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Longest bulk string accepted
+    pub max_bulk_len: u64,
+    /// Most entries accepted in an array, or in any other aggregate
+    pub max_multibulk_len: u64,
+    /// Most bytes a connection buffers while waiting for a complete frame
+    pub max_query_buffer: usize,
+    /// Longest inline command accepted, or `None` to only accept frames.
+    /// Inline commands are sent by clients: every reply starts with the
+    /// type byte of its frame.
+    pub max_inline_len: Option<usize>,
+}
+
+impl Default for Limits {
+    /// No limits, for reading the replies of a trusted server.
+    fn default() -> Limits {
+        Limits {
+            max_bulk_len: u64::MAX,
+            max_multibulk_len: u64::MAX,
+            max_query_buffer: usize::MAX,
+            max_inline_len: None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -53,7 +103,7 @@ impl Frame {
     /// # Panics
     ///
     /// panics if `self` is not an array
-    pub(crate) fn push_int(&mut self, value: u64) {
+    pub(crate) fn push_int(&mut self, value: i64) {
         match self {
             Frame::Array(vec) => {
                 vec.push(Frame::Integer(value));
@@ -62,8 +112,23 @@ impl Frame {
         }
     }
 
-    /// Checks if an entire message can be decoded from `src`
-    pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+    /// Push a nested frame into the array. `self` must be an Array frame.
+    ///
+    /// # Panics
+    ///
+    /// panics if `self` is not an array
+    pub(crate) fn push_frame(&mut self, frame: Frame) {
+        match self {
+            Frame::Array(vec) => {
+                vec.push(frame);
+            }
+            _ => panic!("not an array frame"),
+        }
+    }
+
+    /// Checks if an entire message can be decoded from `src`, and that it
+    /// lies within `limits`.
+    pub fn check(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<(), Error> {
         match get_u8(src)? {
             b'+' => {
                 get_line(src)?;
@@ -74,7 +139,7 @@ impl Frame {
                 Ok(())
             }
             b':' => {
-                let _ = get_decimal(src)?;
+                get_line(src)?;
                 Ok(())
             }
             b'$' => {
@@ -83,21 +148,44 @@ impl Frame {
                     skip(src, 4)
                 } else {
                     // Read the bulk string
-                    let len: usize = get_decimal(src)?.try_into()?;
+                    let len: usize = get_bulk_len(src, limits)?;
 
                     // skip that number of bytes + 2 (\r\n).
-                    skip(src, len + 2)
+                    skip(src, with_crlf(len)?)
+                }
+            }
+            b'*' if b'-' == peek_u8(src)? => {
+                // Skip '-1\r\n'
+                skip(src, 4)
+            }
+            b'*' | b'~' | b'>' => {
+                let len = get_multibulk_len(src, limits)?;
+
+                for _ in 0..len {
+                    check_entry(src, limits)?;
                 }
+
+                Ok(())
             }
-            b'*' => {
-                let len = get_decimal(src)?;
+            b'%' | b'|' => {
+                let len = get_multibulk_len(src, limits)?;
 
+                // Every entry is a key followed by its value.
+                let len = len.checked_mul(2).ok_or("protocol error; invalid multibulk length")?;
                 for _ in 0..len {
-                    Frame::check(src)?;
+                    check_entry(src, limits)?;
                 }
 
                 Ok(())
             }
+            b',' | b'#' | b'(' | b'_' => {
+                get_line(src)?;
+                Ok(())
+            }
+            b'=' => {
+                let len: usize = get_bulk_len(src, limits)?;
+                skip(src, with_crlf(len)?)
+            }
             actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
         }
     }
@@ -124,8 +212,9 @@ impl Frame {
                 Ok(Frame::Error(string))
             }
             b':' => {
-                let len = get_decimal(src)?;
-                Ok(Frame::Integer(len))
+                let line = get_line(src)?;
+                let value = atoi::atoi::<i64>(line).ok_or("protocol error; invalid frame format")?;
+                Ok(Frame::Integer(value))
             }
             b'$' => {
                 if b'-' == peek_u8(src)? {
@@ -139,7 +228,7 @@ impl Frame {
                 } else {
                     // Read the bulk string
                     let len = get_decimal(src)?.try_into()?;
-                    let n = len + 2;
+                    let n = with_crlf(len)?;
 
                     if src.remaining() < n {
                         return Err(Error::Incomplete);
@@ -153,17 +242,74 @@ impl Frame {
                     Ok(Frame::Bulk(data))
                 }
             }
-            b'*' => {
-                let len = get_decimal(src)?.try_into()?;
-                let mut out = Vec::with_capacity(len);
-
-                for _ in 0..len {
-                    out.push(Frame::parse(src)?);
+            b'*' if b'-' == peek_u8(src)? => {
+                if get_line(src)? != b"-1" {
+                    return Err("protocol error; invalid frame format".into());
                 }
-
-                Ok(Frame::Array(out))
+                Ok(Frame::NullArray)
+            }
+            b'*' => Ok(Frame::Array(parse_entries(src)?)),
+            b'~' => Ok(Frame::Set(parse_entries(src)?)),
+            b'>' => Ok(Frame::Push(parse_entries(src)?)),
+            b'%' => Ok(Frame::Map(parse_pairs(src)?)),
+            b'|' => Ok(Frame::Attribute(parse_pairs(src)?)),
+            b',' => {
+                let line = std::str::from_utf8(get_line(src)?).map_err(|_| "protocol error; invalid double")?;
+                // Rust spells infinity `inf`, which is what RESP3 uses.
+                let value = line.parse::<f64>().map_err(|_| "protocol error; invalid double")?;
+                Ok(Frame::Double(value))
             }
-            _ => unimplemented!(),
+            b'#' => match get_line(src)? {
+                b"t" => Ok(Frame::Boolean(true)),
+                b"f" => Ok(Frame::Boolean(false)),
+                _ => Err("protocol error; invalid boolean".into()),
+            },
+            b'(' => {
+                let line = String::from_utf8(get_line(src)?.to_vec())?;
+                let digits = line.strip_prefix('-').unwrap_or(&line);
+                if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err("protocol error; invalid big number".into());
+                }
+                Ok(Frame::BigNumber(line))
+            }
+            b'_' => {
+                if !get_line(src)?.is_empty() {
+                    return Err("protocol error; invalid frame format".into());
+                }
+                Ok(Frame::Null)
+            }
+            b'=' => {
+                let len: usize = get_decimal(src)?.try_into()?;
+                let n = with_crlf(len)?;
+                if len < 4 || src.remaining() < n {
+                    return Err("protocol error; invalid verbatim string".into());
+                }
+                let data = &src.chunk()[..len];
+                if data[3] != b':' {
+                    return Err("protocol error; invalid verbatim string".into());
+                }
+                let format = String::from_utf8(data[..3].to_vec())?;
+                let data = Bytes::copy_from_slice(&data[4..]);
+                skip(src, n)?;
+                Ok(Frame::Verbatim(format, data))
+            }
+            actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
+        }
+    }
+
+    /// Returns the arguments of a command frame: the string and bulk
+    /// entries of an array. Other frames have no arguments.
+    pub fn args(&self) -> Vec<Bytes> {
+        match self {
+            Frame::Array(entries) => entries
+                .iter()
+                .filter_map(|entry| match entry {
+                    Frame::Bulk(bytes) => Some(bytes.clone()),
+                    Frame::Simple(string) => Some(Bytes::from(string.clone())),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
         }
     }
 
@@ -195,8 +341,8 @@ impl fmt::Display for Frame {
                 Ok(string) => string.fmt(fmt),
                 Err(_) => write!(fmt, "{:?}", msg),
             },
-            Frame::Null => "(nil)".fmt(fmt),
-            Frame::Array(parts) => {
+            Frame::Null | Frame::NullArray => "(nil)".fmt(fmt),
+            Frame::Array(parts) | Frame::Set(parts) | Frame::Push(parts) => {
                 for (i, part) in parts.iter().enumerate() {
                     if i > 0 {
                         // use space as the array element display separator
@@ -208,8 +354,72 @@ impl fmt::Display for Frame {
 
                 Ok(())
             }
+            Frame::Map(pairs) | Frame::Attribute(pairs) => {
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " ")?;
+                    }
+
+                    write!(fmt, "{} => {}", key, value)?;
+                }
+
+                Ok(())
+            }
+            Frame::Double(value) => value.fmt(fmt),
+            Frame::Boolean(value) => value.fmt(fmt),
+            Frame::BigNumber(digits) => digits.fmt(fmt),
+            Frame::Verbatim(_, text) => Frame::Bulk(text.clone()).fmt(fmt),
+        }
+    }
+}
+
+/// Check the next entry of an aggregate frame. Attributes don't count as
+/// entries, the ones preceding the entry are checked along with it.
+fn check_entry(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<(), Error> {
+    while b'|' == peek_u8(src)? {
+        Frame::check(src, limits)?;
+    }
+    Frame::check(src, limits)
+}
+
+/// Parse the entries of an aggregate frame, after its type byte. The
+/// attributes annotating an entry are kept in front of it.
+fn parse_entries(src: &mut Cursor<&[u8]>) -> Result<Vec<Frame>, Error> {
+    let len = get_decimal(src)?.try_into()?;
+    let mut out = Vec::with_capacity(len);
+
+    for _ in 0..len {
+        while b'|' == peek_u8(src)? {
+            out.push(Frame::parse(src)?);
         }
+        out.push(Frame::parse(src)?);
     }
+
+    Ok(out)
+}
+
+/// Parse the key value pairs of a map or attribute frame, after its type
+/// byte.
+fn parse_pairs(src: &mut Cursor<&[u8]>) -> Result<Vec<(Frame, Frame)>, Error> {
+    let len = get_decimal(src)?.try_into()?;
+    let mut out = Vec::with_capacity(len);
+
+    for _ in 0..len {
+        let key = parse_entry(src)?;
+        let value = parse_entry(src)?;
+        out.push((key, value));
+    }
+
+    Ok(out)
+}
+
+/// Parse the next key or value of a map or attribute frame. Pairs have no
+/// room for the attributes annotating them, they are dropped.
+fn parse_entry(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
+    while b'|' == peek_u8(src)? {
+        Frame::parse(src)?;
+    }
+    Frame::parse(src)
 }
 
 fn peek_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
@@ -246,6 +456,29 @@ fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
     atoi::<u64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
 }
 
+/// Read the length of a bulk string, checking it against `limits`
+fn get_bulk_len(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<usize, Error> {
+    let len = get_decimal(src)?;
+    if len > limits.max_bulk_len {
+        return Err("protocol error; invalid bulk length".into());
+    }
+    Ok(len.try_into()?)
+}
+
+/// Read the number of entries of an aggregate, checking it against `limits`
+fn get_multibulk_len(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<u64, Error> {
+    let len = get_decimal(src)?;
+    if len > limits.max_multibulk_len {
+        return Err("protocol error; invalid multibulk length".into());
+    }
+    Ok(len)
+}
+
+/// The length of a payload of `len` bytes followed by `\r\n`
+fn with_crlf(len: usize) -> Result<usize, Error> {
+    len.checked_add(2).ok_or_else(|| "protocol error; invalid bulk length".into())
+}
+
 /// Find a line
 fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
     // Scan the bytes directly
@@ -299,4 +532,165 @@ impl fmt::Display for Error {
             Error::Other(err) => err.fmt(fmt),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(input: &[u8], limits: &Limits) -> Result<(), Error> {
+        Frame::check(&mut Cursor::new(input), limits)
+    }
+
+    fn rejected(input: &[u8], limits: &Limits) -> String {
+        match check(input, limits) {
+            Err(Error::Other(e)) => e.to_string(),
+            Err(Error::Incomplete) => panic!("{:?} is incomplete, not rejected", input),
+            Ok(()) => panic!("{:?} accepted", input),
+        }
+    }
+
+    #[test]
+    fn bulk_length_limit() {
+        let limits = Limits {
+            max_bulk_len: 5,
+            ..Limits::default()
+        };
+        assert!(check(b"$5\r\nhello\r\n", &limits).is_ok());
+        // The length alone is enough to refuse the frame, before its data
+        // arrives.
+        assert!(rejected(b"$6\r\n", &limits).contains("invalid bulk length"));
+        assert!(rejected(b"*1\r\n$6\r\n", &limits).contains("invalid bulk length"));
+        assert!(rejected(b"=10\r\n", &limits).contains("invalid bulk length"));
+    }
+
+    #[test]
+    fn multibulk_length_limit() {
+        let limits = Limits {
+            max_multibulk_len: 2,
+            ..Limits::default()
+        };
+        assert!(check(b"*2\r\n:1\r\n:2\r\n", &limits).is_ok());
+        assert!(rejected(b"*3\r\n", &limits).contains("invalid multibulk length"));
+        assert!(rejected(b"~3\r\n", &limits).contains("invalid multibulk length"));
+        assert!(rejected(b"%3\r\n", &limits).contains("invalid multibulk length"));
+        assert!(rejected(b"*1\r\n*3\r\n", &limits).contains("invalid multibulk length"));
+    }
+
+    #[test]
+    fn huge_lengths_dont_overflow() {
+        let limits = Limits::default();
+        assert!(rejected(b"$18446744073709551615\r\n", &limits).contains("invalid bulk length"));
+        assert!(rejected(b"=18446744073709551615\r\n", &limits).contains("invalid bulk length"));
+        assert!(rejected(b"%9223372036854775808\r\n", &limits).contains("invalid multibulk length"));
+        assert!(rejected(b"$18446744073709551616\r\n", &limits).contains("invalid frame format"));
+
+        // Lengths that fit are merely waiting for their data.
+        assert!(matches!(check(b"$18446744073709551613\r\n", &limits), Err(Error::Incomplete)));
+        assert!(matches!(check(b"*18446744073709551615\r\n", &limits), Err(Error::Incomplete)));
+
+        let mut src = Cursor::new(&b"$18446744073709551615\r\nabc\r\n"[..]);
+        assert!(Frame::parse(&mut src).is_err());
+        let mut src = Cursor::new(&b"=18446744073709551615\r\ntxt:abc\r\n"[..]);
+        assert!(Frame::parse(&mut src).is_err());
+    }
+
+    #[test]
+    fn signed_integers() {
+        let mut src = Cursor::new(&b":-42\r\n"[..]);
+        assert!(matches!(Frame::parse(&mut src).unwrap(), Frame::Integer(-42)));
+        let mut src = Cursor::new(&b":x\r\n"[..]);
+        assert!(Frame::parse(&mut src).is_err());
+    }
+
+    /// Check then parse the single frame making up `input`.
+    fn parse(input: &[u8]) -> Result<Frame, Error> {
+        check(input, &Limits::default())?;
+        let mut src = Cursor::new(input);
+        let frame = Frame::parse(&mut src)?;
+        assert_eq!(src.position() as usize, input.len(), "{:?} not entirely parsed", input);
+        Ok(frame)
+    }
+
+    #[test]
+    fn resp3_scalars() {
+        assert!(matches!(parse(b",1.5\r\n").unwrap(), Frame::Double(x) if x == 1.5));
+        assert!(matches!(parse(b",-inf\r\n").unwrap(), Frame::Double(x) if x == f64::NEG_INFINITY));
+        assert!(matches!(parse(b",nan\r\n").unwrap(), Frame::Double(x) if x.is_nan()));
+        assert!(matches!(parse(b"#t\r\n").unwrap(), Frame::Boolean(true)));
+        assert!(matches!(parse(b"#f\r\n").unwrap(), Frame::Boolean(false)));
+        assert!(matches!(parse(b"_\r\n").unwrap(), Frame::Null));
+        match parse(b"(-3492890328409238509324850943850943825024385\r\n").unwrap() {
+            Frame::BigNumber(digits) => assert_eq!(digits, "-3492890328409238509324850943850943825024385"),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+        match parse(b"=15\r\ntxt:Some string\r\n").unwrap() {
+            Frame::Verbatim(format, text) => assert_eq!((&format[..], &text[..]), ("txt", &b"Some string"[..])),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn invalid_resp3_scalars() {
+        for input in [
+            &b",1.5x\r\n"[..],
+            b"#x\r\n",
+            b"(\r\n",
+            b"(12a\r\n",
+            b"(-\r\n",
+            b"_x\r\n",
+            b"=3\r\ntxt\r\n",
+            b"=4\r\ntxt-\r\n",
+        ] {
+            assert!(parse(input).is_err(), "{:?} was accepted", input);
+        }
+    }
+
+    #[test]
+    fn resp3_aggregates() {
+        match parse(b"%2\r\n+first\r\n:1\r\n+second\r\n~2\r\n#t\r\n,2.5\r\n").unwrap() {
+            Frame::Map(pairs) => {
+                assert_eq!(pairs.len(), 2);
+                assert_eq!(pairs[0].0, "first");
+                assert!(matches!(pairs[0].1, Frame::Integer(1)));
+                assert_eq!(pairs[1].0, "second");
+                assert!(matches!(&pairs[1].1, Frame::Set(entries) if entries.len() == 2));
+            }
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+        match parse(b">3\r\n$7\r\nmessage\r\n$7\r\nchannel\r\n$5\r\nhello\r\n").unwrap() {
+            Frame::Push(entries) => assert_eq!(entries[2], "hello"),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+        // Incomplete aggregates wait for the rest.
+        assert!(matches!(check(b"%1\r\n+key\r\n", &Limits::default()), Err(Error::Incomplete)));
+    }
+
+    #[test]
+    fn attributes_are_not_counted_as_entries() {
+        let input = b"*2\r\n|1\r\n+ttl\r\n:3600\r\n+first\r\n+second\r\n";
+        match parse(input).unwrap() {
+            Frame::Array(entries) => {
+                assert!(matches!(&entries[0], Frame::Attribute(pairs) if pairs.len() == 1));
+                assert_eq!(entries[1], "first");
+                assert_eq!(entries[2], "second");
+            }
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+
+        // The ones annotating a key or a value of a map are dropped.
+        match parse(b"%1\r\n|1\r\n+a\r\n+b\r\n+key\r\n+value\r\n").unwrap() {
+            Frame::Map(pairs) => {
+                assert_eq!(pairs.len(), 1);
+                assert_eq!(pairs[0].0, "key");
+                assert_eq!(pairs[0].1, "value");
+            }
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+
+        // On their own, they come before the reply they annotate.
+        let mut src = Cursor::new(&b"|1\r\n+ttl\r\n:3600\r\n+OK\r\n"[..]);
+        check(b"|1\r\n+ttl\r\n:3600\r\n", &Limits::default()).unwrap();
+        assert!(matches!(Frame::parse(&mut src).unwrap(), Frame::Attribute(_)));
+        assert_eq!(Frame::parse(&mut src).unwrap(), "OK");
+    }
+}
@@ -0,0 +1,177 @@
+//! Glob-style pattern matching, following the rules Redis uses for `KEYS`,
+//! `CONFIG GET` and ACL patterns.
+//!
+//! * `?` matches any single byte
+//! * `*` matches any sequence of bytes, including an empty one
+//! * `[abc]`, `[^abc]` and `[a-z]` match (or exclude) a set of bytes
+//! * `\` escapes the next byte
+
+/// Returns `true` if `string` matches `pattern`.
+pub fn matches(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let eq = |a: u8, b: u8| {
+        if nocase {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    };
+
+    let (mut p, mut s) = (0, 0);
+    // Position to resume from after the last `*`, as (pattern, string).
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while s < string.len() {
+        let matched = if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    // Collapse consecutive stars and remember where to retry.
+                    while p < pattern.len() && pattern[p] == b'*' {
+                        p += 1;
+                    }
+                    if p == pattern.len() {
+                        return true;
+                    }
+                    backtrack = Some((p, s));
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    true
+                }
+                b'[' => {
+                    let (ok, next) = match_class(pattern, p, string[s], &eq);
+                    p = next;
+                    ok
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    let ok = eq(pattern[p + 1], string[s]);
+                    p += 2;
+                    ok
+                }
+                c => {
+                    p += 1;
+                    eq(c, string[s])
+                }
+            }
+        } else {
+            false
+        };
+
+        if matched {
+            s += 1;
+        } else if let Some((bp, bs)) = backtrack {
+            // Let the last `*` swallow one more byte and try again.
+            p = bp;
+            s = bs + 1;
+            backtrack = Some((bp, bs + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Match `c` against the class starting at `pattern[start] == '['`.
+///
+/// Returns whether it matched and the position after the class.
+fn match_class(pattern: &[u8], start: usize, c: u8, eq: &impl Fn(u8, u8) -> bool) -> (bool, usize) {
+    let mut p = start + 1;
+    let negate = p < pattern.len() && pattern[p] == b'^';
+    if negate {
+        p += 1;
+    }
+
+    let mut matched = false;
+    while p < pattern.len() && pattern[p] != b']' {
+        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+            matched |= eq(pattern[p + 1], c);
+            p += 2;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+            let (lo, hi) = if pattern[p] <= pattern[p + 2] {
+                (pattern[p], pattern[p + 2])
+            } else {
+                (pattern[p + 2], pattern[p])
+            };
+            matched |= (lo..=hi).any(|x| eq(x, c));
+            p += 3;
+        } else {
+            matched |= eq(pattern[p], c);
+            p += 1;
+        }
+    }
+
+    if p == pattern.len() {
+        // Unterminated class, Redis treats the end of the pattern as `]`.
+        return (matched != negate, p);
+    }
+    (matched != negate, p + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    fn m(pattern: &str, string: &str) -> bool {
+        matches(pattern.as_bytes(), string.as_bytes(), false)
+    }
+
+    #[test]
+    fn literals_and_wildcards() {
+        assert!(m("key", "key"));
+        assert!(!m("key", "keys"));
+        assert!(!m("keys", "key"));
+        assert!(m("", ""));
+        assert!(!m("", "a"));
+        assert!(m("k?y", "key"));
+        assert!(!m("k?y", "ky"));
+        assert!(m("*", ""));
+        assert!(m("user:*", "user:1000"));
+        assert!(m("*:name", "user:1000:name"));
+        assert!(m("a*b*c", "aXXbYYbZZc"));
+        assert!(!m("a*b*c", "aXXbYYbZZ"));
+        assert!(m("a**", "abc"));
+        assert!(m("*a*", "bab"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(m("h[ae]llo", "hello"));
+        assert!(m("h[ae]llo", "hallo"));
+        assert!(!m("h[ae]llo", "hillo"));
+        assert!(m("h[^e]llo", "hallo"));
+        assert!(!m("h[^e]llo", "hello"));
+        assert!(m("h[a-c]llo", "hbllo"));
+        assert!(!m("h[a-c]llo", "hdllo"));
+        // Reversed ranges work as well.
+        assert!(m("h[c-a]llo", "hbllo"));
+        assert!(m(r"[\]]", "]"));
+        assert!(m("[a-]", "-"));
+        // An unterminated class ends with the pattern.
+        assert!(m("x[ab", "xa"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(m(r"a\*b", "a*b"));
+        assert!(!m(r"a\*b", "aXb"));
+        assert!(m(r"a\?", "a?"));
+        assert!(!m(r"a\?", "ab"));
+        // A trailing backslash matches itself.
+        assert!(m("a\\", "a\\"));
+    }
+
+    #[test]
+    fn case() {
+        assert!(!m("MaxClients", "maxclients"));
+        assert!(matches(b"MaxClients", b"maxclients", true));
+        assert!(matches(b"[A-C]*", b"bar", true));
+        assert!(!matches(b"[^B]*", b"bar", true));
+    }
+
+    #[test]
+    fn backtracking_is_not_exponential() {
+        let string = "a".repeat(10_000);
+        assert!(!m("*a*a*a*a*a*a*a*a*b", &string));
+    }
+}
@@ -0,0 +1,317 @@
+//! Rendering of the `INFO` reply.
+//!
+//! The reply uses the Redis text format: sections start with a `# Name`
+//! header and hold `field:value` lines, separated by `\r\n`.
+
+use crate::config::Config;
+use crate::db::{AllDbs, DataTypes};
+use crate::stats::Stats;
+
+use std::fmt::Write;
+
+/// Sections returned when `INFO` is called without arguments or with
+/// `default`.
+const DEFAULT_SECTIONS: &[&str] = &["server", "clients", "memory", "stats", "cluster", "keyspace"];
+
+/// Every section, returned for `all` and `everything`.
+const ALL_SECTIONS: &[&str] = &[
+    "server",
+    "clients",
+    "memory",
+    "stats",
+    "commandstats",
+    "cluster",
+    "keyspace",
+];
+
+/// Everything `INFO` reports on.
+pub struct InfoContext<'a> {
+    pub stats: &'a Stats,
+    pub config: &'a Config,
+    pub all_dbs: &'a AllDbs,
+}
+
+/// Render the requested sections. Unknown section names are ignored.
+pub fn render(requested: &[String], ctx: &InfoContext<'_>) -> String {
+    let mut sections: Vec<&str> = Vec::new();
+    if requested.is_empty() {
+        sections.extend(DEFAULT_SECTIONS);
+    }
+    for name in requested {
+        match &name.to_lowercase()[..] {
+            "default" => sections.extend(DEFAULT_SECTIONS),
+            "all" | "everything" => sections.extend(ALL_SECTIONS),
+            name => {
+                if let Some(section) = ALL_SECTIONS.iter().find(|section| **section == name) {
+                    sections.push(section);
+                }
+            }
+        }
+    }
+
+    let mut out = String::new();
+    // Keep the canonical order and drop duplicates.
+    for section in ALL_SECTIONS.iter().filter(|section| sections.contains(section)) {
+        if !out.is_empty() {
+            out.push_str("\r\n");
+        }
+        match *section {
+            "server" => server(&mut out, ctx),
+            "clients" => clients(&mut out, ctx),
+            "memory" => memory(&mut out, ctx),
+            "stats" => stats(&mut out, ctx),
+            "commandstats" => commandstats(&mut out, ctx),
+            "cluster" => cluster(&mut out, ctx),
+            "keyspace" => keyspace(&mut out, ctx),
+            _ => unreachable!(),
+        }
+    }
+    out
+}
+
+fn server(out: &mut String, ctx: &InfoContext<'_>) {
+    let uptime = ctx.stats.uptime().as_secs();
+    let mode = if ctx.config.cluster_enabled { "cluster" } else { "standalone" };
+    let config_file = ctx
+        .config
+        .config_file
+        .as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+
+    out.push_str("# Server\r\n");
+    let _ = write!(
+        out,
+        "redis_version:{}\r\n\
+         redis_mode:{}\r\n\
+         os:{} {}\r\n\
+         arch_bits:{}\r\n\
+         process_id:{}\r\n\
+         tcp_port:{}\r\n\
+         uptime_in_seconds:{}\r\n\
+         uptime_in_days:{}\r\n\
+         config_file:{}\r\n",
+        env!("CARGO_PKG_VERSION"),
+        mode,
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        usize::BITS,
+        std::process::id(),
+        ctx.config.port,
+        uptime,
+        uptime / (24 * 60 * 60),
+        config_file,
+    );
+}
+
+fn clients(out: &mut String, ctx: &InfoContext<'_>) {
+    out.push_str("# Clients\r\n");
+    let _ = write!(
+        out,
+        "connected_clients:{}\r\n\
+         maxclients:{}\r\n\
+         blocked_clients:{}\r\n",
+        ctx.stats.connected_clients(),
+        ctx.config.maxclients,
+        ctx.stats.blocked_clients(),
+    );
+}
+
+fn memory(out: &mut String, ctx: &InfoContext<'_>) {
+    let used = used_memory(ctx.all_dbs);
+    out.push_str("# Memory\r\n");
+    let _ = write!(
+        out,
+        "used_memory:{}\r\n\
+         used_memory_human:{}\r\n",
+        used,
+        human_bytes(used),
+    );
+}
+
+fn stats(out: &mut String, ctx: &InfoContext<'_>) {
+    out.push_str("# Stats\r\n");
+    let _ = write!(
+        out,
+        "total_connections_received:{}\r\n\
+         total_commands_processed:{}\r\n\
+         rejected_connections:{}\r\n\
+         total_error_replies:{}\r\n\
+         client_output_buffer_limit_disconnections:{}\r\n",
+        ctx.stats.total_connections_received(),
+        ctx.stats.total_commands_processed(),
+        ctx.stats.rejected_connections(),
+        ctx.stats.total_error_replies(),
+        ctx.stats.client_output_buffer_limit_disconnections(),
+    );
+}
+
+fn commandstats(out: &mut String, ctx: &InfoContext<'_>) {
+    out.push_str("# Commandstats\r\n");
+    for (name, stats) in ctx.stats.commands() {
+        let per_call = if stats.calls == 0 {
+            0.0
+        } else {
+            stats.usec as f64 / stats.calls as f64
+        };
+        let _ = write!(
+            out,
+            "cmdstat_{}:calls={},usec={},usec_per_call={:.2},rejected_calls={},failed_calls={}\r\n",
+            name, stats.calls, stats.usec, per_call, stats.rejected_calls, stats.failed_calls,
+        );
+    }
+}
+
+fn cluster(out: &mut String, ctx: &InfoContext<'_>) {
+    out.push_str("# Cluster\r\n");
+    let _ = write!(out, "cluster_enabled:{}\r\n", ctx.config.cluster_enabled as u8);
+}
+
+fn keyspace(out: &mut String, ctx: &InfoContext<'_>) {
+    out.push_str("# Keyspace\r\n");
+    for index in 0..ctx.all_dbs.len() {
+        let keys = ctx
+            .all_dbs
+            .get_instance(index)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|value| !matches!(value, DataTypes::SenderList(_)))
+            .count();
+        // Keys never expire, so there is nothing to report beyond the count.
+        if keys > 0 {
+            let _ = write!(out, "db{}:keys={},expires=0,avg_ttl=0\r\n", index, keys);
+        }
+    }
+}
+
+/// Rough estimate of the memory held by the stored data: key and value
+/// bytes plus a fixed per-entry and per-list-element overhead.
+fn used_memory(all_dbs: &AllDbs) -> usize {
+    const ENTRY_OVERHEAD: usize = 64;
+    const ELEMENT_OVERHEAD: usize = 40;
+
+    let mut total = 0;
+    for index in 0..all_dbs.len() {
+        let db = all_dbs.get_instance(index).unwrap();
+        let db = db.lock().unwrap();
+        for (key, value) in db.iter() {
+            total += ENTRY_OVERHEAD + key.len();
+            total += match value {
+                DataTypes::BytesInDb(bytes) => bytes.len(),
+                DataTypes::List(list) => list.iter().map(|item| ELEMENT_OVERHEAD + item.len()).sum(),
+                DataTypes::SenderList(senders) => senders.len() * ELEMENT_OVERHEAD,
+            };
+        }
+    }
+    total
+}
+
+fn human_bytes(bytes: usize) -> String {
+    const UNITS: &[&str] = &["B", "K", "M", "G", "T"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}B", bytes)
+    } else {
+        format!("{:.2}{}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{SenderType, SpecialSender};
+    use bytes::Bytes;
+    use std::collections::LinkedList;
+
+    fn headers(info: &str) -> Vec<&str> {
+        info.lines().filter(|line| line.starts_with("# ")).collect()
+    }
+
+    fn render_with(requested: &[&str], all_dbs: &AllDbs) -> String {
+        let requested: Vec<String> = requested.iter().map(|name| name.to_string()).collect();
+        let ctx = InfoContext {
+            stats: &Stats::new(),
+            config: &Config::default(),
+            all_dbs,
+        };
+        render(&requested, &ctx)
+    }
+
+    fn waiters() -> DataTypes {
+        let (sender, _) = tokio::sync::mpsc::channel(1);
+        let mut senders = LinkedList::new();
+        senders.push_back(SpecialSender {
+            sender,
+            type_sender: SenderType::fromBlpop,
+        });
+        DataTypes::SenderList(senders)
+    }
+
+    #[test]
+    fn default_and_all_sections() {
+        let all_dbs = AllDbs::new(1);
+        let default = ["# Server", "# Clients", "# Memory", "# Stats", "# Cluster", "# Keyspace"];
+        assert_eq!(headers(&render_with(&[], &all_dbs)), default);
+        assert_eq!(headers(&render_with(&["default"], &all_dbs)), default);
+
+        let all = ["# Server", "# Clients", "# Memory", "# Stats", "# Commandstats", "# Cluster", "# Keyspace"];
+        assert_eq!(headers(&render_with(&["all"], &all_dbs)), all);
+        assert_eq!(headers(&render_with(&["EVERYTHING"], &all_dbs)), all);
+    }
+
+    #[test]
+    fn named_sections_in_canonical_order_without_duplicates() {
+        let all_dbs = AllDbs::new(1);
+        let info = render_with(&["keyspace", "Server", "nope", "server", "commandstats"], &all_dbs);
+        assert_eq!(headers(&info), ["# Server", "# Commandstats", "# Keyspace"]);
+        // Sections are separated by an empty line.
+        assert!(info.contains("\r\n\r\n# Commandstats\r\n"));
+
+        assert_eq!(render_with(&["nope"], &all_dbs), "");
+        assert_eq!(headers(&render_with(&["default", "memory", "all"], &all_dbs)).len(), ALL_SECTIONS.len());
+    }
+
+    #[test]
+    fn keyspace_skips_blocked_client_registrations() {
+        let all_dbs = AllDbs::new(3);
+        {
+            let db = all_dbs.get_instance(0).unwrap();
+            let mut db = db.lock().unwrap();
+            db.insert("a".to_string(), DataTypes::BytesInDb(Bytes::from("1")));
+            db.insert("b".to_string(), DataTypes::List(LinkedList::from([Bytes::from("x")])));
+            db.insert("waiting".to_string(), waiters());
+        }
+        all_dbs.get_instance(1).unwrap().lock().unwrap().insert("waiting".to_string(), waiters());
+        all_dbs
+            .get_instance(2)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .insert("c".to_string(), DataTypes::BytesInDb(Bytes::from("1")));
+
+        assert_eq!(
+            render_with(&["keyspace"], &all_dbs),
+            "# Keyspace\r\ndb0:keys=2,expires=0,avg_ttl=0\r\ndb2:keys=1,expires=0,avg_ttl=0\r\n"
+        );
+    }
+
+    #[test]
+    fn human_bytes_units() {
+        assert_eq!(human_bytes(0), "0B");
+        assert_eq!(human_bytes(1023), "1023B");
+        assert_eq!(human_bytes(1024), "1.00K");
+        assert_eq!(human_bytes(1536), "1.50K");
+        assert_eq!(human_bytes(1024 * 1024), "1.00M");
+        assert_eq!(human_bytes(1024 * 1024 * 1024), "1.00G");
+        assert_eq!(human_bytes(1 << 40), "1.00T");
+        // There is no unit above terabytes.
+        assert_eq!(human_bytes(1 << 50), "1024.00T");
+    }
+}
@@ -0,0 +1,317 @@
+//! Latency monitoring.
+//!
+//! Two kinds of latency data are kept:
+//!
+//! * Latency events: internal operations (`command`, `save`, `save-fsync`)
+//!   that took at least `latency-monitor-threshold` milliseconds. Each event
+//!   keeps a history of one sample per second, holding the worst latency seen
+//!   during that second, as reported by `LATENCY LATEST` and `HISTORY`.
+//!
+//! * Latency histograms: the distribution of the execution time of every
+//!   command, reported by `LATENCY HISTOGRAM`. Values are counted in
+//!   power-of-two buckets, so the relative error stays bounded over the whole
+//!   range, in the spirit of an HDR histogram.
+
+use crate::config::Config;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Samples kept per event.
+const HISTORY_LEN: usize = 160;
+
+/// Buckets of a `LatencyHistogram`, enough for any `u64` microsecond value.
+const BUCKETS: usize = 64;
+
+/// A single latency sample.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    /// Unix time of the sample
+    pub timestamp: u64,
+    /// Latency in milliseconds
+    pub latency: u64,
+}
+
+/// History of a single event.
+#[derive(Debug, Clone, Default)]
+pub struct EventHistory {
+    /// Samples, oldest first
+    pub samples: VecDeque<Sample>,
+    /// Highest latency ever recorded, in milliseconds
+    pub max: u64,
+}
+
+impl EventHistory {
+    /// The most recent sample
+    pub fn latest(&self) -> Option<&Sample> {
+        self.samples.back()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LatencyMonitor {
+    /// Mirrors `latency-monitor-threshold`, `0` disables the monitor
+    threshold: AtomicU64,
+    events: Mutex<HashMap<String, EventHistory>>,
+}
+
+impl LatencyMonitor {
+    /// Apply the latency parameters of `config`, called again whenever they
+    /// change at runtime.
+    pub fn configure(&self, config: &Config) {
+        self.threshold.store(config.latency_monitor_threshold, Ordering::Relaxed);
+    }
+
+    /// Record that `event` took `elapsed`, if that reaches the threshold.
+    pub fn record(&self, event: &str, elapsed: Duration) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+        self.record_at(event, elapsed, timestamp);
+    }
+
+    /// Like `record`, at the Unix time `timestamp`.
+    fn record_at(&self, event: &str, elapsed: Duration, timestamp: u64) {
+        let threshold = self.threshold.load(Ordering::Relaxed);
+        let latency = elapsed.as_millis() as u64;
+        if threshold == 0 || latency < threshold {
+            return;
+        }
+
+        let mut events = self.events.lock().unwrap();
+        let history = events.entry(event.to_string()).or_default();
+        history.max = history.max.max(latency);
+        match history.samples.back_mut() {
+            // One sample per second, keeping the worst one.
+            Some(sample) if sample.timestamp == timestamp => {
+                sample.latency = sample.latency.max(latency);
+            }
+            _ => {
+                if history.samples.len() == HISTORY_LEN {
+                    history.samples.pop_front();
+                }
+                history.samples.push_back(Sample { timestamp, latency });
+            }
+        }
+    }
+
+    /// Snapshot of every event, sorted by name
+    pub fn events(&self) -> Vec<(String, EventHistory)> {
+        let mut events: Vec<_> = self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, history)| (name.clone(), history.clone()))
+            .collect();
+        events.sort_by(|a, b| a.0.cmp(&b.0));
+        events
+    }
+
+    pub fn history(&self, event: &str) -> Option<EventHistory> {
+        self.events.lock().unwrap().get(event).cloned()
+    }
+
+    /// Clear the given events, or all of them when `names` is empty.
+    ///
+    /// Returns the number of events cleared.
+    pub fn reset(&self, names: &[String]) -> usize {
+        let mut events = self.events.lock().unwrap();
+        if names.is_empty() {
+            let count = events.len();
+            events.clear();
+            return count;
+        }
+        names
+            .iter()
+            .filter(|name| events.remove(name.as_str()).is_some())
+            .count()
+    }
+
+    /// A human readable analysis of the recorded events, as returned by
+    /// `LATENCY DOCTOR`.
+    pub fn doctor(&self) -> String {
+        let threshold = self.threshold.load(Ordering::Relaxed);
+        if threshold == 0 {
+            return "I'm sorry, I can't help you: the latency monitor is disabled. \
+                    Enable it with CONFIG SET latency-monitor-threshold <milliseconds>.\n"
+                .to_string();
+        }
+
+        let events = self.events();
+        if events.is_empty() {
+            return format!(
+                "No latency spikes were observed above the {} milliseconds threshold \
+                 since the monitor was enabled or last reset.\n",
+                threshold
+            );
+        }
+
+        let mut out = format!(
+            "Latency spikes above {} milliseconds were observed for the following events:\n\n",
+            threshold
+        );
+        for (i, (name, history)) in events.iter().enumerate() {
+            let total: u64 = history.samples.iter().map(|sample| sample.latency).sum();
+            let average = total / history.samples.len().max(1) as u64;
+            out.push_str(&format!(
+                "{}. {}: {} latency spikes (average {}ms, worst {}ms).\n",
+                i + 1,
+                name,
+                history.samples.len(),
+                average,
+                history.max
+            ));
+        }
+
+        out.push_str("\nAdvice:\n\n");
+        for (name, _) in &events {
+            let advice = match &name[..] {
+                "command" => "- Check SLOWLOG GET for the commands causing the spikes. Blocking \
+                              and long list operations are the usual suspects.",
+                "save" => "- Snapshots serialize every database while holding its lock. Reduce \
+                           the frequency with CONFIG SET save.",
+                "save-fsync" => "- Syncing the snapshot to disk is slow, the disk may be \
+                                 saturated by other processes.",
+                _ => continue,
+            };
+            out.push_str(advice);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Distribution of a command's execution time, in microseconds.
+///
+/// Bucket `i` counts the values in `(2^(i-1), 2^i]`, bucket 0 counts `0` and
+/// `1`.
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    buckets: [u64; BUCKETS],
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> LatencyHistogram {
+        LatencyHistogram { buckets: [0; BUCKETS] }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, usec: u64) {
+        let bucket = if usec <= 1 {
+            0
+        } else {
+            (64 - (usec - 1).leading_zeros()) as usize
+        };
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+    }
+
+    /// Cumulative counts as `(upper bound in microseconds, values at or
+    /// below it)`, from the first non-empty bucket up to the last one.
+    pub fn cumulative(&self) -> Vec<(u64, u64)> {
+        let first = match self.buckets.iter().position(|&count| count > 0) {
+            Some(first) => first,
+            None => return vec![],
+        };
+        let last = self.buckets.iter().rposition(|&count| count > 0).unwrap();
+
+        let mut total = 0;
+        (0..=last)
+            .filter_map(|bucket| {
+                total += self.buckets[bucket];
+                (bucket >= first).then(|| (1u64 << bucket, total))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(threshold: u64) -> LatencyMonitor {
+        let monitor = LatencyMonitor::default();
+        let config = Config {
+            latency_monitor_threshold: threshold,
+            ..Config::default()
+        };
+        monitor.configure(&config);
+        monitor
+    }
+
+    fn latencies(history: &EventHistory) -> Vec<(u64, u64)> {
+        history.samples.iter().map(|sample| (sample.timestamp, sample.latency)).collect()
+    }
+
+    #[test]
+    fn histogram_buckets() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.cumulative(), vec![]);
+
+        for usec in [0, 1, 2, 3, 4, 5, 8] {
+            histogram.record(usec);
+        }
+        assert_eq!(histogram.cumulative(), vec![(1, 2), (2, 3), (4, 5), (8, 7)]);
+    }
+
+    #[test]
+    fn histogram_starts_at_the_first_non_empty_bucket() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(3);
+        histogram.record(100);
+        assert_eq!(
+            histogram.cumulative(),
+            vec![(4, 1), (8, 1), (16, 1), (32, 1), (64, 1), (128, 2)]
+        );
+    }
+
+    #[test]
+    fn histogram_clamps_huge_values() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(u64::MAX);
+        histogram.record(1 << 63);
+        assert_eq!(histogram.cumulative(), vec![(1 << 63, 2)]);
+    }
+
+    #[test]
+    fn one_sample_per_second_keeps_the_worst() {
+        let monitor = monitor(10);
+        monitor.record_at("command", Duration::from_millis(20), 100);
+        monitor.record_at("command", Duration::from_millis(50), 100);
+        monitor.record_at("command", Duration::from_millis(30), 100);
+        monitor.record_at("command", Duration::from_millis(15), 101);
+        // Below the threshold
+        monitor.record_at("command", Duration::from_millis(9), 102);
+
+        let history = monitor.history("command").unwrap();
+        assert_eq!(latencies(&history), vec![(100, 50), (101, 15)]);
+        assert_eq!(history.max, 50);
+        assert_eq!(history.latest().unwrap().latency, 15);
+    }
+
+    #[test]
+    fn history_keeps_the_latest_samples() {
+        let monitor = monitor(1);
+        for second in 0..HISTORY_LEN as u64 + 10 {
+            monitor.record_at("save", Duration::from_millis(second + 1), second);
+        }
+
+        let history = monitor.history("save").unwrap();
+        assert_eq!(history.samples.len(), HISTORY_LEN);
+        assert_eq!(history.samples.front().unwrap().timestamp, 10);
+        assert_eq!(history.latest().unwrap().timestamp, HISTORY_LEN as u64 + 9);
+        assert_eq!(history.max, HISTORY_LEN as u64 + 10);
+    }
+
+    #[test]
+    fn zero_threshold_disables_the_monitor() {
+        let monitor = monitor(0);
+        monitor.record("command", Duration::from_secs(5));
+        assert!(monitor.events().is_empty());
+        assert!(monitor.doctor().contains("the latency monitor is disabled"));
+    }
+}
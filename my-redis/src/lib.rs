@@ -16,23 +16,26 @@
 //! * `server`: Redis server implementation. Includes a single `run` function
 //!   that takes a `TcpListener` and starts accepting redis client connections.
 //!
-//! * `clients/client`: an asynchronous Redis client implementation. Demonstrates how to
-//!   build clients with Tokio.
+//! * `clients/client`: an asynchronous Redis client implementation, connecting
+//!   over TCP or a Unix socket. Demonstrates how to build clients with Tokio.
 //!
 //! * `cmd`: implementations of the supported Redis commands.
 //!
+//! * `cluster`: hash slot mapping and redirection logic used when the server
+//!   runs in cluster mode.
+//!
 //! * `frame`: represents a single Redis protocol frame. A frame is used as an
 //!   intermediate representation between a "command" and the byte
 //!   representation.
 
-// pub mod clients;
-// pub use clients::{BlockingClient, BufferedClient, Client};
+pub mod clients;
+pub use clients::Client;
 
 pub mod cmd;
 pub use cmd::Command;
 
 mod connection;
-pub use connection::Connection;
+pub use connection::{Connection, OutputLimitExceeded, OutputLimits, Stream};
 
 pub mod frame;
 pub use frame::Frame;
@@ -46,8 +49,39 @@ use parse::{Parse, ParseError};
 pub mod server;
 pub mod request;
 
-// mod shutdown;
-// use shutdown::Shutdown;
+pub mod cluster;
+
+pub mod dump;
+
+pub mod config;
+pub use config::Config;
+
+pub mod snapshot;
+
+pub mod glob;
+
+pub mod stats;
+
+pub mod info;
+
+pub mod slowlog;
+
+pub mod latency;
+
+pub mod monitor;
+
+pub mod registry;
+
+pub mod acl;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(feature = "tls")]
+pub mod tls;
+
+pub mod shutdown;
+pub use shutdown::Shutdown;
 
 /// Default port that a redis server listens on.
 ///
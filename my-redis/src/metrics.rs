@@ -0,0 +1,216 @@
+//! Prometheus metrics, enabled with the `metrics` cargo feature.
+//!
+//! Counters and histograms are updated as commands run, through `Stats`.
+//! Gauges describing the current state of the server (keys per database,
+//! blocked clients, queue depth of the database workers) are sampled when
+//! the metrics are scraped.
+//!
+//! Unlike the `INFO` counters, these are never reset by `CONFIG RESETSTAT`,
+//! as Prometheus expects counters to only go up.
+
+use crate::db::{AllDbs, DataTypes};
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::time::Duration;
+
+/// Upper bounds of the command latency buckets, in seconds.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.00001, 0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0,
+    5.0,
+];
+
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    commands: IntCounterVec,
+    command_errors: IntCounterVec,
+    command_duration: HistogramVec,
+    rejected_commands: IntCounterVec,
+    connections: IntCounter,
+    connected_clients: IntGauge,
+    blocked_clients: IntGauge,
+    keys: IntGaugeVec,
+    queue_depth: IntGaugeVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let commands = IntCounterVec::new(
+            Opts::new("redis_commands_processed_total", "Commands executed, by command"),
+            &["command"],
+        )
+        .unwrap();
+        let command_errors = IntCounterVec::new(
+            Opts::new("redis_command_errors_total", "Commands that replied with an error, by command"),
+            &["command"],
+        )
+        .unwrap();
+        let command_duration = HistogramVec::new(
+            HistogramOpts::new("redis_command_duration_seconds", "Command execution time, by command")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["command"],
+        )
+        .unwrap();
+        let rejected_commands = IntCounterVec::new(
+            Opts::new("redis_commands_rejected_total", "Commands rejected before execution, by command"),
+            &["command"],
+        )
+        .unwrap();
+        let connections =
+            IntCounter::new("redis_connections_received_total", "Connections accepted").unwrap();
+        let connected_clients =
+            IntGauge::new("redis_connected_clients", "Currently connected clients").unwrap();
+        let blocked_clients = IntGauge::new(
+            "redis_blocked_clients",
+            "Clients waiting in a blocking command",
+        )
+        .unwrap();
+        let keys = IntGaugeVec::new(Opts::new("redis_db_keys", "Keys stored, by database"), &["db"])
+            .unwrap();
+        let queue_depth = IntGaugeVec::new(
+            Opts::new(
+                "redis_db_request_queue_depth",
+                "Requests waiting for the database worker, by database",
+            ),
+            &["db"],
+        )
+        .unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(commands.clone())).unwrap();
+        registry.register(Box::new(command_errors.clone())).unwrap();
+        registry.register(Box::new(command_duration.clone())).unwrap();
+        registry.register(Box::new(rejected_commands.clone())).unwrap();
+        registry.register(Box::new(connections.clone())).unwrap();
+        registry.register(Box::new(connected_clients.clone())).unwrap();
+        registry.register(Box::new(blocked_clients.clone())).unwrap();
+        registry.register(Box::new(keys.clone())).unwrap();
+        registry.register(Box::new(queue_depth.clone())).unwrap();
+
+        Metrics {
+            registry,
+            commands,
+            command_errors,
+            command_duration,
+            rejected_commands,
+            connections,
+            connected_clients,
+            blocked_clients,
+            keys,
+            queue_depth,
+        }
+    }
+
+    pub(crate) fn record_command(&self, name: &str, elapsed: Duration, failed: bool) {
+        self.commands.with_label_values(&[name]).inc();
+        self.command_duration
+            .with_label_values(&[name])
+            .observe(elapsed.as_secs_f64());
+        if failed {
+            self.command_errors.with_label_values(&[name]).inc();
+        }
+    }
+
+    pub(crate) fn record_rejected(&self, name: &str) {
+        self.rejected_commands.with_label_values(&[name]).inc();
+    }
+
+    pub(crate) fn record_connection(&self) {
+        self.connections.inc();
+    }
+
+    /// Render all metrics in the Prometheus text format.
+    ///
+    /// `queue_depths` holds the number of pending requests of each database
+    /// worker, indexed by database.
+    pub fn render(
+        &self,
+        connected_clients: usize,
+        blocked_clients: usize,
+        all_dbs: &AllDbs,
+        queue_depths: &[usize],
+    ) -> String {
+        self.connected_clients.set(connected_clients as i64);
+        self.blocked_clients.set(blocked_clients as i64);
+        for index in 0..all_dbs.len() {
+            let keys = all_dbs
+                .get_instance(index)
+                .unwrap()
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|value| !matches!(value, DataTypes::SenderList(_)))
+                .count();
+            self.keys
+                .with_label_values(&[&index.to_string()])
+                .set(keys as i64);
+        }
+        for (index, depth) in queue_depths.iter().enumerate() {
+            self.queue_depth
+                .with_label_values(&[&index.to_string()])
+                .set(*depth as i64);
+        }
+
+        let mut out = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn render_counts_commands_errors_and_durations() {
+        let metrics = Metrics::new();
+        metrics.record_command("get", Duration::from_micros(300), false);
+        metrics.record_command("get", Duration::from_secs(2), true);
+        metrics.record_command("set", Duration::ZERO, false);
+        let out = metrics.render(0, 0, &AllDbs::new(1), &[]);
+
+        assert!(out.contains("redis_commands_processed_total{command=\"get\"} 2\n"), "{}", out);
+        assert!(out.contains("redis_commands_processed_total{command=\"set\"} 1\n"), "{}", out);
+        assert!(out.contains("redis_command_errors_total{command=\"get\"} 1\n"), "{}", out);
+        assert!(!out.contains("redis_command_errors_total{command=\"set\"}"), "{}", out);
+
+        // Buckets are cumulative.
+        for (le, count) in [("0.00025", 0), ("0.0005", 1), ("1", 1), ("5", 2), ("+Inf", 2)] {
+            let line = format!("redis_command_duration_seconds_bucket{{command=\"get\",le=\"{}\"}} {}\n", le, count);
+            assert!(out.contains(&line), "missing {:?} in {}", line, out);
+        }
+        assert!(out.contains("redis_command_duration_seconds_count{command=\"get\"} 2\n"), "{}", out);
+    }
+
+    #[test]
+    fn render_samples_keys_and_queue_depths_per_db() {
+        let metrics = Metrics::new();
+        let all_dbs = AllDbs::new(2);
+        {
+            let db = all_dbs.get_instance(1).unwrap();
+            let mut db = db.lock().unwrap();
+            db.insert("a".to_string(), DataTypes::BytesInDb(Bytes::from("1")));
+            db.insert("b".to_string(), DataTypes::BytesInDb(Bytes::from("2")));
+        }
+        let out = metrics.render(3, 1, &all_dbs, &[0, 7]);
+
+        assert!(out.contains("redis_db_keys{db=\"0\"} 0\n"), "{}", out);
+        assert!(out.contains("redis_db_keys{db=\"1\"} 2\n"), "{}", out);
+        assert!(out.contains("redis_db_request_queue_depth{db=\"0\"} 0\n"), "{}", out);
+        assert!(out.contains("redis_db_request_queue_depth{db=\"1\"} 7\n"), "{}", out);
+        assert!(out.contains("redis_connected_clients 3\n"), "{}", out);
+        assert!(out.contains("redis_blocked_clients 1\n"), "{}", out);
+    }
+}
@@ -0,0 +1,156 @@
+//! The feed of processed commands consumed by `MONITOR` clients.
+//!
+//! Every command is offered to `Monitors::feed`. Formatting and broadcasting
+//! only happen while at least one client is monitoring; otherwise the cost
+//! is a single atomic load.
+
+use bytes::Bytes;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+/// Lines buffered per monitor before it starts missing some.
+const CAPACITY: usize = 1024;
+
+#[derive(Debug)]
+pub struct Monitors {
+    sender: broadcast::Sender<Bytes>,
+    /// Number of attached monitors
+    active: AtomicUsize,
+}
+
+impl Default for Monitors {
+    fn default() -> Monitors {
+        Monitors::new()
+    }
+}
+
+impl Monitors {
+    pub fn new() -> Monitors {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        Monitors {
+            sender,
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Attach a monitor. It receives every line fed from now on, until the
+    /// returned value is dropped.
+    pub fn subscribe(&self) -> MonitorFeed<'_> {
+        let receiver = self.sender.subscribe();
+        self.active.fetch_add(1, Ordering::Relaxed);
+        MonitorFeed {
+            monitors: self,
+            receiver,
+        }
+    }
+
+    /// Send a processed command to the attached monitors.
+    ///
+    /// The line has the same format as in Redis:
+    ///
+    /// ```text
+    /// 1339518083.107412 [0 127.0.0.1:60866] "lpush" "list" "value"
+    /// ```
+    pub fn feed(&self, db: usize, client_addr: &str, args: &[Bytes]) {
+        if self.active.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut line = format!("{}.{:06} [{} {}]", now.as_secs(), now.subsec_micros(), db, client_addr);
+        for arg in args {
+            line.push(' ');
+            quote(&mut line, arg);
+        }
+        // Fails only when the last monitor just went away.
+        let _ = self.sender.send(Bytes::from(line));
+    }
+}
+
+/// The lines received by a single monitor.
+#[derive(Debug)]
+pub struct MonitorFeed<'a> {
+    monitors: &'a Monitors,
+    receiver: broadcast::Receiver<Bytes>,
+}
+
+impl MonitorFeed<'_> {
+    /// Wait for the next line. Returns how many lines were missed instead if
+    /// the monitor could not keep up.
+    pub async fn recv(&mut self) -> Result<Bytes, u64> {
+        match self.receiver.recv().await {
+            Ok(line) => Ok(line),
+            Err(broadcast::error::RecvError::Lagged(missed)) => Err(missed),
+            // The sender lives as long as `Monitors`, which outlives us.
+            Err(broadcast::error::RecvError::Closed) => unreachable!(),
+        }
+    }
+}
+
+impl Drop for MonitorFeed<'_> {
+    fn drop(&mut self) {
+        self.monitors.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Append `arg` as a double quoted string, escaping anything not printable.
+fn quote(out: &mut String, arg: &[u8]) {
+    out.push('"');
+    for &byte in arg {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            byte if byte.is_ascii_graphic() || byte == b' ' => out.push(byte as char),
+            byte => out.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast::error::TryRecvError;
+
+    fn quoted(arg: &[u8]) -> String {
+        let mut out = String::new();
+        quote(&mut out, arg);
+        out
+    }
+
+    #[test]
+    fn quote_escapes_like_redis() {
+        assert_eq!(quoted(b"value"), r#""value""#);
+        assert_eq!(quoted(b"a b"), r#""a b""#);
+        assert_eq!(quoted(b""), r#""""#);
+        assert_eq!(quoted(br#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quoted(br"C:\dir"), r#""C:\\dir""#);
+        assert_eq!(quoted(b"line\r\n\tend"), r#""line\r\n\tend""#);
+        assert_eq!(quoted(b"\x07\x08"), r#""\a\b""#);
+        assert_eq!(quoted(b"\x00\x1b\x7f\xff"), r#""\x00\x1b\x7f\xff""#);
+    }
+
+    #[test]
+    fn feed_is_a_no_op_without_monitors() {
+        let monitors = Monitors::new();
+        // Watch the channel without counting as a monitor.
+        let mut receiver = monitors.sender.subscribe();
+        monitors.feed(0, "127.0.0.1:1234", &[Bytes::from("get"), Bytes::from("k")]);
+        assert!(matches!(receiver.try_recv(), Err(TryRecvError::Empty)));
+
+        let feed = monitors.subscribe();
+        monitors.feed(3, "127.0.0.1:1234", &[Bytes::from("get"), Bytes::from("k")]);
+        let line = receiver.try_recv().unwrap();
+        assert!(line.ends_with(br#" [3 127.0.0.1:1234] "get" "k""#), "{:?}", line);
+
+        drop(feed);
+        monitors.feed(0, "127.0.0.1:1234", &[Bytes::from("get"), Bytes::from("k")]);
+        assert!(matches!(receiver.try_recv(), Err(TryRecvError::Empty)));
+    }
+}
@@ -106,7 +106,8 @@ impl Parse {
 
         match self.next()? {
             // An integer frame type is already stored as an integer.
-            Frame::Integer(v) => Ok(v),
+            Frame::Integer(v) if v >= 0 => Ok(v as u64),
+            Frame::Integer(_) => Err(MSG.into()),
             // Simple and bulk frames must be parsed as integers. If the parsing
             // fails, an error is returned.
             Frame::Simple(data) => atoi::<u64>(data.as_bytes()).ok_or_else(|| MSG.into()),
@@ -115,6 +116,21 @@ impl Parse {
         }
     }
 
+    /// Number of entries left
+    pub(crate) fn remaining(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// The next entry as raw bytes, without consuming it. `None` if there is
+    /// no next entry or it isn't a string.
+    pub(crate) fn peek_bytes(&self) -> Option<&[u8]> {
+        match self.parts.as_slice().first()? {
+            Frame::Simple(s) => Some(s.as_bytes()),
+            Frame::Bulk(data) => Some(data),
+            _ => None,
+        }
+    }
+
     /// Ensure there are no more entries in the array
     pub(crate) fn finish(&mut self) -> Result<(), ParseError> {
         if self.parts.next().is_none() {
@@ -0,0 +1,469 @@
+//! Registry of the connected clients, backing the `CLIENT` command family.
+//!
+//! Every connection registers itself when accepted and is removed when its
+//! `Registration` is dropped. The connection task keeps its `ClientHandle`
+//! up to date as it processes commands, so `CLIENT LIST` can report on it
+//! from any other connection.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Notify};
+
+/// What `CLIENT PAUSE` holds back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseMode {
+    /// Only commands that may modify the data set
+    Write,
+    All,
+}
+
+/// How a blocking command is ended early, by `CLIENT UNBLOCK` or when the
+/// server shuts down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unblock {
+    /// Return as if the command timed out
+    Timeout,
+    /// Fail with an `UNBLOCKED` error
+    Error,
+    /// Fail because the server is shutting down
+    Shutdown,
+}
+
+/// A connected client.
+#[derive(Debug)]
+pub struct ClientHandle {
+    id: u64,
+    addr: String,
+    laddr: String,
+    created: Instant,
+    state: Mutex<ClientState>,
+    killed: AtomicBool,
+    kill: Notify,
+}
+
+/// The mutable part of a client's description.
+#[derive(Debug)]
+struct ClientState {
+    name: Arc<str>,
+    user: String,
+    db: usize,
+    /// Last command executed, or being executed
+    cmd: String,
+    last_interaction: Instant,
+    /// Bytes buffered in the query buffer, and free space left in it
+    qbuf: usize,
+    qbuf_free: usize,
+    /// Bytes of replies queued and not written yet, and how many replies
+    obl: usize,
+    oll: usize,
+    monitor: bool,
+    no_evict: bool,
+    /// Set while the client waits in a blocking command
+    blocked: Option<oneshot::Sender<Unblock>>,
+    /// Set once the server shuts down, so later blocking commands return
+    /// right away
+    shutting_down: bool,
+}
+
+impl ClientHandle {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn laddr(&self) -> &str {
+        &self.laddr
+    }
+
+    pub fn name(&self) -> Arc<str> {
+        self.state.lock().unwrap().name.clone()
+    }
+
+    pub fn set_name(&self, name: &str) {
+        self.state.lock().unwrap().name = Arc::from(name);
+    }
+
+    pub fn user(&self) -> String {
+        self.state.lock().unwrap().user.clone()
+    }
+
+    pub fn set_user(&self, user: &str) {
+        self.state.lock().unwrap().user = user.to_string();
+    }
+
+    pub fn set_monitor(&self) {
+        self.state.lock().unwrap().monitor = true;
+    }
+
+    pub fn set_no_evict(&self, no_evict: bool) {
+        self.state.lock().unwrap().no_evict = no_evict;
+    }
+
+    /// Record that the client started executing `cmd` against `db`, with
+    /// `qbuf` bytes left in a query buffer with room for `qbuf_free` more.
+    pub fn start_command(&self, cmd: &str, db: usize, qbuf: usize, qbuf_free: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.cmd.clear();
+        state.cmd.push_str(cmd);
+        state.db = db;
+        state.qbuf = qbuf;
+        state.qbuf_free = qbuf_free;
+        state.last_interaction = Instant::now();
+    }
+
+    /// Record that `replies` replies, `bytes` bytes in all, are waiting to
+    /// be written.
+    pub fn set_output(&self, bytes: usize, replies: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.obl = bytes;
+        state.oll = replies;
+    }
+
+    /// Mark the client as blocked until the returned value is dropped.
+    pub fn block(&self) -> Blocked<'_> {
+        let (sender, receiver) = oneshot::channel();
+        let mut state = self.state.lock().unwrap();
+        if state.shutting_down {
+            let _ = sender.send(Unblock::Shutdown);
+        } else {
+            state.blocked = Some(sender);
+        }
+        drop(state);
+        Blocked {
+            handle: self,
+            receiver,
+        }
+    }
+
+    /// End the blocking command the client waits in, if any. Returns
+    /// whether the client was blocked.
+    pub fn unblock(&self, how: Unblock) -> bool {
+        match self.state.lock().unwrap().blocked.take() {
+            Some(sender) => sender.send(how).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Ask the connection to close. It is closed as soon as its task
+    /// notices, even in the middle of a blocking command.
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::Relaxed);
+        self.kill.notify_waiters();
+    }
+
+    /// Completes once the client has been killed.
+    pub async fn killed(&self) {
+        loop {
+            let notified = self.kill.notified();
+            if self.killed.load(Ordering::Relaxed) {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// The client described as a `CLIENT LIST` line, without the trailing
+    /// newline.
+    pub fn describe(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut flags = String::new();
+        if state.monitor {
+            flags.push('O');
+        }
+        if state.blocked.is_some() {
+            flags.push('b');
+        }
+        if state.no_evict {
+            flags.push('e');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+
+        let mut line = String::new();
+        let _ = write!(
+            line,
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db={} qbuf={} qbuf-free={} obl={} oll={} omem={} cmd={} user={}",
+            self.id,
+            self.addr,
+            self.laddr,
+            state.name,
+            self.created.elapsed().as_secs(),
+            state.last_interaction.elapsed().as_secs(),
+            flags,
+            state.db,
+            state.qbuf,
+            state.qbuf_free,
+            state.obl,
+            state.oll,
+            state.obl,
+            if state.cmd.is_empty() { "NULL" } else { &state.cmd },
+            state.user,
+        );
+        line
+    }
+}
+
+#[derive(Debug)]
+pub struct ClientRegistry {
+    clients: Mutex<BTreeMap<u64, Arc<ClientHandle>>>,
+    next_id: AtomicU64,
+    pause: Mutex<Option<(Instant, PauseMode)>>,
+    unpaused: Notify,
+    shutting_down: AtomicBool,
+}
+
+impl Default for ClientRegistry {
+    fn default() -> ClientRegistry {
+        ClientRegistry::new()
+    }
+}
+
+impl ClientRegistry {
+    pub fn new() -> ClientRegistry {
+        ClientRegistry {
+            clients: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU64::new(1),
+            pause: Mutex::new(None),
+            unpaused: Notify::new(),
+            shutting_down: AtomicBool::new(false),
+        }
+    }
+
+    /// Add a client connected from `addr` to the local address `laddr`. It
+    /// stays registered until the returned value is dropped.
+    pub fn register(&self, addr: String, laddr: String) -> Registration<'_> {
+        self.try_register(addr, laddr, usize::MAX)
+            .expect("no limit on the number of clients")
+    }
+
+    /// Add a client as `register` does, unless `max_clients` clients are
+    /// already registered. The check and the insertion happen under the same
+    /// lock, so concurrent connections can't go beyond the limit.
+    pub fn try_register(&self, addr: String, laddr: String, max_clients: usize) -> Option<Registration<'_>> {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= max_clients {
+            return None;
+        }
+
+        let now = Instant::now();
+        let handle = Arc::new(ClientHandle {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            addr,
+            laddr,
+            created: now,
+            state: Mutex::new(ClientState {
+                name: Arc::from(""),
+                user: "default".to_string(),
+                db: 0,
+                cmd: String::new(),
+                last_interaction: now,
+                qbuf: 0,
+                qbuf_free: 0,
+                obl: 0,
+                oll: 0,
+                monitor: false,
+                no_evict: false,
+                blocked: None,
+                shutting_down: self.shutting_down.load(Ordering::Relaxed),
+            }),
+            killed: AtomicBool::new(false),
+            kill: Notify::new(),
+        });
+        clients.insert(handle.id, handle.clone());
+        Some(Registration {
+            registry: self,
+            handle,
+        })
+    }
+
+    /// Every connected client, ordered by id
+    pub fn list(&self) -> Vec<Arc<ClientHandle>> {
+        self.clients.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<ClientHandle>> {
+        self.clients.lock().unwrap().get(&id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Release every blocked client with `Unblock::Shutdown`, and make the
+    /// blocking commands that start afterwards, including ones already
+    /// queued on a worker, return the same way. Returns the number of
+    /// clients released.
+    pub fn shut_down(&self) -> usize {
+        let clients = self.clients.lock().unwrap();
+        self.shutting_down.store(true, Ordering::Relaxed);
+        clients
+            .values()
+            .filter(|handle| {
+                let mut state = handle.state.lock().unwrap();
+                state.shutting_down = true;
+                match state.blocked.take() {
+                    Some(sender) => sender.send(Unblock::Shutdown).is_ok(),
+                    None => false,
+                }
+            })
+            .count()
+    }
+
+    /// Hold back commands matching `mode` for `timeout`. A pause already in
+    /// effect is only ever extended, and `ALL` takes precedence over `WRITE`.
+    pub fn pause(&self, timeout: Duration, mode: PauseMode) {
+        let mut pause = self.pause.lock().unwrap();
+        let mut until = Instant::now() + timeout;
+        let mut mode = mode;
+        if let Some((current_until, current_mode)) = *pause {
+            until = until.max(current_until);
+            if current_mode == PauseMode::All {
+                mode = PauseMode::All;
+            }
+        }
+        *pause = Some((until, mode));
+    }
+
+    pub fn unpause(&self) {
+        *self.pause.lock().unwrap() = None;
+        self.unpaused.notify_waiters();
+    }
+
+    /// Whether a command is held back by `CLIENT PAUSE`. `write` tells
+    /// whether the command may modify the data set.
+    pub fn is_paused(&self, write: bool) -> bool {
+        self.paused_until(write).is_some()
+    }
+
+    fn paused_until(&self, write: bool) -> Option<Instant> {
+        match *self.pause.lock().unwrap() {
+            Some((until, mode)) if until > Instant::now() && (write || mode == PauseMode::All) => Some(until),
+            _ => None,
+        }
+    }
+
+    /// Wait until a command may run. `write` tells whether the command may
+    /// modify the data set.
+    pub async fn wait_unpaused(&self, write: bool) {
+        loop {
+            let notified = self.unpaused.notified();
+            let until = match self.paused_until(write) {
+                Some(until) => until,
+                None => return,
+            };
+            tokio::select! {
+                _ = notified => {}
+                _ = tokio::time::sleep_until(until.into()) => {}
+            }
+        }
+    }
+}
+
+/// Removes the client from the registry when dropped.
+#[derive(Debug)]
+pub struct Registration<'a> {
+    registry: &'a ClientRegistry,
+    handle: Arc<ClientHandle>,
+}
+
+impl Registration<'_> {
+    pub fn handle(&self) -> &Arc<ClientHandle> {
+        &self.handle
+    }
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.registry.clients.lock().unwrap().remove(&self.handle.id);
+    }
+}
+
+/// A client waiting in a blocking command.
+#[derive(Debug)]
+pub struct Blocked<'a> {
+    handle: &'a ClientHandle,
+    receiver: oneshot::Receiver<Unblock>,
+}
+
+impl Blocked<'_> {
+    /// Completes when `CLIENT UNBLOCK` is called for the client.
+    pub async fn unblocked(&mut self) -> Unblock {
+        match (&mut self.receiver).await {
+            Ok(how) => how,
+            // The sender is only dropped along with us.
+            Err(_) => std::future::pending().await,
+        }
+    }
+}
+
+impl Drop for Blocked<'_> {
+    fn drop(&mut self) {
+        self.handle.state.lock().unwrap().blocked = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::thread;
+
+    #[test]
+    fn slots_are_freed_on_drop() {
+        let registry = ClientRegistry::new();
+        let first = registry.try_register("a".into(), "l".into(), 1).unwrap();
+        assert!(registry.try_register("b".into(), "l".into(), 1).is_none());
+        drop(first);
+        assert!(registry.try_register("b".into(), "l".into(), 1).is_some());
+    }
+
+    #[test]
+    fn concurrent_registrations_respect_the_limit() {
+        let registry = ClientRegistry::new();
+        let barrier = Barrier::new(16);
+        let accepted = thread::scope(|scope| {
+            let workers: Vec<_> = (0..16)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        let registration = registry.try_register("a".into(), "l".into(), 4);
+                        let accepted = registration.is_some();
+                        // Hold the slot until everyone has tried.
+                        barrier.wait();
+                        accepted
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).filter(|&a| a).count()
+        });
+        assert_eq!(accepted, 4);
+        assert_eq!(registry.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn blocking_after_shut_down_returns_right_away() {
+        let registry = ClientRegistry::new();
+        let before = registry.register("a".into(), "l".into());
+        let mut blocked = before.handle().block();
+        assert_eq!(registry.shut_down(), 1);
+        assert_eq!(blocked.unblocked().await, Unblock::Shutdown);
+        drop(blocked);
+
+        // Blocking again, as a command queued before shutdown would, and
+        // from a client registered afterwards.
+        assert_eq!(before.handle().block().unblocked().await, Unblock::Shutdown);
+        let after = registry.register("b".into(), "l".into());
+        assert_eq!(after.handle().block().unblocked().await, Unblock::Shutdown);
+    }
+}
@@ -1,11 +1,21 @@
+use bytes::Bytes;
+use std::sync::Arc;
 use tokio::sync::oneshot;
 use crate::cmd::Command;
 use crate::frame::Frame;
+use crate::registry::ClientHandle;
 
 #[derive(Debug)]
 pub struct Request {
     pub cmd: Command,
     pub sender: oneshot::Sender<Frame>,
+    /// Span the request was created in, so the worker executing it is
+    /// traced as part of the originating command.
+    pub span: tracing::Span,
+    /// The command as received, kept for the slow log
+    pub args: Vec<Bytes>,
+    /// The client that sent the request
+    pub client: Option<Arc<ClientHandle>>,
 }
 
 impl Request {
@@ -14,11 +24,21 @@ impl Request {
         let request = Request {
             cmd,
             sender,
+            span: tracing::Span::current(),
+            args: Vec::new(),
+            client: None,
         };
         (request, receiver)
     }
 
+    /// Attach the raw arguments and the client the request came from.
+    pub fn with_client(mut self, args: Vec<Bytes>, client: Arc<ClientHandle>) -> Request {
+        self.args = args;
+        self.client = Some(client);
+        self
+    }
+
     pub fn set_command(&mut self, cmd: Command) {
         self.cmd = cmd
     }
-}
\ No newline at end of file
+}
@@ -27,7 +27,8 @@ struct Handler {
     databases: Arc<AllDbs>,
 }
 
-const MAX_CONNECTIONS: usize = 250;
+/// Default limit on the number of connected clients.
+pub const MAX_CONNECTIONS: usize = 250;
 
 
 pub async fn run(mut receiver: Receiver<Request>, index: usize, all_dbs: Arc<AllDbs>) {
@@ -0,0 +1,153 @@
+//! The slow log: a bounded record of the commands that took longest to run.
+//!
+//! Commands executing for longer than `slowlog-log-slower-than` microseconds
+//! are added to the front of the log. Once it holds `slowlog-max-len`
+//! entries, the oldest ones are dropped.
+
+use crate::config::Config;
+use crate::frame::Frame;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Arguments kept per entry, the last one is replaced by a summary of the
+/// remaining ones.
+const MAX_ARGS: usize = 32;
+
+/// Bytes kept per argument.
+const MAX_ARG_LEN: usize = 128;
+
+/// A command recorded in the slow log.
+#[derive(Debug, Clone)]
+pub struct SlowLogEntry {
+    /// Unique, increasing identifier
+    pub id: u64,
+    /// Unix time at which the command was logged
+    pub timestamp: u64,
+    /// Execution time in microseconds
+    pub duration: u64,
+    /// The command and its arguments, possibly truncated
+    pub args: Vec<Bytes>,
+    pub client_addr: String,
+    pub client_name: String,
+}
+
+#[derive(Debug)]
+pub struct SlowLog {
+    entries: Mutex<VecDeque<SlowLogEntry>>,
+    next_id: AtomicUsize,
+    /// Mirrors `slowlog-log-slower-than`
+    slower_than: AtomicI64,
+    /// Mirrors `slowlog-max-len`
+    max_len: AtomicUsize,
+}
+
+impl SlowLog {
+    pub fn new(config: &Config) -> SlowLog {
+        let slowlog = SlowLog {
+            entries: Mutex::new(VecDeque::new()),
+            next_id: AtomicUsize::new(0),
+            slower_than: AtomicI64::new(0),
+            max_len: AtomicUsize::new(0),
+        };
+        slowlog.configure(config);
+        slowlog
+    }
+
+    /// Apply the slow log parameters of `config`, called again whenever they
+    /// change at runtime.
+    pub fn configure(&self, config: &Config) {
+        self.slower_than.store(config.slowlog_log_slower_than, Ordering::Relaxed);
+        self.max_len.store(config.slowlog_max_len, Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap();
+        entries.truncate(config.slowlog_max_len);
+    }
+
+    /// Log the command `args` if `elapsed` exceeds the threshold.
+    pub fn record(&self, args: &[Bytes], elapsed: Duration, client_addr: &str, client_name: &str) {
+        let slower_than = self.slower_than.load(Ordering::Relaxed);
+        let duration = elapsed.as_micros() as u64;
+        if slower_than < 0 || duration < slower_than as u64 {
+            return;
+        }
+        let max_len = self.max_len.load(Ordering::Relaxed);
+        if max_len == 0 {
+            return;
+        }
+
+        let entry = SlowLogEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) as u64,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_secs())
+                .unwrap_or(0),
+            duration,
+            args: truncate(args),
+            client_addr: client_addr.to_string(),
+            client_name: client_name.to_string(),
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_front(entry);
+        entries.truncate(max_len);
+    }
+
+    /// The `count` most recent entries, newest first. All entries when
+    /// `count` is `None`.
+    pub fn get(&self, count: Option<usize>) -> Vec<SlowLogEntry> {
+        let entries = self.entries.lock().unwrap();
+        let count = count.unwrap_or(entries.len());
+        entries.iter().take(count).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn reset(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl SlowLogEntry {
+    /// The entry as returned by `SLOWLOG GET`.
+    pub fn to_frame(&self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_int(self.id as i64);
+        frame.push_int(self.timestamp as i64);
+        frame.push_int(self.duration as i64);
+        frame.push_frame(Frame::Array(self.args.iter().cloned().map(Frame::Bulk).collect()));
+        frame.push_bulk(Bytes::from(self.client_addr.clone()));
+        frame.push_bulk(Bytes::from(self.client_name.clone()));
+        frame
+    }
+}
+
+/// Limit the number and size of the arguments kept, so a huge command does
+/// not bloat the log.
+fn truncate(args: &[Bytes]) -> Vec<Bytes> {
+    let kept = if args.len() > MAX_ARGS { MAX_ARGS - 1 } else { args.len() };
+    let mut out: Vec<Bytes> = args[..kept]
+        .iter()
+        .map(|arg| {
+            if arg.len() <= MAX_ARG_LEN {
+                return arg.clone();
+            }
+            let mut truncated = BytesMut::with_capacity(MAX_ARG_LEN + 32);
+            truncated.put_slice(&arg[..MAX_ARG_LEN]);
+            truncated.put_slice(format!("... ({} more bytes)", arg.len() - MAX_ARG_LEN).as_bytes());
+            truncated.freeze()
+        })
+        .collect();
+    if kept < args.len() {
+        out.push(Bytes::from(format!("... ({} more arguments)", args.len() - kept)));
+    }
+    out
+}
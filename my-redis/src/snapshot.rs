@@ -0,0 +1,155 @@
+//! Point-in-time snapshots of all databases.
+//!
+//! A snapshot file starts with a magic string and a format version, followed
+//! by one section per non-empty database and an end marker:
+//!
+//! ```text
+//! MYREDIS | version (2, BE)
+//! 0xFE | db index (4, BE) | entry count (4, BE) | entries...
+//! 0xFF
+//! ```
+//!
+//! Each entry is the key and a `dump` payload, both prefixed with their
+//! length as a big-endian `u32`. Reusing the `dump` encoding means every value
+//! carries its own checksum.
+
+use crate::db::AllDbs;
+use crate::dump;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8] = b"MYREDIS";
+const VERSION: u16 = 1;
+const DB_SELECTOR: u8 = 0xFE;
+const EOF: u8 = 0xFF;
+
+/// Outcome of a successful `save`.
+#[derive(Debug, Clone, Copy)]
+pub struct Saved {
+    /// Number of keys written
+    pub keys: usize,
+    /// Time spent syncing the file to disk
+    pub fsync: Duration,
+}
+
+/// Write all databases to `path`.
+///
+/// The snapshot is written to a temporary file first and renamed into place
+/// once synced, so a crash never leaves a truncated snapshot behind.
+pub fn save(all_dbs: &AllDbs, path: &Path) -> crate::Result<Saved> {
+    let mut buf = BytesMut::new();
+    buf.put_slice(MAGIC);
+    buf.put_u16(VERSION);
+
+    let mut total = 0;
+    for index in 0..all_dbs.len() {
+        // Serialize under the lock, write after releasing it.
+        let entries: Vec<(String, Bytes)> = {
+            let db = all_dbs.get_instance(index).unwrap();
+            let db = db.lock().unwrap();
+            db.iter()
+                .filter_map(|(key, value)| dump::serialize(value).map(|payload| (key.clone(), payload)))
+                .collect()
+        };
+        if entries.is_empty() {
+            continue;
+        }
+
+        buf.put_u8(DB_SELECTOR);
+        buf.put_u32(index as u32);
+        buf.put_u32(entries.len() as u32);
+        for (key, payload) in &entries {
+            buf.put_u32(key.len() as u32);
+            buf.put_slice(key.as_bytes());
+            buf.put_u32(payload.len() as u32);
+            buf.put_slice(payload);
+        }
+        total += entries.len();
+    }
+    buf.put_u8(EOF);
+
+    let tmp = path.with_extension(format!("tmp-{}", std::process::id()));
+    let mut file = File::create(&tmp)?;
+    file.write_all(&buf)?;
+    let started = Instant::now();
+    file.sync_all()?;
+    let fsync = started.elapsed();
+    fs::rename(&tmp, path)?;
+
+    Ok(Saved { keys: total, fsync })
+}
+
+/// Load the snapshot at `path` into `all_dbs`.
+///
+/// A missing file is not an error, the databases are simply left empty.
+/// Returns the number of keys loaded.
+pub fn load(all_dbs: &AllDbs, path: &Path) -> crate::Result<usize> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut src = &contents[..];
+    if src.len() < MAGIC.len() + 2 || &src[..MAGIC.len()] != MAGIC {
+        return Err(format!("{} is not a snapshot file", path.display()).into());
+    }
+    src.advance(MAGIC.len());
+    let version = src.get_u16();
+    if version > VERSION {
+        return Err(format!("unsupported snapshot version {}", version).into());
+    }
+
+    let mut total = 0;
+    loop {
+        match get_u8(&mut src)? {
+            EOF => break,
+            DB_SELECTOR => {
+                let index = get_u32(&mut src)? as usize;
+                let db = all_dbs
+                    .get_instance(index)
+                    .ok_or_else(|| format!("snapshot contains database {} but only {} are configured", index, all_dbs.len()))?;
+                let count = get_u32(&mut src)?;
+                let mut db = db.lock().unwrap();
+                for _ in 0..count {
+                    let key = String::from_utf8(get_chunk(&mut src)?.to_vec())
+                        .map_err(|_| "invalid key in snapshot")?;
+                    let value = dump::deserialize(get_chunk(&mut src)?)?;
+                    db.insert(key, value);
+                }
+                total += count as usize;
+            }
+            other => return Err(format!("invalid snapshot section marker {:#x}", other).into()),
+        }
+    }
+
+    Ok(total)
+}
+
+fn get_u8(src: &mut &[u8]) -> crate::Result<u8> {
+    if !src.has_remaining() {
+        return Err("truncated snapshot".into());
+    }
+    Ok(src.get_u8())
+}
+
+fn get_u32(src: &mut &[u8]) -> crate::Result<u32> {
+    if src.remaining() < 4 {
+        return Err("truncated snapshot".into());
+    }
+    Ok(src.get_u32())
+}
+
+fn get_chunk<'a>(src: &mut &'a [u8]) -> crate::Result<&'a [u8]> {
+    let len = get_u32(src)? as usize;
+    if src.remaining() < len {
+        return Err("truncated snapshot".into());
+    }
+    let (chunk, rest) = (*src).split_at(len);
+    *src = rest;
+    Ok(chunk)
+}
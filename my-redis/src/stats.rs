@@ -0,0 +1,219 @@
+//! Server-wide counters reported by `INFO` and `LATENCY`.
+//!
+//! A single `Stats` value is shared by the accept loop, every connection task
+//! and the per-database workers. Plain counters are atomics; the per-command
+//! statistics live behind a mutex as they are keyed by command name.
+//!
+//! With the `metrics` feature enabled, every event is also forwarded to the
+//! Prometheus metrics.
+
+use crate::latency::{LatencyHistogram, LatencyMonitor};
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Call counts and accumulated latency of a single command.
+#[derive(Debug, Clone, Default)]
+pub struct CommandStats {
+    pub calls: u64,
+    /// Total execution time in microseconds
+    pub usec: u64,
+    /// Calls rejected before execution, e.g. redirected in cluster mode
+    pub rejected_calls: u64,
+    /// Calls that were executed but replied with an error
+    pub failed_calls: u64,
+    /// Distribution of the execution time
+    pub histogram: LatencyHistogram,
+}
+
+#[derive(Debug)]
+pub struct Stats {
+    start: Instant,
+    connected_clients: AtomicUsize,
+    blocked_clients: AtomicUsize,
+    total_connections_received: AtomicU64,
+    rejected_connections: AtomicU64,
+    client_output_buffer_limit_disconnections: AtomicU64,
+    total_commands_processed: AtomicU64,
+    total_error_replies: AtomicU64,
+    commands: Mutex<HashMap<String, CommandStats>>,
+    latency: LatencyMonitor,
+    #[cfg(feature = "metrics")]
+    metrics: crate::metrics::Metrics,
+}
+
+impl Default for Stats {
+    fn default() -> Stats {
+        Stats::new()
+    }
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats {
+            start: Instant::now(),
+            connected_clients: AtomicUsize::new(0),
+            blocked_clients: AtomicUsize::new(0),
+            total_connections_received: AtomicU64::new(0),
+            rejected_connections: AtomicU64::new(0),
+            client_output_buffer_limit_disconnections: AtomicU64::new(0),
+            total_commands_processed: AtomicU64::new(0),
+            total_error_replies: AtomicU64::new(0),
+            commands: Mutex::new(HashMap::new()),
+            latency: LatencyMonitor::default(),
+            #[cfg(feature = "metrics")]
+            metrics: crate::metrics::Metrics::new(),
+        }
+    }
+
+    /// Time since the server started
+    pub fn uptime(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Record a new connection. The client counts as connected until the
+    /// returned guard is dropped.
+    pub fn client_connected(&self) -> ClientGuard<'_> {
+        self.total_connections_received.fetch_add(1, Ordering::Relaxed);
+        self.connected_clients.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.metrics.record_connection();
+        ClientGuard { stats: self }
+    }
+
+    /// Record a connection refused because of the `maxclients` limit.
+    pub fn connection_rejected(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a client disconnected for going beyond its output buffer
+    /// limits.
+    pub fn output_limit_disconnection(&self) {
+        self.client_output_buffer_limit_disconnections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that a client is waiting in a blocking command. The client
+    /// counts as blocked until the returned guard is dropped.
+    pub fn client_blocked(&self) -> BlockedGuard<'_> {
+        self.blocked_clients.fetch_add(1, Ordering::Relaxed);
+        BlockedGuard { stats: self }
+    }
+
+    /// Record an executed command, its execution time and whether it
+    /// replied with an error.
+    pub fn record_command(&self, name: &str, elapsed: Duration, failed: bool) {
+        self.total_commands_processed.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.total_error_replies.fetch_add(1, Ordering::Relaxed);
+        }
+        #[cfg(feature = "metrics")]
+        self.metrics.record_command(name, elapsed, failed);
+
+        self.latency.record("command", elapsed);
+
+        let mut commands = self.commands.lock().unwrap();
+        let entry = commands.entry(name.to_string()).or_default();
+        entry.calls += 1;
+        entry.usec += elapsed.as_micros() as u64;
+        entry.histogram.record(elapsed.as_micros() as u64);
+        if failed {
+            entry.failed_calls += 1;
+        }
+    }
+
+    /// Record a command that was rejected without being executed.
+    pub fn record_rejected(&self, name: &str) {
+        self.total_error_replies.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.metrics.record_rejected(name);
+        let mut commands = self.commands.lock().unwrap();
+        commands.entry(name.to_string()).or_default().rejected_calls += 1;
+    }
+
+    /// Reset the counters cleared by `CONFIG RESETSTAT`. Gauges such as the
+    /// number of connected clients are left alone.
+    pub fn reset(&self) {
+        self.total_connections_received.store(0, Ordering::Relaxed);
+        self.rejected_connections.store(0, Ordering::Relaxed);
+        self.client_output_buffer_limit_disconnections.store(0, Ordering::Relaxed);
+        self.total_commands_processed.store(0, Ordering::Relaxed);
+        self.total_error_replies.store(0, Ordering::Relaxed);
+        self.commands.lock().unwrap().clear();
+    }
+
+    pub fn connected_clients(&self) -> usize {
+        self.connected_clients.load(Ordering::Relaxed)
+    }
+
+    pub fn blocked_clients(&self) -> usize {
+        self.blocked_clients.load(Ordering::Relaxed)
+    }
+
+    pub fn total_connections_received(&self) -> u64 {
+        self.total_connections_received.load(Ordering::Relaxed)
+    }
+
+    pub fn rejected_connections(&self) -> u64 {
+        self.rejected_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn client_output_buffer_limit_disconnections(&self) -> u64 {
+        self.client_output_buffer_limit_disconnections.load(Ordering::Relaxed)
+    }
+
+    pub fn total_commands_processed(&self) -> u64 {
+        self.total_commands_processed.load(Ordering::Relaxed)
+    }
+
+    pub fn total_error_replies(&self) -> u64 {
+        self.total_error_replies.load(Ordering::Relaxed)
+    }
+
+    /// Latency events, see `LATENCY LATEST`
+    pub fn latency(&self) -> &LatencyMonitor {
+        &self.latency
+    }
+
+    /// Prometheus metrics fed by this value
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &crate::metrics::Metrics {
+        &self.metrics
+    }
+
+    /// Snapshot of the per-command statistics, sorted by command name
+    pub fn commands(&self) -> Vec<(String, CommandStats)> {
+        let mut commands: Vec<_> = self
+            .commands
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, stats)| (name.clone(), stats.clone()))
+            .collect();
+        commands.sort_by(|a, b| a.0.cmp(&b.0));
+        commands
+    }
+}
+
+/// Decrements the connected client count when dropped.
+pub struct ClientGuard<'a> {
+    stats: &'a Stats,
+}
+
+impl Drop for ClientGuard<'_> {
+    fn drop(&mut self) {
+        self.stats.connected_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Decrements the blocked client count when dropped.
+pub struct BlockedGuard<'a> {
+    stats: &'a Stats,
+}
+
+impl Drop for BlockedGuard<'_> {
+    fn drop(&mut self) {
+        self.stats.blocked_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
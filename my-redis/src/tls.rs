@@ -0,0 +1,96 @@
+//! TLS for client connections, implemented with rustls.
+//!
+//! The server certificate and key, and the CA certificates client
+//! certificates are checked against, are read from the files named by the
+//! `tls-*` parameters. Setting any of them with `CONFIG SET` reads all the
+//! files again, so certificates can be rotated without a restart. Connections
+//! already established are not affected.
+
+use crate::config::Config;
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tokio_rustls::TlsAcceptor;
+
+#[derive(Debug)]
+pub struct Tls {
+    /// Replaced as a whole on reload
+    server_config: RwLock<Arc<ServerConfig>>,
+}
+
+impl Tls {
+    pub fn new(config: &Config) -> crate::Result<Tls> {
+        Ok(Tls {
+            server_config: RwLock::new(server_config(config)?),
+        })
+    }
+
+    /// Read the certificate files again. On error, the ones previously
+    /// loaded stay in use.
+    pub fn reload(&self, config: &Config) -> crate::Result<()> {
+        let server_config = server_config(config)?;
+        *self.server_config.write().unwrap() = server_config;
+        Ok(())
+    }
+
+    /// The acceptor to run the handshake of a new connection with.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.server_config.read().unwrap().clone())
+    }
+}
+
+fn server_config(config: &Config) -> crate::Result<Arc<ServerConfig>> {
+    let cert_file = config.tls_cert_file.as_deref().ok_or("tls-cert-file is not set")?;
+    let key_file = config.tls_key_file.as_deref().ok_or("tls-key-file is not set")?;
+    let certs = load_certs(cert_file)?;
+    let key = load_key(key_file)?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone()).with_safe_default_protocol_versions()?;
+    let builder = if config.tls_auth_clients == "no" {
+        builder.with_no_client_auth()
+    } else {
+        let ca_file = config
+            .tls_ca_cert_file
+            .as_deref()
+            .ok_or("tls-ca-cert-file is required to authenticate clients")?;
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(ca_file)? {
+            roots.add(cert)?;
+        }
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+        let verifier = if config.tls_auth_clients == "optional" {
+            verifier.allow_unauthenticated()
+        } else {
+            verifier
+        };
+        builder.with_client_cert_verifier(verifier.build()?)
+    };
+
+    Ok(Arc::new(builder.with_single_cert(certs, key)?))
+}
+
+/// Read the PEM encoded certificates of `path`.
+fn load_certs(path: &Path) -> crate::Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("can't read certificates from {}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {}", path.display()).into());
+    }
+    Ok(certs)
+}
+
+/// Read the first PEM encoded private key of `path`.
+fn load_key(path: &Path) -> crate::Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("can't read private key from {}: {}", path.display(), e))?
+        .ok_or_else(|| format!("no private key found in {}", path.display()).into())
+}
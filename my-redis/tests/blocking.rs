@@ -0,0 +1,45 @@
+mod support;
+
+use my_redis::frame::Frame;
+use std::time::Duration;
+use support::{call, Server};
+
+#[tokio::test]
+async fn blpop_times_out_with_a_null_array() {
+    let server = Server::start(&[]).await;
+    let mut connection = server.connect().await;
+
+    let reply = call(&mut connection, &["blpop", "list", "0.1"]).await;
+    assert!(matches!(reply, Frame::NullArray), "{:?}", reply);
+    let reply = call(&mut connection, &["brpop", "list", "0.1"]).await;
+    assert!(matches!(reply, Frame::NullArray), "{:?}", reply);
+
+    // RESP3 has a single null.
+    assert!(matches!(call(&mut connection, &["hello", "3"]).await, Frame::Map(_)));
+    let reply = call(&mut connection, &["blpop", "list", "0.1"]).await;
+    assert!(matches!(reply, Frame::Null), "{:?}", reply);
+}
+
+#[tokio::test]
+async fn client_unblock_timeout_replies_a_null_array() {
+    let server = Server::start(&[]).await;
+    let mut blocked = server.connect().await;
+    let mut other = server.connect().await;
+
+    let id = match call(&mut blocked, &["client", "id"]).await {
+        Frame::Integer(id) => id.to_string(),
+        frame => panic!("unexpected CLIENT ID reply {:?}", frame),
+    };
+    let pop = tokio::spawn(async move { call(&mut blocked, &["blpop", "list", "0"]).await });
+
+    // The client may not be blocked yet.
+    loop {
+        match call(&mut other, &["client", "unblock", &id, "timeout"]).await {
+            Frame::Integer(1) => break,
+            Frame::Integer(0) => tokio::time::sleep(Duration::from_millis(10)).await,
+            frame => panic!("unexpected CLIENT UNBLOCK reply {:?}", frame),
+        }
+    }
+    let reply = pop.await.unwrap();
+    assert!(matches!(reply, Frame::NullArray), "{:?}", reply);
+}
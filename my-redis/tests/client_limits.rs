@@ -0,0 +1,74 @@
+mod support;
+
+use my_redis::frame::Frame;
+use my_redis::Connection;
+use std::time::Duration;
+use support::{call, Server};
+use tokio::time::{timeout, Instant};
+
+/// Connect and check the connection is accepted. The probe connection of
+/// `Server::start` may still hold a slot for a moment, so retry a little.
+async fn connect_accepted(server: &Server) -> Connection {
+    for _ in 0..100 {
+        let mut connection = server.connect().await;
+        match call(&mut connection, &["ping"]).await {
+            Frame::Error(e) if e == "ERR max number of clients reached" => {
+                tokio::time::sleep(Duration::from_millis(20)).await
+            }
+            Frame::Error(e) => panic!("unexpected PING error {:?}", e),
+            _ => return connection,
+        }
+    }
+    panic!("connection never accepted");
+}
+
+/// Connect and check the server refuses the connection, then closes it.
+async fn connect_rejected(server: &Server) {
+    let mut connection = server.connect().await;
+    let reply = timeout(Duration::from_secs(5), connection.read_frame()).await.unwrap().unwrap();
+    assert!(
+        matches!(&reply, Some(Frame::Error(e)) if e == "ERR max number of clients reached"),
+        "{:?}",
+        reply
+    );
+    let closed = timeout(Duration::from_secs(5), connection.read_frame()).await.unwrap();
+    assert!(matches!(closed, Ok(None) | Err(_)), "{:?}", closed);
+}
+
+#[tokio::test]
+async fn connections_past_maxclients_are_refused() {
+    let server = Server::start(&["--maxclients", "2"]).await;
+    let _first = connect_accepted(&server).await;
+    let second = connect_accepted(&server).await;
+
+    connect_rejected(&server).await;
+
+    // The slot of a client that leaves is given to the next one.
+    drop(second);
+    let _third = connect_accepted(&server).await;
+}
+
+#[tokio::test]
+async fn config_set_maxclients_applies_to_new_connections() {
+    let server = Server::start(&[]).await;
+    let mut admin = connect_accepted(&server).await;
+
+    assert!(call(&mut admin, &["config", "set", "maxclients", "1"]).await == "OK");
+    connect_rejected(&server).await;
+    // Connected clients stay.
+    assert!(call(&mut admin, &["set", "k", "v"]).await == "OK");
+
+    assert!(call(&mut admin, &["config", "set", "maxclients", "2"]).await == "OK");
+    let _other = connect_accepted(&server).await;
+}
+
+#[tokio::test]
+async fn idle_clients_are_closed_after_timeout() {
+    let server = Server::start(&["--timeout", "1"]).await;
+    let mut connection = connect_accepted(&server).await;
+    let idle_since = Instant::now();
+
+    let closed = timeout(Duration::from_secs(5), connection.read_frame()).await.unwrap();
+    assert!(matches!(closed, Ok(None)), "{:?}", closed);
+    assert!(idle_since.elapsed() >= Duration::from_millis(900), "closed after {:?}", idle_since.elapsed());
+}
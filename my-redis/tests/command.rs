@@ -0,0 +1,64 @@
+mod support;
+
+use my_redis::frame::Frame;
+use support::{call, Server};
+
+fn strings(frame: Frame) -> Vec<String> {
+    match frame {
+        Frame::Array(entries) => entries.iter().map(|entry| entry.to_string()).collect(),
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+fn error(frame: Frame) -> String {
+    match frame {
+        Frame::Error(message) => message,
+        frame => panic!("expected an error, got {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn getkeys() {
+    let server = Server::start(&[]).await;
+    let mut connection = server.connect().await;
+
+    let keys = call(&mut connection, &["command", "getkeys", "set", "key", "value"]).await;
+    assert_eq!(strings(keys), ["key"]);
+    let keys = call(&mut connection, &["command", "getkeys", "EXISTS", "a", "b"]).await;
+    assert_eq!(strings(keys), ["a", "b"]);
+    let keys = call(&mut connection, &["command", "getkeys", "brpop", "a", "b", "5"]).await;
+    assert_eq!(strings(keys), ["a", "b"]);
+    let keys = call(&mut connection, &["command", "getkeys", "migrate", "h", "1", "", "0", "10", "KEYS", "a", "b"]).await;
+    assert_eq!(strings(keys), ["a", "b"]);
+}
+
+#[tokio::test]
+async fn getkeys_errors() {
+    let server = Server::start(&[]).await;
+    let mut connection = server.connect().await;
+
+    let reply = call(&mut connection, &["command", "getkeys", "nope", "key"]).await;
+    assert_eq!(error(reply), "ERR Invalid command specified");
+    let reply = call(&mut connection, &["command", "getkeys", "get", "a", "b"]).await;
+    assert_eq!(error(reply), "ERR Invalid number of arguments specified for command");
+    let reply = call(&mut connection, &["command", "getkeys", "ping", "hello"]).await;
+    assert_eq!(error(reply), "ERR The command has no key arguments");
+    let reply = call(&mut connection, &["command", "getkeys", "migrate", "h", "1", "", "0", "10"]).await;
+    assert_eq!(error(reply), "ERR The command has no key arguments");
+}
+
+#[tokio::test]
+async fn count_matches_list() {
+    let server = Server::start(&[]).await;
+    let mut connection = server.connect().await;
+
+    // Subcommands are listed, but not counted.
+    let names = strings(call(&mut connection, &["command", "list"]).await);
+    assert!(names.contains(&"get".to_string()));
+    assert!(names.contains(&"config|get".to_string()));
+    let commands = names.iter().filter(|name| !name.contains('|')).count();
+    match call(&mut connection, &["command", "count"]).await {
+        Frame::Integer(count) => assert_eq!(count as usize, commands),
+        frame => panic!("unexpected COMMAND COUNT reply {:?}", frame),
+    }
+}
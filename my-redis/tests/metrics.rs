@@ -0,0 +1,42 @@
+#![cfg(feature = "metrics")]
+
+mod support;
+
+use std::time::Duration;
+use support::{call, free_port, Server};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Send an HTTP GET for `path` and return the whole response.
+async fn get(port: u16, path: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+        .await
+        .expect("timed out waiting for the response")
+        .unwrap();
+    String::from_utf8(response).unwrap()
+}
+
+#[tokio::test]
+async fn metrics_are_served_over_http() {
+    let metrics_port = free_port();
+    let metrics_addr = format!("127.0.0.1:{}", metrics_port);
+    let server = Server::start(&["--metrics-addr", &metrics_addr]).await;
+    let mut connection = server.connect().await;
+    assert!(call(&mut connection, &["set", "k", "v"]).await == "OK");
+
+    let response = get(metrics_port, "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"), "{}", response);
+    assert!(response.contains("redis_commands_processed_total{command=\"set\"} 1\n"), "{}", response);
+    assert!(response.contains("redis_db_keys{db=\"0\"} 1\n"), "{}", response);
+
+    for path in ["/", "/metricsx", "/other"] {
+        let response = get(metrics_port, path).await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}: {}", path, response);
+    }
+}
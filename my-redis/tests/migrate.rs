@@ -0,0 +1,132 @@
+mod support;
+
+use bytes::Bytes;
+use my_redis::frame::Frame;
+use support::{call, Server};
+
+fn exists(frame: &Frame) -> bool {
+    match frame {
+        Frame::Integer(n) => *n == 1,
+        frame => panic!("unexpected EXISTS reply {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn migrate_moves_the_key() {
+    let source = Server::start(&[]).await;
+    let destination = Server::start(&[]).await;
+    let mut src = source.connect().await;
+    let mut dst = destination.connect().await;
+
+    assert_eq!(call(&mut src, &["set", "greeting", "hello"]).await, "OK");
+    let port = destination.port.to_string();
+    assert_eq!(call(&mut src, &["migrate", "127.0.0.1", &port, "greeting", "0", "1000"]).await, "OK");
+
+    assert!(!exists(&call(&mut src, &["exists", "greeting"]).await));
+    assert_eq!(call(&mut dst, &["get", "greeting"]).await, "hello");
+}
+
+#[tokio::test]
+async fn migrate_copy_keeps_the_key() {
+    let source = Server::start(&[]).await;
+    let destination = Server::start(&[]).await;
+    let mut src = source.connect().await;
+    let mut dst = destination.connect().await;
+
+    call(&mut src, &["rpush", "list", "a", "b", "c"]).await;
+    let dump = bulk(call(&mut src, &["dump", "list"]).await);
+    let port = destination.port.to_string();
+    assert_eq!(call(&mut src, &["migrate", "127.0.0.1", &port, "list", "0", "1000", "COPY"]).await, "OK");
+
+    assert_eq!(bulk(call(&mut src, &["dump", "list"]).await), dump);
+    assert_eq!(bulk(call(&mut dst, &["dump", "list"]).await), dump);
+}
+
+#[tokio::test]
+async fn migrate_replace() {
+    let source = Server::start(&[]).await;
+    let destination = Server::start(&[]).await;
+    let mut src = source.connect().await;
+    let mut dst = destination.connect().await;
+
+    call(&mut src, &["set", "key", "new"]).await;
+    call(&mut dst, &["set", "key", "old"]).await;
+    let port = destination.port.to_string();
+
+    // Without REPLACE the existing key is kept on both sides.
+    match call(&mut src, &["migrate", "127.0.0.1", &port, "key", "0", "1000"]).await {
+        Frame::Error(msg) => assert!(msg.contains("BUSYKEY"), "{}", msg),
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+    assert_eq!(call(&mut src, &["get", "key"]).await, "new");
+    assert_eq!(call(&mut dst, &["get", "key"]).await, "old");
+
+    assert_eq!(call(&mut src, &["migrate", "127.0.0.1", &port, "key", "0", "1000", "REPLACE"]).await, "OK");
+    assert!(!exists(&call(&mut src, &["exists", "key"]).await));
+    assert_eq!(call(&mut dst, &["get", "key"]).await, "new");
+
+    // COPY and REPLACE together keep the local key.
+    call(&mut src, &["set", "key", "newer"]).await;
+    let reply = call(&mut src, &["migrate", "127.0.0.1", &port, "key", "0", "1000", "COPY", "REPLACE"]).await;
+    assert_eq!(reply, "OK");
+    assert_eq!(call(&mut src, &["get", "key"]).await, "newer");
+    assert_eq!(call(&mut dst, &["get", "key"]).await, "newer");
+}
+
+#[tokio::test]
+async fn migrate_keys_to_another_database() {
+    let source = Server::start(&[]).await;
+    let destination = Server::start(&[]).await;
+    let mut src = source.connect().await;
+    let mut dst = destination.connect().await;
+
+    call(&mut src, &["set", "a", "1"]).await;
+    call(&mut src, &["set", "b", "2"]).await;
+    let port = destination.port.to_string();
+    let reply = call(&mut src, &["migrate", "127.0.0.1", &port, "", "3", "1000", "KEYS", "a", "b", "missing"]).await;
+    assert_eq!(reply, "OK");
+
+    assert!(!exists(&call(&mut src, &["exists", "a"]).await));
+    assert!(!exists(&call(&mut src, &["exists", "b"]).await));
+    assert!(!exists(&call(&mut dst, &["exists", "a"]).await));
+    assert_eq!(call(&mut dst, &["select", "3"]).await, "OK");
+    assert_eq!(call(&mut dst, &["get", "a"]).await, "1");
+    assert_eq!(call(&mut dst, &["get", "b"]).await, "2");
+
+    let reply = call(&mut src, &["migrate", "127.0.0.1", &port, "", "3", "1000", "KEYS", "a"]).await;
+    assert_eq!(reply, "NOKEY");
+}
+
+#[tokio::test]
+async fn migrate_authenticates() {
+    let source = Server::start(&[]).await;
+    let destination = Server::start(&["--requirepass", "secret"]).await;
+    let mut src = source.connect().await;
+    let port = destination.port.to_string();
+
+    call(&mut src, &["set", "a", "1"]).await;
+    call(&mut src, &["set", "b", "2"]).await;
+
+    match call(&mut src, &["migrate", "127.0.0.1", &port, "a", "0", "1000"]).await {
+        Frame::Error(msg) => assert!(msg.contains("NOAUTH"), "{}", msg),
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+    assert!(exists(&call(&mut src, &["exists", "a"]).await));
+
+    let reply = call(&mut src, &["migrate", "127.0.0.1", &port, "a", "0", "1000", "AUTH", "secret"]).await;
+    assert_eq!(reply, "OK");
+    let reply = call(&mut src, &["migrate", "127.0.0.1", &port, "", "0", "1000", "AUTH2", "default", "secret", "KEYS", "b"]).await;
+    assert_eq!(reply, "OK");
+
+    let mut dst = destination.connect().await;
+    assert_eq!(call(&mut dst, &["auth", "secret"]).await, "OK");
+    assert_eq!(call(&mut dst, &["get", "a"]).await, "1");
+    assert_eq!(call(&mut dst, &["get", "b"]).await, "2");
+}
+
+fn bulk(frame: Frame) -> Bytes {
+    match frame {
+        Frame::Bulk(bytes) => bytes,
+        frame => panic!("unexpected reply {:?}", frame),
+    }
+}
@@ -0,0 +1,49 @@
+mod support;
+
+use my_redis::frame::Frame;
+use my_redis::Connection;
+use std::time::Duration;
+use support::{call, Server};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Read the next line written to `monitor`.
+async fn next_line(monitor: &mut Connection) -> String {
+    let frame = timeout(Duration::from_secs(5), monitor.read_frame())
+        .await
+        .expect("timed out waiting for a monitor line")
+        .unwrap()
+        .expect("monitor closed by the server");
+    match frame {
+        Frame::Simple(line) => line,
+        frame => panic!("unexpected monitor frame {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn monitors_see_commands_with_db_and_client_address() {
+    let server = Server::start(&[]).await;
+    let mut monitor = server.connect().await;
+    assert!(call(&mut monitor, &["monitor"]).await == "OK");
+
+    let stream = TcpStream::connect(server.addr()).await.unwrap();
+    let addr = stream.local_addr().unwrap();
+    let mut connection = Connection::new(stream);
+    assert!(call(&mut connection, &["set", "k", "v"]).await == "OK");
+    assert!(call(&mut connection, &["select", "2"]).await == "OK");
+    assert!(call(&mut connection, &["set", "k", "with \"quotes\""]).await == "OK");
+
+    let line = next_line(&mut monitor).await;
+    let suffix = format!(" [0 {}] \"set\" \"k\" \"v\"", addr);
+    assert!(line.ends_with(&suffix), "{:?} doesn't end with {:?}", line, suffix);
+    // Starts with a timestamp, in seconds with microseconds.
+    let (timestamp, _) = line.split_once(' ').unwrap();
+    let (secs, micros) = timestamp.split_once('.').unwrap();
+    assert!(secs.parse::<u64>().is_ok() && micros.len() == 6, "{:?}", line);
+
+    let line = next_line(&mut monitor).await;
+    assert!(line.ends_with(&format!(" [0 {}] \"select\" \"2\"", addr)), "{:?}", line);
+    let line = next_line(&mut monitor).await;
+    let suffix = format!(" [2 {}] \"set\" \"k\" \"with \\\"quotes\\\"\"", addr);
+    assert!(line.ends_with(&suffix), "{:?} doesn't end with {:?}", line, suffix);
+}
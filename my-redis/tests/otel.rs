@@ -0,0 +1,126 @@
+#![cfg(feature = "otel")]
+
+mod support;
+
+use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{TraceService, TraceServiceServer};
+use opentelemetry_proto::tonic::collector::trace::v1::{ExportTraceServiceRequest, ExportTraceServiceResponse};
+use opentelemetry_proto::tonic::common::v1::any_value::Value;
+use opentelemetry_proto::tonic::trace::v1::Span;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use support::{call, free_port, Server};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout};
+
+/// Accepts OTLP exports and hands the spans over to the test.
+struct Collector {
+    spans: mpsc::UnboundedSender<Span>,
+}
+
+#[tonic::async_trait]
+impl TraceService for Collector {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportTraceServiceRequest>,
+    ) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+        let spans = request
+            .into_inner()
+            .resource_spans
+            .into_iter()
+            .flat_map(|resource| resource.scope_spans)
+            .flat_map(|scope| scope.spans);
+        for span in spans {
+            let _ = self.spans.send(span);
+        }
+        Ok(tonic::Response::new(ExportTraceServiceResponse::default()))
+    }
+}
+
+/// Start a collector on a free port.
+async fn collector() -> (u16, mpsc::UnboundedReceiver<Span>) {
+    let port = free_port();
+    let (tx, rx) = mpsc::unbounded_channel();
+    let service = TraceServiceServer::new(Collector { spans: tx });
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve(([127, 0, 0, 1], port).into()),
+    );
+    while TcpStream::connect(("127.0.0.1", port)).await.is_err() {
+        sleep(Duration::from_millis(20)).await;
+    }
+    (port, rx)
+}
+
+/// The attribute `key` of `span`, formatted as a string: integers too, as
+/// they may be exported either way.
+fn attribute(span: &Span, key: &str) -> Option<String> {
+    let value = span
+        .attributes
+        .iter()
+        .find(|attribute| attribute.key == key)?
+        .value
+        .as_ref()?
+        .value
+        .as_ref()?;
+    Some(match value {
+        Value::StringValue(value) => value.clone(),
+        Value::IntValue(value) => value.to_string(),
+        value => format!("{:?}", value),
+    })
+}
+
+/// Wait for the span named `name` that `matches`.
+async fn expect_span(spans: &mut mpsc::UnboundedReceiver<Span>, name: &str, matches: impl Fn(&Span) -> bool) -> Span {
+    timeout(Duration::from_secs(10), async {
+        loop {
+            let span = spans.recv().await.expect("collector stopped");
+            if span.name == name && matches(&span) {
+                return span;
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("no `{}` span exported", name))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// `trace_id` in the X-Ray format: `1-<start time, 8 hex digits>-<24 hex
+/// digits>`.
+fn xray_trace_id(trace_id: &[u8]) -> String {
+    let hex: String = trace_id.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("1-{}-{}", &hex[..8], &hex[8..])
+}
+
+#[tokio::test]
+async fn spans_are_exported() {
+    let started = now();
+    let (port, mut spans) = collector().await;
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let server = Server::start_with_env(&[], &[("OTEL_EXPORTER_OTLP_ENDPOINT", &endpoint)]).await;
+
+    let mut connection = server.connect().await;
+    assert_eq!(call(&mut connection, &["set", "key", "value"]).await, "OK");
+    assert_eq!(call(&mut connection, &["get", "key"]).await, "value");
+    drop(connection);
+
+    let set = expect_span(&mut spans, "command", |span| attribute(span, "name").as_deref() == Some("set")).await;
+    assert_eq!(attribute(&set, "db").as_deref(), Some("0"));
+    assert_eq!(attribute(&set, "keys").as_deref(), Some("1"));
+    assert_eq!(attribute(&set, "outcome").as_deref(), Some("ok"));
+
+    // Commands are children of the span of their connection, which ends
+    // when the client disconnects.
+    let connection = expect_span(&mut spans, "connection", |span| span.span_id == set.parent_span_id).await;
+    assert_eq!(set.trace_id, connection.trace_id);
+
+    // Trace ids start with the time the trace started, as X-Ray requires.
+    let xray = xray_trace_id(&set.trace_id);
+    let parts: Vec<&str> = xray.split('-').collect();
+    assert_eq!((parts.len(), parts[1].len(), parts[2].len()), (3, 8, 24), "{}", xray);
+    let time = u64::from_str_radix(parts[1], 16).unwrap();
+    assert!(started <= time && time <= now(), "{} doesn't start at the current time", xray);
+}
@@ -0,0 +1,85 @@
+mod support;
+
+use my_redis::frame::Frame;
+use std::time::Duration;
+use support::{call, Server};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Read from `stream` until the server closes it, returning the number of
+/// bytes received.
+async fn read_until_closed(stream: &mut TcpStream) -> usize {
+    let mut received = 0;
+    let mut buf = vec![0; 64 * 1024];
+    timeout(Duration::from_secs(10), async {
+        loop {
+            match stream.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => received += n,
+            }
+        }
+    })
+    .await
+    .expect("the server didn't close the connection");
+    received
+}
+
+async fn disconnections(server: &Server) -> u64 {
+    let mut connection = server.connect().await;
+    let info = match call(&mut connection, &["info", "stats"]).await {
+        Frame::Bulk(info) | Frame::Verbatim(_, info) => String::from_utf8(info.to_vec()).unwrap(),
+        frame => panic!("unexpected INFO reply {:?}", frame),
+    };
+    info.lines()
+        .find_map(|line| line.strip_prefix("client_output_buffer_limit_disconnections:"))
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn slow_consumers_are_disconnected() {
+    let server = Server::start(&[]).await;
+    let mut connection = server.connect().await;
+    assert_eq!(call(&mut connection, &["config", "set", "client-output-buffer-limit", "normal 1mb 0 0"]).await, "OK");
+    assert_eq!(call(&mut connection, &["set", "big", &"x".repeat(100 * 1024)]).await, "OK");
+
+    // 10MB of replies, to a client that doesn't read them.
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    let get = b"*2\r\n$3\r\nGET\r\n$3\r\nbig\r\n";
+    stream.write_all(&get.repeat(100)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let received = read_until_closed(&mut stream).await;
+    assert!(received < 100 * 100 * 1024, "every reply was received");
+    assert_eq!(disconnections(&server).await, 1);
+
+    // Clients keeping up are left alone.
+    for _ in 0..20 {
+        assert!(matches!(call(&mut connection, &["get", "big"]).await, Frame::Bulk(_)));
+    }
+    assert_eq!(disconnections(&server).await, 1);
+}
+
+#[tokio::test]
+async fn slow_monitors_are_disconnected() {
+    let server = Server::start(&[]).await;
+    let mut connection = server.connect().await;
+    assert_eq!(call(&mut connection, &["config", "set", "client-output-buffer-limit", "pubsub 64kb 0 0"]).await, "OK");
+
+    let mut monitor = TcpStream::connect(server.addr()).await.unwrap();
+    monitor.write_all(b"*1\r\n$7\r\nMONITOR\r\n").await.unwrap();
+    let mut ok = [0; 5];
+    monitor.read_exact(&mut ok).await.unwrap();
+    assert_eq!(&ok, b"+OK\r\n");
+
+    // 10MB of commands are fed to the monitor, which doesn't read them.
+    let value = "x".repeat(10 * 1024);
+    for _ in 0..1000 {
+        call(&mut connection, &["set", "key", &value]).await;
+    }
+    read_until_closed(&mut monitor).await;
+    assert_eq!(disconnections(&server).await, 1);
+}
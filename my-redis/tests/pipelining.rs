@@ -0,0 +1,64 @@
+mod support;
+
+use std::time::Duration;
+use support::Server;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Read from `stream` until `expected` has been received in full.
+async fn expect(stream: &mut TcpStream, expected: &[u8]) {
+    let mut received = vec![0; expected.len()];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut received))
+        .await
+        .expect("timed out waiting for the replies")
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&received), String::from_utf8_lossy(expected));
+}
+
+#[tokio::test]
+async fn pipelined_replies_come_back_in_order() {
+    let server = Server::start(&[]).await;
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+
+    let mut commands = Vec::new();
+    let mut replies = Vec::new();
+    for i in 0..500 {
+        let value = i.to_string();
+        commands.extend_from_slice(format!("*3\r\n$3\r\nSET\r\n$1\r\nk\r\n${}\r\n{}\r\n", value.len(), value).as_bytes());
+        commands.extend_from_slice(b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n");
+        replies.extend_from_slice(format!("+OK\r\n${}\r\n{}\r\n", value.len(), value).as_bytes());
+    }
+    stream.write_all(&commands).await.unwrap();
+    expect(&mut stream, &replies).await;
+}
+
+#[tokio::test]
+async fn commands_split_across_reads() {
+    let server = Server::start(&[]).await;
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+
+    // The second command is only complete once the rest of it arrives.
+    stream.write_all(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n*2\r\n$3\r\nGE").await.unwrap();
+    expect(&mut stream, b"+OK\r\n").await;
+    stream.write_all(b"T\r\n$1\r\nk\r\n").await.unwrap();
+    expect(&mut stream, b"$1\r\nv\r\n").await;
+}
+
+#[tokio::test]
+async fn replies_are_flushed_before_blocking() {
+    let server = Server::start(&[]).await;
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    let mut other = TcpStream::connect(server.addr()).await.unwrap();
+
+    // The reply to SET doesn't wait for BLPOP to return.
+    stream
+        .write_all(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n*3\r\n$5\r\nBLPOP\r\n$4\r\nlist\r\n$1\r\n0\r\n")
+        .await
+        .unwrap();
+    expect(&mut stream, b"+OK\r\n").await;
+
+    other.write_all(b"*3\r\n$5\r\nRPUSH\r\n$4\r\nlist\r\n$1\r\na\r\n").await.unwrap();
+    expect(&mut other, b":1\r\n").await;
+    expect(&mut stream, b"*2\r\n$4\r\nlist\r\n$1\r\na\r\n").await;
+}
@@ -0,0 +1,93 @@
+mod support;
+
+use my_redis::frame::Frame;
+use std::time::Duration;
+use support::{call, Server};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Long enough for a clean shutdown, and much shorter than the
+/// `shutdown-timeout` the tests set, so waiting it out fails the test.
+const EXIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Wait until `count` clients are blocked, as INFO reports them.
+async fn wait_for_blocked(server: &Server, count: usize) {
+    let mut connection = server.connect().await;
+    let expected = format!("blocked_clients:{}\r\n", count);
+    for _ in 0..500 {
+        match call(&mut connection, &["info", "clients"]).await {
+            Frame::Bulk(info) if String::from_utf8_lossy(&info).contains(&expected) => return,
+            Frame::Bulk(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            frame => panic!("unexpected INFO reply {:?}", frame),
+        }
+    }
+    panic!("{} clients never got blocked", count);
+}
+
+#[tokio::test]
+async fn sigterm_stops_the_server() {
+    let mut server = Server::start(&[]).await;
+    server.signal("TERM");
+    assert!(server.wait_for_exit(EXIT_TIMEOUT).await.success());
+}
+
+#[tokio::test]
+async fn sigint_stops_the_server() {
+    let mut server = Server::start(&[]).await;
+    server.signal("INT");
+    assert!(server.wait_for_exit(EXIT_TIMEOUT).await.success());
+}
+
+#[tokio::test]
+async fn shutdown_save_writes_a_snapshot() {
+    let mut server = Server::start(&[]).await;
+    let mut connection = server.connect().await;
+    assert!(call(&mut connection, &["set", "k", "v"]).await == "OK");
+
+    connection.write_frame(&Frame::Array(vec![Frame::Bulk("shutdown".into()), Frame::Bulk("save".into())])).await.unwrap();
+    assert!(server.wait_for_exit(EXIT_TIMEOUT).await.success());
+    let snapshot = std::fs::metadata(server.dir().join("dump.snapshot")).unwrap();
+    assert!(snapshot.len() > 0);
+}
+
+#[tokio::test]
+async fn shutdown_nosave_skips_the_snapshot() {
+    let mut server = Server::start(&[]).await;
+    let mut connection = server.connect().await;
+    assert!(call(&mut connection, &["set", "k", "v"]).await == "OK");
+
+    connection.write_frame(&Frame::Array(vec![Frame::Bulk("shutdown".into()), Frame::Bulk("nosave".into())])).await.unwrap();
+    assert!(server.wait_for_exit(EXIT_TIMEOUT).await.success());
+    assert!(!server.dir().join("dump.snapshot").exists());
+}
+
+#[tokio::test]
+async fn blocked_clients_are_released_and_queued_commands_drained() {
+    let mut server = Server::start(&[]).await;
+    let mut other = server.connect().await;
+    assert!(call(&mut other, &["config", "set", "shutdown-timeout", "60"]).await == "OK");
+
+    // A single batch: the first BLPOP blocks, the rest of the batch is only
+    // forwarded once shutdown has started.
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    let mut commands = b"*3\r\n$5\r\nBLPOP\r\n$1\r\nq\r\n$1\r\n0\r\n".to_vec();
+    let mut replies = b"-UNBLOCKED server is shutting down\r\n".to_vec();
+    for i in 1..=100 {
+        commands.extend_from_slice(b"*3\r\n$5\r\nRPUSH\r\n$1\r\nl\r\n$1\r\nv\r\n");
+        replies.extend_from_slice(format!(":{}\r\n", i).as_bytes());
+    }
+    commands.extend_from_slice(b"*3\r\n$5\r\nBLPOP\r\n$1\r\nq\r\n$1\r\n0\r\n");
+    replies.extend_from_slice(b"-UNBLOCKED server is shutting down\r\n");
+    stream.write_all(&commands).await.unwrap();
+    wait_for_blocked(&server, 1).await;
+
+    other.write_frame(&Frame::Array(vec![Frame::Bulk("shutdown".into())])).await.unwrap();
+    let mut received = Vec::new();
+    timeout(EXIT_TIMEOUT, stream.read_to_end(&mut received))
+        .await
+        .expect("timed out waiting for the replies")
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&received), String::from_utf8_lossy(&replies));
+    assert!(server.wait_for_exit(EXIT_TIMEOUT).await.success());
+}
@@ -0,0 +1,53 @@
+mod support;
+
+use my_redis::frame::Frame;
+use support::{call, Server};
+
+/// The commands in the slow log, most recent first.
+async fn logged(server: &Server) -> Vec<Vec<String>> {
+    let mut connection = server.connect().await;
+    match call(&mut connection, &["slowlog", "get", "-1"]).await {
+        Frame::Array(entries) => entries
+            .into_iter()
+            .map(|entry| match entry {
+                Frame::Array(fields) => match &fields[3] {
+                    Frame::Array(args) => args.iter().map(|arg| arg.to_string()).collect(),
+                    frame => panic!("unexpected arguments {:?}", frame),
+                },
+                entry => panic!("unexpected entry {:?}", entry),
+            })
+            .collect(),
+        frame => panic!("unexpected SLOWLOG GET reply {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn commands_run_by_the_connection_are_logged() {
+    let server = Server::start(&[]).await;
+    let mut connection = server.connect().await;
+    assert_eq!(call(&mut connection, &["config", "set", "slowlog-log-slower-than", "0"]).await, "OK");
+    assert_eq!(call(&mut connection, &["slowlog", "reset"]).await, "OK");
+
+    assert_eq!(call(&mut connection, &["set", "key", "value"]).await, "OK");
+    assert_eq!(call(&mut connection, &["save"]).await, "OK");
+    assert!(matches!(call(&mut connection, &["config", "get", "maxclients"]).await, Frame::Array(_) | Frame::Map(_)));
+
+    let logged = logged(&server).await;
+    assert!(logged.contains(&vec!["set".to_string(), "key".to_string(), "value".to_string()]), "{:?}", logged);
+    assert!(logged.contains(&vec!["save".to_string()]), "{:?}", logged);
+    assert!(logged.contains(&vec!["config".to_string(), "get".to_string(), "maxclients".to_string()]), "{:?}", logged);
+}
+
+#[tokio::test]
+async fn passwords_are_not_logged() {
+    let server = Server::start(&[]).await;
+    let mut connection = server.connect().await;
+    assert_eq!(call(&mut connection, &["config", "set", "slowlog-log-slower-than", "0"]).await, "OK");
+    assert_eq!(call(&mut connection, &["slowlog", "reset"]).await, "OK");
+
+    assert!(matches!(call(&mut connection, &["auth", "nobody", "secret"]).await, Frame::Error(_)));
+    assert_eq!(call(&mut connection, &["acl", "setuser", "alice", ">secret"]).await, "OK");
+
+    let logged = logged(&server).await;
+    assert!(logged.iter().flatten().all(|arg| !arg.contains("secret")), "{:?}", logged);
+}
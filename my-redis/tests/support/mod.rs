@@ -0,0 +1,114 @@
+//! Runs `my-redis-server` processes for the integration tests.
+
+#![allow(dead_code)]
+
+use bytes::Bytes;
+use my_redis::frame::Frame;
+use my_redis::Connection;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::{sleep, Instant};
+
+/// A server listening on a free local port, killed when dropped.
+pub struct Server {
+    pub port: u16,
+    child: Child,
+    dir: PathBuf,
+}
+
+impl Server {
+    /// Start a server with the extra command line `args`, and wait until it
+    /// accepts connections.
+    pub async fn start(args: &[&str]) -> Server {
+        Server::start_with_env(args, &[]).await
+    }
+
+    /// Start a server as `start` does, with the environment variables `envs`
+    /// set.
+    pub async fn start_with_env(args: &[&str], envs: &[(&str, &str)]) -> Server {
+        let port = free_port();
+        let dir = std::env::temp_dir().join(format!("my-redis-test-{}-{}", std::process::id(), port));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_my-redis-server"))
+            .arg("--port")
+            .arg(port.to_string())
+            .arg("--dir")
+            .arg(&dir)
+            .args(["--save", "0"])
+            .args(args)
+            .envs(envs.iter().copied())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let server = Server { port, child, dir };
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while TcpStream::connect(server.addr()).await.is_err() {
+            assert!(Instant::now() < deadline, "server on port {} didn't start", port);
+            sleep(Duration::from_millis(20)).await;
+        }
+        server
+    }
+
+    pub fn addr(&self) -> String {
+        format!("127.0.0.1:{}", self.port)
+    }
+
+    pub async fn connect(&self) -> Connection {
+        Connection::new(TcpStream::connect(self.addr()).await.unwrap())
+    }
+
+    /// The `--dir` of the server, where snapshots are written
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Send the signal `name`, e.g. `TERM`, to the server.
+    pub fn signal(&self, name: &str) {
+        let status = Command::new("kill")
+            .args(["-s", name, &self.child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success(), "can't send SIG{} to the server", name);
+    }
+
+    /// Wait for the server to exit on its own, for at most `timeout`.
+    pub async fn wait_for_exit(&mut self, timeout: Duration) -> ExitStatus {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "server on port {} didn't exit", self.port);
+            sleep(Duration::from_millis(20)).await;
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A port nothing listens on, at least for now.
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// Send the command `args` and return the reply.
+pub async fn call<S>(connection: &mut Connection<S>, args: &[&str]) -> Frame
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let frame = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::from(arg.to_string()))).collect());
+    connection.write_frame(&frame).await.unwrap();
+    connection.read_frame().await.unwrap().expect("connection closed by the server")
+}
@@ -0,0 +1,210 @@
+#![cfg(feature = "tls")]
+
+mod support;
+
+use my_redis::Connection;
+use rcgen::{BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use support::{call, free_port, Server};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+/// A certificate authority, and a certificate it signed.
+struct Pki {
+    ca: String,
+    cert: String,
+    key: String,
+}
+
+impl Pki {
+    /// A new authority, signing a certificate for `localhost` usable by both
+    /// servers and clients.
+    fn new() -> Pki {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = params.self_signed(&ca_key).unwrap();
+
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth, ExtendedKeyUsagePurpose::ClientAuth];
+        let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+
+        Pki {
+            ca: ca.pem(),
+            cert: cert.pem(),
+            key: key.serialize_pem(),
+        }
+    }
+
+    /// Write the PEM files to `dir`, named after `name`.
+    fn write(&self, dir: &Path, name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let files = (
+            dir.join(format!("{}-ca.pem", name)),
+            dir.join(format!("{}-cert.pem", name)),
+            dir.join(format!("{}-key.pem", name)),
+        );
+        std::fs::write(&files.0, &self.ca).unwrap();
+        std::fs::write(&files.1, &self.cert).unwrap();
+        std::fs::write(&files.2, &self.key).unwrap();
+        files
+    }
+}
+
+fn temp_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("my-redis-tls-{}-{}", test, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Connect to `port` trusting `ca`, presenting `identity` if given.
+async fn connect(port: u16, ca: &Pki, identity: Option<&Pki>) -> std::io::Result<Connection<TlsStream<TcpStream>>> {
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_slice(ca.ca.as_bytes()).unwrap()).unwrap();
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+    let config = match identity {
+        Some(pki) => {
+            let cert = CertificateDer::from_pem_slice(pki.cert.as_bytes()).unwrap();
+            let key = PrivateKeyDer::from_pem_slice(pki.key.as_bytes()).unwrap();
+            builder.with_client_auth_cert(vec![cert], key).unwrap()
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let socket = TcpStream::connect(("127.0.0.1", port)).await?;
+    let stream = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("localhost").unwrap(), socket)
+        .await?;
+    Ok(Connection::new(stream))
+}
+
+/// Whether a command gets a reply on `connection`. With TLS 1.3 the server
+/// checks the client certificate after the client finished its handshake,
+/// so a rejection shows on the first read.
+async fn answers(connection: &mut Connection<TlsStream<TcpStream>>) -> bool {
+    let ping = my_redis::Frame::Array(vec![my_redis::Frame::Bulk("ping".into())]);
+    connection.write_frame(&ping).await.is_ok() && matches!(connection.read_frame().await, Ok(Some(_)))
+}
+
+#[tokio::test]
+async fn round_trip() {
+    let dir = temp_dir("round-trip");
+    let pki = Pki::new();
+    let (_, cert, key) = pki.write(&dir, "server");
+    let tls_port = free_port();
+    let server = Server::start(&[
+        "--tls-port",
+        &tls_port.to_string(),
+        "--tls-cert-file",
+        cert.to_str().unwrap(),
+        "--tls-key-file",
+        key.to_str().unwrap(),
+        "--tls-auth-clients",
+        "no",
+    ])
+    .await;
+
+    let mut connection = connect(tls_port, &pki, None).await.unwrap();
+    assert_eq!(call(&mut connection, &["set", "key", "value"]).await, "OK");
+    assert_eq!(call(&mut connection, &["get", "key"]).await, "value");
+
+    // The plain port serves the same data.
+    let mut plain = server.connect().await;
+    assert_eq!(call(&mut plain, &["get", "key"]).await, "value");
+
+    // A client that doesn't trust the server's authority gives up.
+    assert!(connect(tls_port, &Pki::new(), None).await.is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn client_certificates_are_required() {
+    let dir = temp_dir("mtls");
+    let pki = Pki::new();
+    let (ca, cert, key) = pki.write(&dir, "server");
+    let tls_port = free_port();
+    let _server = Server::start(&[
+        "--tls-port",
+        &tls_port.to_string(),
+        "--tls-cert-file",
+        cert.to_str().unwrap(),
+        "--tls-key-file",
+        key.to_str().unwrap(),
+        "--tls-ca-cert-file",
+        ca.to_str().unwrap(),
+        "--tls-auth-clients",
+        "yes",
+    ])
+    .await;
+
+    if let Ok(mut connection) = connect(tls_port, &pki, None).await {
+        assert!(!answers(&mut connection).await, "served a client without a certificate");
+    }
+
+    // A certificate from another authority is refused as well.
+    if let Ok(mut connection) = connect(tls_port, &pki, Some(&Pki::new())).await {
+        assert!(!answers(&mut connection).await, "served a client with an unknown certificate");
+    }
+
+    let mut connection = connect(tls_port, &pki, Some(&pki)).await.unwrap();
+    assert_eq!(call(&mut connection, &["set", "key", "value"]).await, "OK");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn reload_picks_up_a_new_certificate() {
+    let dir = temp_dir("reload");
+    let old = Pki::new();
+    let (_, cert, key) = old.write(&dir, "server");
+    let tls_port = free_port();
+    let server = Server::start(&[
+        "--tls-port",
+        &tls_port.to_string(),
+        "--tls-cert-file",
+        cert.to_str().unwrap(),
+        "--tls-key-file",
+        key.to_str().unwrap(),
+        "--tls-auth-clients",
+        "no",
+    ])
+    .await;
+
+    let mut established = connect(tls_port, &old, None).await.unwrap();
+    assert_eq!(call(&mut established, &["set", "key", "value"]).await, "OK");
+
+    // Replace the files, then have the server read them again.
+    let new = Pki::new();
+    new.write(&dir, "server");
+    let mut plain = server.connect().await;
+    assert_eq!(call(&mut plain, &["config", "set", "tls-cert-file", cert.to_str().unwrap()]).await, "OK");
+
+    let mut connection = connect(tls_port, &new, None).await.unwrap();
+    assert_eq!(call(&mut connection, &["get", "key"]).await, "value");
+    assert!(connect(tls_port, &old, None).await.is_err());
+
+    // Connections established before the reload keep working.
+    assert_eq!(call(&mut established, &["get", "key"]).await, "value");
+
+    // Files that can't be loaded keep the current certificate in use.
+    std::fs::write(&cert, "not a certificate").unwrap();
+    assert!(matches!(
+        call(&mut plain, &["config", "set", "tls-cert-file", cert.to_str().unwrap()]).await,
+        my_redis::Frame::Error(_)
+    ));
+    assert!(connect(tls_port, &new, None).await.is_ok());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
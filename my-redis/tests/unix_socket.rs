@@ -0,0 +1,51 @@
+#![cfg(unix)]
+
+mod support;
+
+use my_redis::clients::Client;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::PathBuf;
+use support::Server;
+
+fn temp_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("my-redis-unix-{}-{}", test, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn set_and_get_over_the_unix_socket() {
+    let dir = temp_dir("round-trip");
+    let path = dir.join("s.sock");
+    let _server = Server::start(&["--unixsocket", path.to_str().unwrap(), "--unixsocketperm", "700"]).await;
+
+    let metadata = std::fs::metadata(&path).unwrap();
+    assert!(metadata.file_type().is_socket());
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o700);
+
+    let mut client = Client::connect_unix(&path).await.unwrap();
+    client.set("k", "v".into()).await.unwrap();
+    assert_eq!(client.get("k").await.unwrap().as_deref(), Some(&b"v"[..]));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn a_stale_socket_is_replaced_on_restart() {
+    let dir = temp_dir("stale");
+    let path = dir.join("s.sock");
+    let args = ["--unixsocket", path.to_str().unwrap()];
+
+    // A killed server leaves its socket behind.
+    let server = Server::start(&args).await;
+    drop(server);
+    assert!(std::fs::metadata(&path).unwrap().file_type().is_socket());
+    assert!(Client::connect_unix(&path).await.is_err());
+
+    let _server = Server::start(&args).await;
+    let mut client = Client::connect_unix(&path).await.unwrap();
+    client.set("k", "v".into()).await.unwrap();
+    assert_eq!(client.get("k").await.unwrap().as_deref(), Some(&b"v"[..]));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}